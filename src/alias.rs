@@ -0,0 +1,179 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::env::is_valid_name;
+use crate::error::{Result, RnixError};
+
+// A session's aliases, kept sorted by name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Aliases {
+    map: BTreeMap<String, String>,
+}
+
+impl Aliases {
+    pub fn new() -> Aliases {
+        Aliases::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.map.get(name).map(String::as_str)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | '.' | '!' | '@'));
+        if !valid {
+            return Err(RnixError::InvalidArgument(format!("`{}': invalid alias name", name)));
+        }
+        self.map.insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.map.remove(name).is_some()
+    }
+
+    pub fn clear(&mut self) {
+        self.map.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.map.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    // Replace the first word of `line` while it names an alias. Each alias is
+    // expanded at most once, so `alias ls='ls -a'` and alias loops terminate.
+    // As in bash, a value ending in a space makes the next word eligible too.
+    pub fn expand(&self, line: &str) -> String {
+        let mut seen = BTreeSet::new();
+        let mut done = String::new();
+        let mut rest = line.to_string();
+        loop {
+            let start = rest.len() - rest.trim_start().len();
+            let end = rest[start..].find(char::is_whitespace).map_or(rest.len(), |idx| start + idx);
+            let word = &rest[start..end];
+            let value = match self.get(word) {
+                Some(value) if !seen.contains(word) => value,
+                _ => break,
+            };
+            seen.insert(word.to_string());
+            let expanded = format!("{}{}{}", &rest[..start], value, &rest[end..]);
+            if value.ends_with(' ') {
+                // Move past the expansion and look at the word after it
+                let split = start + value.len();
+                done.push_str(&expanded[..split]);
+                rest = expanded[split..].to_string();
+            } else {
+                rest = expanded;
+            }
+        }
+        done + &rest
+    }
+}
+
+// Deepest allowed nesting of function calls
+pub const MAX_FUNCTION_DEPTH: usize = 100;
+
+// A session's shell functions: each body is a list of command lines
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Functions {
+    map: BTreeMap<String, Vec<String>>,
+}
+
+impl Functions {
+    pub fn new() -> Functions {
+        Functions::default()
+    }
+
+    pub fn get(&self, name: &str) -> Option<&[String]> {
+        self.map.get(name).map(Vec::as_slice)
+    }
+
+    pub fn define(&mut self, name: &str, body: Vec<String>) {
+        self.map.insert(name.to_string(), body);
+    }
+
+    pub fn remove(&mut self, name: &str) -> bool {
+        self.map.remove(name).is_some()
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.map.keys().map(String::as_str)
+    }
+}
+
+// How a line relates to a function definition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Definition {
+    // Not a definition
+    None,
+    // `name() { cmd; cmd; }` on one line
+    Complete(String, Vec<String>),
+    // `name() {` with the body on the following lines, up to a lone `}`
+    Open(String, Vec<String>),
+}
+
+// Function to recognise `name() { ... }` and `function name { ... }`
+pub fn parse_definition(line: &str) -> Result<Definition> {
+    let line = line.trim();
+    let (name, rest) = if let Some(rest) = line.strip_prefix("function ") {
+        let rest = rest.trim_start();
+        let end = rest.find(|c: char| c.is_whitespace() || c == '(' || c == '{').unwrap_or(rest.len());
+        let after = rest[end..].trim_start();
+        (&rest[..end], after.strip_prefix("()").unwrap_or(after))
+    } else {
+        let idx = match line.find('(') {
+            Some(idx) => idx,
+            None => return Ok(Definition::None),
+        };
+        match line[idx + 1..].trim_start().strip_prefix(')') {
+            Some(after) => (line[..idx].trim_end(), after),
+            None => return Ok(Definition::None),
+        }
+    };
+    if name.is_empty() || name.contains(char::is_whitespace) {
+        return Ok(Definition::None);
+    }
+    let body = match rest.trim_start().strip_prefix('{') {
+        Some(body) => body,
+        None => return Ok(Definition::None),
+    };
+    if !is_valid_name(name) {
+        return Err(RnixError::InvalidArgument(format!("`{}': not a valid identifier", name)));
+    }
+    let body = body.trim();
+    match body.strip_suffix('}') {
+        Some(inner) => Ok(Definition::Complete(name.to_string(), split_commands(inner))),
+        None => Ok(Definition::Open(name.to_string(), split_commands(body))),
+    }
+}
+
+// Split a function body into commands at newlines and `;` outside quotes
+pub fn split_commands(body: &str) -> Vec<String> {
+    let mut commands = Vec::new();
+    let mut current = String::new();
+    let mut quote = None;
+    let mut escaped = false;
+    for c in body.chars() {
+        match (c, quote) {
+            _ if escaped => escaped = false,
+            ('\\', Some('\'')) => {}
+            ('\\', _) => escaped = true,
+            ('\'' | '"', None) => quote = Some(c),
+            (q, Some(open)) if q == open => quote = None,
+            (';' | '\n', None) => {
+                commands.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    commands.push(current);
+    commands
+        .into_iter()
+        .map(|command| command.trim().to_string())
+        .filter(|command| !command.is_empty() && !command.starts_with('#'))
+        .collect()
+}
@@ -0,0 +1,70 @@
+use bcrypt::{hash, verify};
+
+use crate::error::Result;
+
+// Function to securely hash passwords using bcrypt
+pub fn hashp(password: &str) -> Result<String> {
+    Ok(hash(password, bcrypt::DEFAULT_COST)?)
+}
+
+// Function to securely encrypt data
+pub fn edcrypt(data: &mut [u8]) {
+    let key: [u8; 9] = [7, 19, 4, 1, 3, 6, 11, 5, 2]; // Example of a more secure key
+    for (i, byte) in data.iter_mut().enumerate() {
+        *byte ^= key[i % key.len()];
+    }
+}
+
+// A single `username:hash` record from an account file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Account {
+    pub username: String,
+    pub hash: String,
+}
+
+impl Account {
+    pub fn new(username: &str, password: &str) -> Result<Account> {
+        Ok(Account {
+            username: username.to_string(),
+            hash: hashp(password)?,
+        })
+    }
+
+    // Verify password using bcrypt constant-time comparison
+    pub fn verify(&self, password: &str) -> bool {
+        verify(password, &self.hash).unwrap_or(false)
+    }
+}
+
+// Function to decode an encrypted account file into its records
+pub fn decode_accounts(contents: &[u8]) -> Vec<Account> {
+    let mut decrypted_contents = contents.to_vec();
+    edcrypt(&mut decrypted_contents);
+    let decrypted_str = String::from_utf8(decrypted_contents).unwrap_or_default();
+    decrypted_str
+        .trim()
+        .lines()
+        .filter_map(|account| {
+            let parts: Vec<&str> = account.split(':').collect();
+            if parts.len() == 2 {
+                Some(Account {
+                    username: parts[0].trim().to_string(),
+                    hash: parts[1].trim().to_string(),
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+// Function to encode account records into an encrypted account file
+pub fn encode_accounts(accounts: &[Account]) -> Vec<u8> {
+    let lines: Vec<String> = accounts
+        .iter()
+        .map(|account| format!("{}:{}", account.username, account.hash))
+        .collect();
+    let mut encrypted_bytes = lines.join("\n").into_bytes();
+    edcrypt(&mut encrypted_bytes); // Encrypt the data before writing
+    encrypted_bytes
+}
@@ -0,0 +1,1357 @@
+// The standard rnix commands.
+
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::time::SystemTime;
+
+use chrono::{Datelike, Local, TimeZone};
+use fatfs::FileAttributes;
+
+use crate::command::{Args, Builtin, Command, Context, Privilege, Registry, Spec};
+use crate::disk::{self, format_size, opendisk, walkdisk};
+use crate::ed::Ed;
+use crate::env::is_valid_name;
+use crate::error::{IoResultExt, Result, RnixError};
+use crate::external::{self, Outcome};
+use crate::find;
+use crate::fsck;
+use crate::path;
+use crate::shell::{self, save_history, Flow};
+use crate::sparse;
+use crate::system::{EntryInfo, MOUNTABLE_DISKS, ROOT_DISK};
+use crate::text;
+use crate::transfer;
+use crate::version::{get_rnix_api_version, get_rnix_version};
+use crate::visual;
+
+// Function to add every standard command to `registry`
+pub fn register(registry: &mut Registry) {
+    let one = |usage: &str| Spec::new(usage).args(1, Some(1));
+
+    registry.register(Builtin::new("listdisks", Spec::new(""), "List mounted disks", listdisks));
+    registry.register(Builtin::new(
+        "df",
+        Spec::new("[-h]").flag('h').long("human-readable", 'h'),
+        "Show the size and free space of each mounted disk",
+        df,
+    ));
+    registry.register(Builtin::new(
+        "du",
+        Spec::new("[-s] [-h] [-d depth] [path...]")
+            .args(0, None)
+            .flag('s')
+            .flag('h')
+            .option('d')
+            .long("summarize", 's')
+            .long("human-readable", 'h')
+            .long("max-depth", 'd'),
+        "Show the disk space used under each path, in 1K blocks or -h sizes",
+        du,
+    ));
+    registry.register(Builtin::new(
+        "createdisk",
+        one("<disk_name>"),
+        "Create a new disk image",
+        createdisk,
+    ));
+    registry.register(
+        Builtin::new(
+            "mount",
+            Spec::new("[-n] [-o ro|rw] <disk_name>").args(1, Some(1)).flag('n').option('o'),
+            "Mount a disk and change to it (-n stays put, -o ro mounts it read-only)",
+            mount,
+        )
+        .privilege(Privilege::Sudo),
+    );
+    registry.register(
+        Builtin::new(
+            "umount",
+            Spec::new("[-f] <disk_name>").args(1, Some(1)).flag('f'),
+            "Unmount a disk (-f even when the current directory is on it)",
+            umount,
+        )
+        .privilege(Privilege::Sudo),
+    );
+    registry.register(Builtin::new("sync", Spec::new(""), "Write every mounted disk out to its image", sync));
+    registry.register(Builtin::new(
+        "readdisk",
+        Spec::new("[--extract] <disk_path|disk_name> [path] [host_path]")
+            .args(1, Some(3))
+            .flag('x')
+            .long("extract", 'x'),
+        "List the files on a disk image, print one, or extract it to the host with --extract",
+        readdisk,
+    ));
+    registry.register(
+        Builtin::new(
+            "fsck",
+            Spec::new("[-r | --repair] [disk_name|disk_path]")
+                .args(0, Some(1))
+                .flag('r')
+                .long("repair", 'r'),
+            "Check a disk image for damage (the root disk by default), repairing it with -r",
+            fsck,
+        )
+        .privilege(Privilege::Sudo),
+    );
+    registry.register(
+        Builtin::new(
+            "compact",
+            Spec::new("[disk_name|disk_path]").args(0, Some(1)),
+            "Zero the free clusters of a disk image (the root disk by default) and give their space back to the host",
+            compact,
+        )
+        .privilege(Privilege::Sudo),
+    );
+    registry.register(Builtin::new(
+        "ls-images",
+        Spec::new("[-h]").flag('h').long("human-readable", 'h'),
+        "List the disk images on the host with their apparent and actual sizes",
+        ls_images,
+    ));
+    registry.register(Builtin::new(
+        "find",
+        Spec::new("[path...] [-name pattern] [-type f|d] [-size [+|-]N[ckMG]] [-mtime [+|-]N] [-maxdepth N] [-print] [-delete] [-exec command {} ;]")
+            .args(0, None)
+            .raw(),
+        "Search for files and directories, across mounted disks",
+        find,
+    ));
+    registry.register(Builtin::new(
+        "grep",
+        Spec::new("[-i] [-v] [-n] [-r] <pattern> [file...]")
+            .args(1, None)
+            .flag('i')
+            .flag('v')
+            .flag('n')
+            .flag('r')
+            .long("ignore-case", 'i')
+            .long("invert-match", 'v')
+            .long("line-number", 'n')
+            .long("recursive", 'r'),
+        "Print lines matching a regular expression, from files or piped input",
+        grep,
+    ));
+    registry.register(Builtin::new(
+        "sort",
+        Spec::new("[-r] [-n] [-u] [file...]")
+            .args(0, None)
+            .flag('r')
+            .flag('n')
+            .flag('u')
+            .long("reverse", 'r')
+            .long("numeric-sort", 'n')
+            .long("unique", 'u'),
+        "Sort lines of text",
+        sort,
+    ));
+    registry.register(Builtin::new(
+        "uniq",
+        Spec::new("[-c] [-d] [-u] [file]")
+            .args(0, Some(1))
+            .flag('c')
+            .flag('d')
+            .flag('u')
+            .long("count", 'c')
+            .long("repeated", 'd')
+            .long("unique", 'u'),
+        "Collapse repeated adjacent lines",
+        uniq,
+    ));
+    registry.register(Builtin::new(
+        "cut",
+        Spec::new("-f list [-d delim] | -c list [file...]")
+            .args(0, None)
+            .option('f')
+            .option('d')
+            .option('c')
+            .long("fields", 'f')
+            .long("delimiter", 'd')
+            .long("characters", 'c'),
+        "Print selected fields (tab-separated by default) or characters of each line",
+        cut,
+    ));
+    registry.register(Builtin::new(
+        "tr",
+        Spec::new("[-d] <set1> [set2]").args(1, Some(2)).flag('d').long("delete", 'd'),
+        "Translate or delete characters of piped input",
+        tr,
+    ));
+    registry.register(Builtin::new(
+        "sed",
+        Spec::new("<s/regex/replacement/[gi]> [file...]").args(1, None),
+        "Substitute text in each line",
+        sed,
+    ));
+    registry.register(Builtin::new(
+        "diff",
+        Spec::new("<file1> <file2>").args(2, Some(2)),
+        "Compare two files line by line ('-' is piped input)",
+        diff,
+    ));
+    registry.register(Builtin::new(
+        "mkdir",
+        Spec::new("<directory_name>...").args(1, None),
+        "Create new directories",
+        mkdir,
+    ));
+    registry.register(Builtin::new(
+        "touch",
+        Spec::new("[-t [[CC]YY]MMDDhhmm[.ss]] <file_name>...").args(1, None).option('t'),
+        "Create new files, or update the modification time of existing ones",
+        touch,
+    ));
+    registry.register(Builtin::new(
+        "stat",
+        Spec::new("<path>...").args(1, None),
+        "Show the FAT attributes and timestamps of files or directories",
+        stat,
+    ));
+    registry.register(Builtin::new(
+        "attrib",
+        Spec::new("[+r|-r] [+h|-h] [+s|-s] [+a|-a] <path>...").args(1, None).raw(),
+        "Show or change the read-only, hidden, system and archive attributes",
+        attrib,
+    ));
+    registry.register(Builtin::new(
+        "rm",
+        Spec::new("<file_or_directory>...").args(1, None),
+        "Remove files or directories",
+        rm,
+    ));
+    registry.register(Builtin::new(
+        "mv",
+        Spec::new("<source_path>... <destination_path>").args(2, None),
+        "Move or rename files or directories",
+        mv,
+    ));
+    registry.register(Builtin::new(
+        "cp",
+        Spec::new("<source_path>... <destination_path>").args(2, None),
+        "Copy files",
+        cp,
+    ));
+    registry.register(Builtin::new(
+        "import",
+        Spec::new("<host_path> <image_path>").args(2, Some(2)),
+        "Copy a file or directory from the host into the image",
+        import,
+    ));
+    registry.register(Builtin::new(
+        "export-host",
+        Spec::new("<image_path> <host_path>").args(2, Some(2)),
+        "Copy a file or directory from the image out to the host (`export` sets variables)",
+        export_host,
+    ));
+    registry.register(Builtin::new(
+        "ls",
+        Spec::new("[-l] [directory...]").args(0, None).flag('l'),
+        "List contents of a directory (-l: with attributes, sizes and times)",
+        ls,
+    ));
+    registry.register(Builtin::new(
+        "cd",
+        Spec::new("[directory | -]").args(0, Some(1)),
+        "Change the current directory",
+        cd,
+    ));
+    registry.register(Builtin::new(
+        "export",
+        Spec::new("[NAME[=VALUE]...]").args(0, None).raw(),
+        "Set session variables, or list them",
+        export,
+    ));
+    registry.register(Builtin::new(
+        "unset",
+        Spec::new("[-f] <NAME>...").args(1, None).flag('f'),
+        "Remove session variables (-f: shell functions)",
+        unset,
+    ));
+    registry.register(Builtin::new("env", Spec::new(""), "List session variables", env));
+    registry.register(Builtin::new(
+        "alias",
+        Spec::new("[NAME[=VALUE]...]").args(0, None).raw(),
+        "Define aliases, or list them",
+        alias,
+    ));
+    registry.register(Builtin::new(
+        "unalias",
+        Spec::new("[-a] <NAME>...").args(0, None).flag('a'),
+        "Remove aliases (-a: all of them)",
+        unalias,
+    ));
+    registry.register(Builtin::new("echo", Spec::new("[-n] [args...]").args(0, None).raw(), "Print arguments", echo));
+    registry.register(Builtin::new("pwd", Spec::new(""), "Print the current directory", pwd));
+    registry.register(Builtin::new(
+        "edit",
+        Spec::new("[-v | --external] <file_name>")
+            .args(1, Some(1))
+            .flag('v')
+            .flag('e')
+            .long("external", 'e'),
+        "Edit a file with the line editor (h lists its commands), full-screen with -v, or in the host's $EDITOR",
+        edit,
+    ));
+    registry.register(Builtin::new("clear", Spec::new(""), "Clear the terminal", clear).alias("cls"));
+    registry.register(Builtin::new("whoami", Spec::new(""), "Display current user", whoami));
+    registry.register(Builtin::new(
+        "history",
+        Spec::new("[-c | -w | N]").args(0, Some(1)).flag('c').flag('w'),
+        "Show or clear the command history",
+        history,
+    ));
+    registry.register(Builtin::new(
+        "run",
+        Spec::new("<executable_name> [args...]").args(1, None).raw(),
+        "Run a program on the host",
+        run,
+    ));
+    registry.register(Builtin::new(
+        "sudo",
+        Spec::new("<command> [args...]").args(1, None).raw(),
+        "Run a command with elevated privileges",
+        sudo,
+    ));
+    registry.register(
+        Builtin::new(
+            "resetroot",
+            Spec::new(""),
+            "Remove all accounts so setup runs again",
+            resetroot,
+        )
+        .privilege(Privilege::Root),
+    );
+    registry.register(
+        Builtin::new("source", one("<file>"), "Run the commands in a file", source).alias("."),
+    );
+    registry.register(Builtin::new("version", Spec::new(""), "Show the rnix and API versions", version));
+    registry.register(Builtin::new(
+        "help",
+        Spec::new("[command]").args(0, Some(1)),
+        "List commands or describe one",
+        help,
+    ));
+    registry.register(
+        Builtin::new(
+            "exit",
+            Spec::new("[status]").args(0, Some(1)).raw(),
+            "Exit the program",
+            exit,
+        )
+        .alias("logout"),
+    );
+}
+
+fn listdisks(ctx: &mut Context<'_>, _args: &Args<'_>) -> Result<Flow> {
+    writeln!(ctx.console, "Disks:")?;
+    writeln!(ctx.console, "  /dev/disk0 - Root Disk")?;
+    let mounted = ctx.system.mounted_disks();
+    if mounted.is_empty() {
+        writeln!(ctx.console, "  No disks currently mounted.")?;
+    } else {
+        for disk in mounted {
+            let mode = if ctx.system.is_read_only(&disk) { " (read-only)" } else { "" };
+            writeln!(ctx.console, "  {} - /dev/{}{}", disk, disk, mode)?;
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+fn df(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let human = args.has('h');
+    writeln!(
+        ctx.console,
+        "{:<8} {:<6} {:>10} {:>10} {:>10} {:>4} Mounted on",
+        "Disk",
+        "Type",
+        if human { "Size" } else { "1K-blocks" },
+        "Used",
+        "Available",
+        "Use%"
+    )?;
+    for disk in ctx.system.disk_usage()? {
+        writeln!(
+            ctx.console,
+            "{:<8} {:<6} {:>10} {:>10} {:>10} {:>3}% {}",
+            disk.disk,
+            disk.fat_type,
+            format_size(disk.total, human),
+            format_size(disk.used(), human),
+            format_size(disk.free, human),
+            disk.use_percent(),
+            disk.mount_point
+        )?;
+    }
+    Ok(Flow::Continue)
+}
+
+fn du(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let human = args.has('h');
+    let max_depth = match (args.has('s'), args.value('d')) {
+        (true, Some(_)) => return Err(args.usage_error()),
+        (true, None) => Some(0),
+        (false, Some(depth)) => Some(
+            depth
+                .parse::<usize>()
+                .map_err(|_| RnixError::InvalidArgument(format!("invalid depth '{}'", depth)))?,
+        ),
+        (false, None) => None,
+    };
+    let paths = if args.is_empty() { &["."][..] } else { args.positional() };
+    each(ctx, "du", paths, |ctx, p| {
+        let mut lines = Vec::new();
+        ctx.system.disk_used(ctx.session, p, &mut |shown, depth, used| {
+            if max_depth.is_none_or(|max| depth <= max) {
+                lines.push(format!("{}\t{}", format_size(used, human), shown));
+            }
+            Ok(())
+        })?;
+        for line in lines {
+            writeln!(ctx.console, "{}", line)?;
+        }
+        Ok(())
+    })
+}
+
+fn createdisk(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let disk_name = args.required(0)?;
+    ctx.system.create_disk(disk_name)?;
+    writeln!(ctx.console, "Disk created: {}", disk_name)?;
+    Ok(Flow::Continue)
+}
+
+fn mount(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let disk_name = args.required(0)?;
+    let mount_point = match args.value('o') {
+        None | Some("rw") => ctx.system.mount(disk_name)?,
+        Some("ro") => ctx.system.mount_read_only(disk_name)?,
+        Some(other) => return Err(RnixError::InvalidArgument(format!("unknown mount option '{}'", other))),
+    };
+    match ctx.system.is_read_only(disk_name) {
+        true => writeln!(ctx.console, "Disk {} mounted read-only.", disk_name)?,
+        false => writeln!(ctx.console, "Disk {} mounted.", disk_name)?,
+    }
+    if !args.has('n') {
+        ctx.system.cd(ctx.session, &mount_point)?;
+    }
+    Ok(Flow::Continue)
+}
+
+fn umount(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let disk_name = args.required(0)?;
+    // Commands don't keep files open between them, so only the current
+    // directory can hold a disk busy
+    let cwd = ctx.session.cwd().to_string();
+    let busy = ctx.system.mounted_disks().contains(&disk_name.to_string()) && ctx.system.disk_of(&cwd) == disk_name;
+    if busy && !args.has('f') {
+        return Err(RnixError::InvalidArgument(format!(
+            "{}: target is busy; the current directory {} is on it (-f unmounts anyway)",
+            disk_name, cwd
+        )));
+    }
+    ctx.system.unmount(disk_name)?;
+    writeln!(ctx.console, "Disk {} unmounted.", disk_name)?;
+    if busy {
+        let home = ctx.session.home();
+        if ctx.system.cd(ctx.session, &home).is_err() {
+            ctx.system.cd(ctx.session, "/")?;
+        }
+        writeln!(ctx.console, "Changed directory to '{}'.", ctx.session.cwd())?;
+    }
+    Ok(Flow::Continue)
+}
+
+fn sync(ctx: &mut Context<'_>, _args: &Args<'_>) -> Result<Flow> {
+    ctx.system.sync()?;
+    Ok(Flow::Continue)
+}
+
+fn readdisk(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let disk_path = args.required(0)?;
+    let extract = args.has('x');
+    if (extract && args.len() < 2) || (!extract && args.len() > 2) {
+        return Err(args.usage_error());
+    }
+    let fs = opendisk(image_of(ctx, disk_path))?;
+    let inner = path::resolve("/", args.get(1).unwrap_or("/"));
+
+    if extract {
+        let host = args.get(2).unwrap_or(".");
+        let done = transfer::extract(&fs, &inner, Path::new(host))?;
+        writeln!(ctx.console, "Extracted {} to '{}'.", done, host)?;
+        return Ok(Flow::Continue);
+    }
+    let root = fs.root_dir();
+    if inner != "/" && root.open_dir(&inner[1..]).is_err() {
+        let mut contents = Vec::new();
+        root.open_file(&inner[1..]).at(&inner)?.read_to_end(&mut contents)?;
+        write!(ctx.console, "{}", String::from_utf8_lossy(&contents))?;
+        return Ok(Flow::Continue);
+    }
+
+    writeln!(ctx.console, "Contents of disk image '{}':", disk_path)?;
+    walkdisk(&fs, &inner, &mut |entry| {
+        let size = if entry.is_dir { "-".to_string() } else { entry.len.to_string() };
+        let fatfs::DateTime { date, time } = entry.modified;
+        writeln!(
+            ctx.console,
+            "{} {:>10} {:04}-{:02}-{:02} {:02}:{:02}  {}{}{}",
+            entry.flags(),
+            size,
+            date.year,
+            date.month,
+            date.day,
+            time.hour,
+            time.min,
+            "  ".repeat(entry.depth),
+            entry.name(),
+            if entry.is_dir { "/" } else { "" }
+        )?;
+        Ok(())
+    })?;
+    Ok(Flow::Continue)
+}
+
+fn fsck(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let disk = args.get(0).unwrap_or(ROOT_DISK);
+    let image = image_of(ctx, disk);
+    let mounted = disk_of_image(ctx, &image).is_some();
+    if mounted && args.has('r') {
+        let same = |name: &str| fs::canonicalize(ctx.system.disk_path(name)).ok() == fs::canonicalize(&image).ok();
+        let hint = if same(ROOT_DISK) {
+            "run `rnix fsck --repair` from the host while rnix is not running"
+        } else {
+            "unmount it first"
+        };
+        return Err(RnixError::InvalidArgument(format!("{}: cannot repair a disk in use; {}", disk, hint)));
+    }
+    let options = fsck::Options { repair: args.has('r'), mounted };
+    let report = fsck::check(&image, options)?;
+    for problem in &report.problems {
+        writeln!(ctx.console, "{}", problem)?;
+    }
+    writeln!(ctx.console, "{}: {}", disk, report)?;
+    if report.is_clean() {
+        Ok(Flow::Continue)
+    } else {
+        Err(RnixError::Reported(report.status()))
+    }
+}
+
+fn compact(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let disk = args.get(0).unwrap_or(ROOT_DISK);
+    let image = image_of(ctx, disk);
+    // A host path may name the image of a disk mounted read-only too
+    if disk_of_image(ctx, &image).is_some_and(|mounted| ctx.system.is_read_only(&mounted)) {
+        return Err(RnixError::ReadOnly(disk.to_string()));
+    }
+    let done = sparse::compact(&image)?;
+    writeln!(ctx.console, "{}: {}", disk, done)?;
+    Ok(Flow::Continue)
+}
+
+fn ls_images(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    for line in sparse::image_table(ctx.system.image_dir(), args.has('h'))? {
+        writeln!(ctx.console, "{}", line)?;
+    }
+    Ok(Flow::Continue)
+}
+
+// The root or mounted disk that `image` backs, if any
+fn disk_of_image(ctx: &Context<'_>, image: &Path) -> Option<String> {
+    let image = fs::canonicalize(image).ok()?;
+    std::iter::once(ROOT_DISK.to_string())
+        .chain(ctx.system.mounted_disks())
+        .find(|disk| fs::canonicalize(ctx.system.disk_path(disk)).ok().as_ref() == Some(&image))
+}
+
+// Host path for a `readdisk` argument: a known disk name, or else a path on the host
+fn image_of(ctx: &Context<'_>, disk: &str) -> PathBuf {
+    let known = std::iter::once(ROOT_DISK)
+        .chain(MOUNTABLE_DISKS)
+        .any(|name| name.eq_ignore_ascii_case(disk));
+    if known && !Path::new(disk).exists() {
+        ctx.system.disk_path(disk)
+    } else {
+        PathBuf::from(disk)
+    }
+}
+
+fn mkdir(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    each(ctx, "mkdir", args.positional(), |ctx, dir_name| {
+        ctx.system.mkdir(ctx.session, dir_name)?;
+        writeln!(ctx.console, "Directory '{}' created.", dir_name)?;
+        Ok(())
+    })
+}
+
+fn touch(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let time = match args.value('t') {
+        Some(stamp) => Some(touch_time(stamp, ctx.system.now())?),
+        None => None,
+    };
+    each(ctx, "touch", args.positional(), |ctx, file_name| {
+        let created = ctx.system.metadata(ctx.session, file_name).is_err();
+        ctx.system.touch(ctx.session, file_name)?;
+        if let Some(time) = time {
+            ctx.system.set_modified(ctx.session, file_name, time)?;
+        }
+        if created {
+            writeln!(ctx.console, "File '{}' created.", file_name)?;
+        }
+        Ok(())
+    })
+}
+
+// Function to read a `touch -t` time, `[[CC]YY]MMDDhhmm[.ss]` in local time;
+// the year defaults to the one `now` is in, and as with POSIX two-digit years
+// from 69 are in the 1900s
+fn touch_time(stamp: &str, now: SystemTime) -> Result<SystemTime> {
+    let invalid = || RnixError::InvalidArgument(format!("invalid date format '{}'", stamp));
+    let (digits, seconds) = match stamp.split_once('.') {
+        Some((digits, seconds)) if seconds.len() == 2 => (digits, seconds),
+        Some(_) => return Err(invalid()),
+        None => (stamp, "00"),
+    };
+    if !digits.bytes().chain(seconds.bytes()).all(|b| b.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    let number = |text: &str| text.parse::<u32>().map_err(|_| invalid());
+    let (year, rest) = match digits.len() {
+        8 => (chrono::DateTime::<Local>::from(now).year(), digits),
+        10 => match number(&digits[..2])? {
+            yy @ 69..=99 => (1900 + yy as i32, &digits[2..]),
+            yy => (2000 + yy as i32, &digits[2..]),
+        },
+        12 => (number(&digits[..4])? as i32, &digits[4..]),
+        _ => return Err(invalid()),
+    };
+    let local = Local
+        .with_ymd_and_hms(
+            year,
+            number(&rest[..2])?,
+            number(&rest[2..4])?,
+            number(&rest[4..6])?,
+            number(&rest[6..8])?,
+            number(seconds)?,
+        )
+        .earliest()
+        .ok_or_else(invalid)?;
+    Ok(local.into())
+}
+
+fn stat(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    each(ctx, "stat", args.positional(), |ctx, p| {
+        let metadata = ctx.system.metadata(ctx.session, p)?;
+        let kind = if metadata.is_dir { "directory" } else { "regular file" };
+        writeln!(ctx.console, "      File: {}", p)?;
+        writeln!(ctx.console, "      Size: {}", metadata.len)?;
+        writeln!(ctx.console, "      Type: {}", kind)?;
+        if !metadata.short_name.is_empty() {
+            writeln!(ctx.console, "Short name: {}", metadata.short_name)?;
+        }
+        let names: Vec<&str> = [
+            (FileAttributes::READ_ONLY, "read-only"),
+            (FileAttributes::HIDDEN, "hidden"),
+            (FileAttributes::SYSTEM, "system"),
+            (FileAttributes::DIRECTORY, "directory"),
+            (FileAttributes::ARCHIVE, "archive"),
+        ]
+        .iter()
+        .filter(|(flag, _)| metadata.attributes.contains(*flag))
+        .map(|&(_, name)| name)
+        .collect();
+        let names = if names.is_empty() { "none".to_string() } else { names.join(", ") };
+        writeln!(ctx.console, "Attributes: {} ({})", metadata.flags(), names)?;
+        match (metadata.created, metadata.modified, metadata.accessed) {
+            (Some(created), Some(modified), Some(accessed)) => {
+                writeln!(ctx.console, "   Created: {}", date_time(created, true))?;
+                writeln!(ctx.console, "  Modified: {}", date_time(modified, false))?;
+                let fatfs::Date { year, month, day } = accessed;
+                writeln!(ctx.console, "  Accessed: {:04}-{:02}-{:02}", year, month, day)?;
+            }
+            _ => writeln!(ctx.console, "The root of a disk has no timestamps.")?,
+        }
+        Ok(())
+    })
+}
+
+// A FAT date and time as `YYYY-MM-DD hh:mm:ss`, with hundredths of a second
+// for creation times, which are the only ones FAT keeps them for
+fn date_time(date_time: fatfs::DateTime, hundredths: bool) -> String {
+    let fatfs::DateTime { date, time } = date_time;
+    let mut text = format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        date.year, date.month, date.day, time.hour, time.min, time.sec
+    );
+    if hundredths {
+        text.push_str(&format!(".{:02}", time.millis / 10));
+    }
+    text
+}
+
+fn attrib(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let (mut set, mut clear) = (FileAttributes::empty(), FileAttributes::empty());
+    let mut paths = Vec::new();
+    for &word in args.positional() {
+        let change = match word.as_bytes() {
+            [sign @ (b'+' | b'-'), letter] => Some((*sign, letter.to_ascii_lowercase())),
+            _ => None,
+        };
+        let attribute = match change.map(|(_, letter)| letter) {
+            Some(b'r') => FileAttributes::READ_ONLY,
+            Some(b'h') => FileAttributes::HIDDEN,
+            Some(b's') => FileAttributes::SYSTEM,
+            Some(b'a') => FileAttributes::ARCHIVE,
+            Some(_) => return Err(args.usage_error()),
+            _ => {
+                paths.push(word);
+                continue;
+            }
+        };
+        if change.is_some_and(|(sign, _)| sign == b'+') {
+            set |= attribute;
+            clear -= attribute;
+        } else {
+            clear |= attribute;
+            set -= attribute;
+        }
+    }
+    if paths.is_empty() {
+        return Err(args.usage_error());
+    }
+    let changing = !(set | clear).is_empty();
+    each(ctx, "attrib", &paths, |ctx, p| {
+        let attributes = if changing {
+            ctx.system.set_attributes(ctx.session, p, set, clear)?
+        } else {
+            ctx.system.metadata(ctx.session, p)?.attributes
+        };
+        writeln!(ctx.console, "{}  {}", disk::attribute_flags(attributes), p)?;
+        Ok(())
+    })
+}
+
+fn rm(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    each(ctx, "rm", args.positional(), |ctx, item_name| {
+        let kind = if ctx.system.is_dir(ctx.session, item_name) { "Directory" } else { "File" };
+        ctx.system.remove(ctx.session, item_name)?;
+        writeln!(ctx.console, "{} '{}' removed.", kind, item_name)?;
+        Ok(())
+    })
+}
+
+fn mv(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let moves = destinations(ctx, args)?;
+    each(ctx, "mv", &moves, |ctx, (src, dst)| {
+        ctx.system.rename(ctx.session, src, dst)?;
+        writeln!(ctx.console, "Renamed '{}' to '{}'.", src, dst)?;
+        Ok(())
+    })
+}
+
+fn cp(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let copies = destinations(ctx, args)?;
+    each(ctx, "cp", &copies, |ctx, (src, dst)| {
+        ctx.system.copy(ctx.session, src, dst)?;
+        writeln!(ctx.console, "File '{}' copied to '{}'.", src, dst)?;
+        Ok(())
+    })
+}
+
+// Work out each source's destination for `SRC DST`, `SRC... DIR` and the
+// older `SRC DST_DIR DST_NAME` form (three arguments whose last is not a
+// directory). Three words from a glob such as `cp *.txt dir` are always
+// sources and a directory, never the older form.
+fn destinations<'a>(ctx: &Context<'_>, args: &Args<'a>) -> Result<Vec<(&'a str, String)>> {
+    let (target, sources) = match args.positional().split_last() {
+        Some((target, sources)) => (*target, sources),
+        None => unreachable!("spec requires at least two arguments"),
+    };
+    match sources {
+        [src] => Ok(vec![(*src, target.to_string())]),
+        _ if ctx.system.is_dir(ctx.session, target) => Ok(sources
+            .iter()
+            .map(|src| {
+                let (_, name) = path::split(src.trim_end_matches('/'));
+                (*src, path::join(target, name))
+            })
+            .collect()),
+        [src, dst_dir] if !ctx.globbed => Ok(vec![(*src, path::join(dst_dir, target))]),
+        _ => Err(RnixError::InvalidArgument(format!("target '{}' is not a directory", target))),
+    }
+}
+
+// Function to apply `action` to each item in turn, carrying on past failures.
+// With several items each failure is printed as it happens; a lone item's
+// error is returned as is.
+fn each<T>(
+    ctx: &mut Context<'_>,
+    command: &str,
+    items: &[T],
+    mut action: impl FnMut(&mut Context<'_>, &T) -> Result<()>,
+) -> Result<Flow> {
+    let mut status = 0;
+    for item in items {
+        if let Err(err) = action(ctx, item) {
+            if items.len() == 1 {
+                return Err(err);
+            }
+            ctx.console.write_error(&format!("{}: {}\n", command, err))?;
+            status = err.status();
+        }
+    }
+    match status {
+        0 => Ok(Flow::Continue),
+        status => Err(RnixError::Reported(status)),
+    }
+}
+
+fn import(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let (host, dest) = (args.required(0)?, args.required(1)?);
+    let done = transfer::import(ctx.system, ctx.session, Path::new(host), dest)?;
+    writeln!(ctx.console, "Imported {} from '{}'.", done, host)?;
+    Ok(Flow::Continue)
+}
+
+fn export_host(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let (src, host) = (args.required(0)?, args.required(1)?);
+    let done = transfer::export(ctx.system, ctx.session, src, Path::new(host))?;
+    writeln!(ctx.console, "Exported {} to '{}'.", done, host)?;
+    Ok(Flow::Continue)
+}
+
+fn find(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let query = find::Query::parse(args.positional())?;
+    let now = ctx.system.now();
+    let mut status = 0;
+    let mut failed = |ctx: &mut Context<'_>, command: &str, err: RnixError| -> Result<()> {
+        if !matches!(err, RnixError::Reported(_)) {
+            ctx.console.write_error(&format!("{}: {}\n", command, err))?;
+        }
+        status = err.status();
+        Ok(())
+    };
+    for start in &query.paths {
+        let matched = match find::search(ctx.system, ctx.session, &query, start, now) {
+            Ok(matched) => matched,
+            Err(err) => {
+                failed(ctx, "find", err)?;
+                continue;
+            }
+        };
+        for found in matched {
+            for action in &query.actions {
+                match action {
+                    find::Action::Print => writeln!(ctx.console, "{}", found.path)?,
+                    // As with GNU find, the current directory itself is never deleted
+                    find::Action::Delete if found.path == "." => {}
+                    find::Action::Delete => {
+                        if let Err(err) = ctx.system.remove(ctx.session, &found.path) {
+                            failed(ctx, "find", err)?;
+                        }
+                    }
+                    find::Action::Exec(command) => {
+                        let words: Vec<String> = command.iter().map(|word| word.replace("{}", &found.path)).collect();
+                        let words: Vec<&str> = words.iter().map(String::as_str).collect();
+                        let registry = ctx.registry;
+                        if let Err(err) = registry.dispatch(ctx, &words) {
+                            failed(ctx, words[0], err)?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+    match status {
+        0 => Ok(Flow::Continue),
+        status => Err(RnixError::Reported(status)),
+    }
+}
+
+fn grep(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let regex = text::pattern(args.required(0)?, args.has('i'))?;
+    let recursive = args.has('r');
+    let mut files = args.positional()[1..].to_vec();
+    // Only without piped input does -r fall back to the current directory
+    if recursive && files.is_empty() && ctx.stdin.is_none() {
+        files.push(".");
+    }
+    // Directories stand for every file below them with -r
+    let mut names = Vec::new();
+    for &file in &files {
+        if recursive && ctx.system.is_dir(ctx.session, file) {
+            let query = find::Query::parse(&[file, "-type", "f"])?;
+            let found = find::search(ctx.system, ctx.session, &query, file, ctx.system.now())?;
+            names.extend(found.into_iter().map(|found| found.path));
+        } else {
+            names.push(file.to_string());
+        }
+    }
+    let names: Vec<&str> = names.iter().map(String::as_str).collect();
+    let prefix = (recursive && !names.is_empty()) || names.len() > 1;
+
+    let mut matched = false;
+    for (name, contents) in inputs(ctx, &names)? {
+        for (index, line) in contents.lines().enumerate() {
+            if regex.is_match(line) == args.has('v') {
+                continue;
+            }
+            matched = true;
+            if prefix {
+                write!(ctx.console, "{}:", name)?;
+            }
+            if args.has('n') {
+                write!(ctx.console, "{}:", index + 1)?;
+            }
+            writeln!(ctx.console, "{}", line)?;
+        }
+    }
+    // As with grep, finding nothing is exit status 1
+    match matched {
+        true => Ok(Flow::Continue),
+        false => Err(RnixError::Reported(1)),
+    }
+}
+
+fn sort(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let contents: String = inputs(ctx, args.positional())?.into_iter().map(|(_, contents)| contents).collect();
+    let mut lines: Vec<&str> = contents.lines().collect();
+    text::sort(&mut lines, args.has('n'), args.has('r'), args.has('u'));
+    for line in lines {
+        writeln!(ctx.console, "{}", line)?;
+    }
+    Ok(Flow::Continue)
+}
+
+fn uniq(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let contents: String = inputs(ctx, args.positional())?.into_iter().map(|(_, contents)| contents).collect();
+    let lines: Vec<&str> = contents.lines().collect();
+    for (count, line) in text::uniq(&lines) {
+        if (args.has('d') && count == 1) || (args.has('u') && count > 1) {
+            continue;
+        }
+        if args.has('c') {
+            write!(ctx.console, "{:>7} ", count)?;
+        }
+        writeln!(ctx.console, "{}", line)?;
+    }
+    Ok(Flow::Continue)
+}
+
+fn cut(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let (list, by_fields) = match (args.value('f'), args.value('c')) {
+        (Some(list), None) => (list, true),
+        (None, Some(list)) if args.value('d').is_none() => (list, false),
+        _ => return Err(args.usage_error()),
+    };
+    let list = text::List::parse(list)?;
+    let mut delimiter = args.value('d').unwrap_or("\t").chars();
+    let delimiter = match (delimiter.next(), delimiter.next()) {
+        (Some(c), None) => c,
+        (Some('\\'), Some('t')) if delimiter.next().is_none() => '\t',
+        _ => return Err(RnixError::InvalidArgument("the delimiter must be a single character".to_string())),
+    };
+    for (_, contents) in inputs(ctx, args.positional())? {
+        for line in contents.lines() {
+            let kept = match by_fields {
+                true => list.fields(line, delimiter),
+                false => list.chars(line),
+            };
+            writeln!(ctx.console, "{}", kept)?;
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+fn tr(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let from = text::char_set(args.required(0)?)?;
+    let translated = match (args.has('d'), args.get(1)) {
+        (true, None) => {
+            let contents = piped(ctx)?;
+            contents.chars().filter(|c| !from.contains(c)).collect()
+        }
+        (false, Some(to)) if !to.is_empty() => {
+            let to = text::char_set(to)?;
+            text::translate(&piped(ctx)?, &from, &to)
+        }
+        _ => return Err(args.usage_error()),
+    };
+    write!(ctx.console, "{}", translated)?;
+    Ok(Flow::Continue)
+}
+
+fn sed(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let substitution = text::Substitution::parse(args.required(0)?)?;
+    for (_, contents) in inputs(ctx, &args.positional()[1..])? {
+        for line in contents.lines() {
+            writeln!(ctx.console, "{}", substitution.apply(line))?;
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+fn diff(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let files = inputs(ctx, args.positional())?;
+    let old: Vec<&str> = files[0].1.lines().collect();
+    let new: Vec<&str> = files[1].1.lines().collect();
+    let changes = text::diff(&old, &new);
+    for line in &changes {
+        writeln!(ctx.console, "{}", line)?;
+    }
+    // As with diff, status 1 means the files differ
+    match changes.is_empty() {
+        true => Ok(Flow::Continue),
+        false => Err(RnixError::Reported(1)),
+    }
+}
+
+// Function to read what a text command works on: each of `files` in the image,
+// with `-` for piped input, or the piped input alone when there are no files.
+// Pairs each with the name to show for it.
+fn inputs(ctx: &mut Context<'_>, files: &[&str]) -> Result<Vec<(String, String)>> {
+    if files.is_empty() {
+        return Ok(vec![("(standard input)".to_string(), piped(ctx)?)]);
+    }
+    let mut read = Vec::new();
+    for &file in files {
+        let contents = match file {
+            "-" => piped(ctx)?,
+            file => String::from_utf8_lossy(&ctx.system.read_file(ctx.session, file)?).into_owned(),
+        };
+        read.push((file.to_string(), contents));
+    }
+    Ok(read)
+}
+
+// Function to take the output of the previous command in the pipeline
+fn piped(ctx: &mut Context<'_>) -> Result<String> {
+    ctx.stdin
+        .take()
+        .ok_or_else(|| RnixError::InvalidArgument("no input; name a file or pipe a command into this one".to_string()))
+}
+
+fn ls(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let long = args.has('l');
+    if args.is_empty() {
+        let entries = ctx.system.list_dir(ctx.session, ".")?;
+        writeln!(ctx.console, "Contents of current directory:")?;
+        for entry in entries {
+            writeln!(ctx.console, "{}", ls_line(&entry, long))?;
+        }
+        return Ok(Flow::Continue);
+    }
+    each(ctx, "ls", args.positional(), |ctx, dir_name| {
+        // As in sh, a file argument lists just its own name
+        if ctx.system.is_file(ctx.session, dir_name) {
+            let metadata = ctx.system.metadata(ctx.session, dir_name)?;
+            let entry = EntryInfo {
+                name: dir_name.to_string(),
+                is_dir: false,
+                len: metadata.len,
+                attributes: metadata.attributes,
+                // Only the root of a disk has no times, and it is a directory
+                modified: metadata.modified.ok_or_else(|| RnixError::NotFound(dir_name.to_string()))?,
+            };
+            writeln!(ctx.console, "{}", ls_line(&entry, long))?;
+            return Ok(());
+        }
+        let entries = ctx.system.list_dir(ctx.session, dir_name)?;
+        writeln!(ctx.console, "Contents of directory '{}':", dir_name)?;
+        for entry in entries {
+            writeln!(ctx.console, "{}", ls_line(&entry, long))?;
+        }
+        Ok(())
+    })
+}
+
+// An `ls` line: the name alone, or with `long` laid out as `readdisk` does
+fn ls_line(entry: &EntryInfo, long: bool) -> String {
+    if !long {
+        return entry.name.clone();
+    }
+    let size = if entry.is_dir { "-".to_string() } else { entry.len.to_string() };
+    let fatfs::DateTime { date, time } = entry.modified;
+    format!(
+        "{} {:>10} {:04}-{:02}-{:02} {:02}:{:02}  {}{}",
+        entry.flags(),
+        size,
+        date.year,
+        date.month,
+        date.day,
+        time.hour,
+        time.min,
+        entry.name,
+        if entry.is_dir { "/" } else { "" }
+    )
+}
+
+fn cd(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    // No argument means $HOME, and `-` the previous directory
+    let env = ctx.session.env();
+    let new_dir_name = match args.get(0) {
+        Some("-") => env.get("OLDPWD").ok_or_else(|| RnixError::InvalidArgument("OLDPWD not set".to_string()))?,
+        Some(dir) => dir,
+        None => env.get("HOME").ok_or_else(|| RnixError::InvalidArgument("HOME not set".to_string()))?,
+    }
+    .to_string();
+    ctx.system.cd(ctx.session, &new_dir_name)?;
+    writeln!(ctx.console, "Changed directory to '{}'.", new_dir_name)?;
+    Ok(Flow::Continue)
+}
+
+fn export(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    if args.is_empty() {
+        for (name, value) in ctx.session.env().iter() {
+            writeln!(ctx.console, "export {}=\"{}\"", name, value.replace('\\', "\\\\").replace('"', "\\\""))?;
+        }
+    }
+    for assignment in args.positional() {
+        match assignment.split_once('=') {
+            Some((name, value)) => ctx.session.env_mut().set(name, value)?,
+            // Every variable is already exported; just check the name
+            None if is_valid_name(assignment) => {}
+            None => {
+                // Two paths are most likely meant as a copy out to the host
+                let hint = match args.positional() {
+                    [_, _] => " (export-host copies a file out to the host)",
+                    _ => "",
+                };
+                return Err(RnixError::InvalidArgument(format!("`{}': not a valid identifier{}", assignment, hint)));
+            }
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+fn unset(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    for name in args.positional() {
+        if args.has('f') {
+            ctx.session.functions_mut().remove(name);
+        } else {
+            ctx.session.env_mut().unset(name);
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+fn alias(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    if args.is_empty() {
+        let aliases: Vec<String> = ctx.session.aliases().iter().map(|(name, value)| quote_alias(name, value)).collect();
+        for alias in aliases {
+            writeln!(ctx.console, "{}", alias)?;
+        }
+    }
+    let mut missing = None;
+    for definition in args.positional() {
+        match definition.split_once('=') {
+            Some((name, value)) => ctx.session.aliases_mut().set(name, value)?,
+            None => match ctx.session.aliases().get(definition) {
+                Some(value) => {
+                    let line = quote_alias(definition, value);
+                    writeln!(ctx.console, "{}", line)?;
+                }
+                None => missing = Some(definition),
+            },
+        }
+    }
+    match missing {
+        Some(name) => Err(RnixError::InvalidArgument(format!("{}: not found", name))),
+        None => Ok(Flow::Continue),
+    }
+}
+
+// Function to print an alias so that it can be pasted back into a startup file
+fn quote_alias(name: &str, value: &str) -> String {
+    format!("alias {}='{}'", name, value.replace('\'', "'\\''"))
+}
+
+fn unalias(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    if args.has('a') {
+        ctx.session.aliases_mut().clear();
+        return Ok(Flow::Continue);
+    }
+    if args.is_empty() {
+        return Err(args.usage_error());
+    }
+    for name in args.positional() {
+        if !ctx.session.aliases_mut().remove(name) {
+            return Err(RnixError::InvalidArgument(format!("{}: not found", name)));
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+fn echo(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let (newline, words) = match args.positional() {
+        ["-n", rest @ ..] => (false, rest),
+        all => (true, all),
+    };
+    write!(ctx.console, "{}", words.join(" "))?;
+    if newline {
+        writeln!(ctx.console)?;
+    }
+    Ok(Flow::Continue)
+}
+
+fn env(ctx: &mut Context<'_>, _args: &Args<'_>) -> Result<Flow> {
+    for (name, value) in ctx.session.env().iter() {
+        writeln!(ctx.console, "{}={}", name, value)?;
+    }
+    Ok(Flow::Continue)
+}
+
+fn pwd(ctx: &mut Context<'_>, _args: &Args<'_>) -> Result<Flow> {
+    writeln!(ctx.console, "{}", ctx.session.cwd())?;
+    Ok(Flow::Continue)
+}
+
+fn edit(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let file_name = args.required(0)?;
+    if args.has('v') && args.has('e') {
+        return Err(args.usage_error());
+    }
+    // Refuse before any editing rather than when the work is saved
+    ctx.system.check_writable(ctx.session, file_name)?;
+    if args.has('e') {
+        let command = external::editor_command(ctx.session)?;
+        match external::edit(ctx.system, ctx.session, file_name, |copy| external::launch(&command, copy))? {
+            Outcome::Unchanged => writeln!(ctx.console, "No changes to '{}'.", file_name)?,
+            Outcome::Saved(bytes) => writeln!(ctx.console, "File '{}' has been updated ({} bytes).", file_name, bytes)?,
+        }
+    } else if args.has('v') {
+        visual::edit(ctx.system, ctx.session, ctx.console, file_name)?;
+    } else {
+        Ed::open(ctx.system, ctx.session, ctx.console, file_name)?.run()?;
+    }
+    Ok(Flow::Continue)
+}
+
+fn clear(ctx: &mut Context<'_>, _args: &Args<'_>) -> Result<Flow> {
+    ctx.console.clear()?;
+    Ok(Flow::Continue)
+}
+
+fn whoami(ctx: &mut Context<'_>, _args: &Args<'_>) -> Result<Flow> {
+    writeln!(ctx.console, "{}", ctx.session.user())?;
+    Ok(Flow::Continue)
+}
+
+fn history(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    if args.has('c') {
+        ctx.history.clear();
+    } else if args.has('w') {
+        save_history(ctx.system, ctx.session, ctx.history)?;
+    } else {
+        let skip = match args.get(0) {
+            Some(count) => {
+                let count: usize = count.parse().map_err(|_| args.usage_error())?;
+                ctx.history.len().saturating_sub(count)
+            }
+            None => 0,
+        };
+        for (number, line) in ctx.history.iter().skip(skip) {
+            writeln!(ctx.console, "{:>5}  {}", number, line)?;
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+fn run(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let executable_name = args.required(0)?;
+    process::Command::new(executable_name)
+        .args(&args.positional()[1..])
+        .stdout(Stdio::inherit())
+        .stderr(Stdio::inherit())
+        .spawn()
+        .at(executable_name)?;
+    writeln!(ctx.console, "Executing {}...", executable_name)?;
+    Ok(Flow::Continue)
+}
+
+fn sudo(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let password = ctx.console.prompt_password("Password: ")?.unwrap_or_default();
+    if ctx.system.authenticate(ctx.session.user(), &password).is_err() {
+        return Err(RnixError::AuthFailed);
+    }
+    // Only the command sudo runs is elevated, not whatever runs after it
+    let elevated = std::mem::replace(&mut ctx.elevated, true);
+    let registry = ctx.registry;
+    let result = registry.dispatch(ctx, args.positional());
+    ctx.elevated = elevated;
+    // The command's own errors are labelled with its name rather than sudo's
+    match result {
+        Err(err @ (RnixError::Reported(_) | RnixError::UnknownCommand(_))) => Err(err),
+        Err(err) => {
+            ctx.console.write_error(&format!("{}: {}\n", args.required(0)?, err))?;
+            Err(RnixError::Reported(err.status()))
+        }
+        flow => flow,
+    }
+}
+
+fn source(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    shell::source(ctx, args.required(0)?)
+}
+
+fn resetroot(ctx: &mut Context<'_>, _args: &Args<'_>) -> Result<Flow> {
+    let removed = ctx.system.reset_root(ctx.session)?;
+    writeln!(ctx.console, "Resetting root disk...")?;
+    for file in removed {
+        writeln!(ctx.console, "{} removed.", file)?;
+    }
+    writeln!(ctx.console, "Root disk reset complete. Please restart the program.")?;
+    Ok(Flow::Continue)
+}
+
+fn version(ctx: &mut Context<'_>, _args: &Args<'_>) -> Result<Flow> {
+    writeln!(ctx.console, "{}", get_rnix_version())?;
+    writeln!(ctx.console, "{}", get_rnix_api_version())?;
+    Ok(Flow::Continue)
+}
+
+fn help(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let registry = ctx.registry;
+    match args.get(0) {
+        Some(name) => {
+            let command = registry
+                .find(name)
+                .ok_or_else(|| RnixError::InvalidArgument(format!("no help topics match '{}'", name)))?;
+            writeln!(ctx.console, "usage: {}", synopsis(command))?;
+            writeln!(ctx.console, "    {}", command.help())?;
+            if !command.aliases().is_empty() {
+                writeln!(ctx.console, "    aliases: {}", command.aliases().join(", "))?;
+            }
+            match command.privilege() {
+                Privilege::Sudo => writeln!(ctx.console, "    Requires sudo.")?,
+                Privilege::Root => writeln!(ctx.console, "    Only root can run this command.")?,
+                Privilege::User => {}
+            }
+        }
+        None => {
+            writeln!(ctx.console, "Available commands:")?;
+            for command in registry.commands() {
+                writeln!(ctx.console, "  {} - {}", synopsis(command), command.help())?;
+            }
+            writeln!(ctx.console, "Type 'help <command>' for more about a command.")?;
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+// Function to render a command's name followed by its usage line
+fn synopsis(command: &dyn Command) -> String {
+    format!("{} {}", command.name(), command.spec().usage()).trim_end().to_string()
+}
+
+fn exit(ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+    let status = match args.get(0) {
+        Some(code) => code
+            .parse()
+            .map_err(|_| RnixError::InvalidArgument(format!("{}: numeric argument required", code)))?,
+        None => ctx.status,
+    };
+    Ok(Flow::Exit(status))
+}
@@ -0,0 +1,76 @@
+// Where rnix gets the current time: the host clock normally, a fixed time in
+// tests so that timestamps written to the image come out the same every run.
+
+use std::fmt;
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+
+use crate::transfer::to_fat;
+
+pub trait TimeProvider: fmt::Debug + Send + Sync {
+    fn now(&self) -> SystemTime;
+}
+
+// The host's clock
+#[derive(Debug, Clone, Copy, Default)]
+pub struct HostTime;
+
+impl TimeProvider for HostTime {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+// A clock that only moves when told to
+#[derive(Debug)]
+pub struct FixedTime(Mutex<SystemTime>);
+
+impl FixedTime {
+    pub fn new(time: SystemTime) -> FixedTime {
+        FixedTime(Mutex::new(time))
+    }
+
+    pub fn set(&self, time: SystemTime) {
+        *self.0.lock().unwrap() = time;
+    }
+
+    pub fn advance(&self, by: Duration) {
+        *self.0.lock().unwrap() += by;
+    }
+}
+
+impl TimeProvider for FixedTime {
+    fn now(&self) -> SystemTime {
+        *self.0.lock().unwrap()
+    }
+}
+
+// The provider a `System` and its filesystems use, which can be swapped
+// after the filesystems have been opened
+#[derive(Debug)]
+pub(crate) struct Clock(RwLock<Arc<dyn TimeProvider>>);
+
+impl Clock {
+    // fatfs only takes a `'static` time provider, so each `System` leaks one
+    pub(crate) fn leak() -> &'static Clock {
+        Box::leak(Box::new(Clock(RwLock::new(Arc::new(HostTime)))))
+    }
+
+    pub(crate) fn set(&self, provider: Arc<dyn TimeProvider>) {
+        *self.0.write().unwrap() = provider;
+    }
+
+    pub(crate) fn now(&self) -> SystemTime {
+        self.0.read().unwrap().now()
+    }
+}
+
+impl fatfs::TimeProvider for Clock {
+    fn get_current_date(&self) -> fatfs::Date {
+        to_fat(self.now()).date
+    }
+
+    fn get_current_date_time(&self) -> fatfs::DateTime {
+        to_fat(self.now())
+    }
+}
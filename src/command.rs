@@ -0,0 +1,382 @@
+// The command registry: every shell command declares its name, aliases,
+// argument spec, help text and privilege level, and the registry takes care of
+// lookup, privilege checks, option parsing and usage errors before calling it.
+
+use crate::console::Console;
+use crate::error::{Result, RnixError};
+use crate::history::History;
+use crate::shell::Flow;
+use crate::system::{Session, System};
+
+// Who may run a command
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Privilege {
+    // Anyone
+    User,
+    // Only through `sudo`
+    Sudo,
+    // Only the root account
+    Root,
+}
+
+// What a command accepts: a usage line, the number of positional arguments,
+// and single-letter flags (`-f`) and options taking a value (`-o VALUE`), which
+// may also have long names (`--force`, `--output=VALUE`).
+// Options must come before the positional arguments; `--` ends them.
+#[derive(Debug, Clone, Default)]
+pub struct Spec {
+    usage: String,
+    min: usize,
+    max: Option<usize>,
+    flags: Vec<char>,
+    options: Vec<char>,
+    longs: Vec<(String, char)>,
+    raw: bool,
+}
+
+impl Spec {
+    // A command taking no arguments; `usage` is shown after the command name
+    pub fn new(usage: &str) -> Spec {
+        Spec {
+            usage: usage.to_string(),
+            max: Some(0),
+            ..Spec::default()
+        }
+    }
+
+    // Accept between `min` and `max` positional arguments (`None` for no limit)
+    pub fn args(mut self, min: usize, max: Option<usize>) -> Spec {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    pub fn flag(mut self, flag: char) -> Spec {
+        self.flags.push(flag);
+        self
+    }
+
+    pub fn option(mut self, option: char) -> Spec {
+        self.options.push(option);
+        self
+    }
+
+    // Accept `--name` for the flag or option `short`
+    pub fn long(mut self, name: &str, short: char) -> Spec {
+        self.longs.push((name.to_string(), short));
+        self
+    }
+
+    // Hand every argument to the command unparsed, even ones starting with `-`
+    pub fn raw(mut self) -> Spec {
+        self.raw = true;
+        self
+    }
+
+    pub fn usage(&self) -> &str {
+        &self.usage
+    }
+
+    // Split `words` into flags, options and positional arguments
+    pub fn parse<'a>(&self, command: &str, words: &[&'a str]) -> Result<Args<'a>> {
+        let usage = format!("{} {}", command, self.usage).trim_end().to_string();
+        let usage_error = || RnixError::Usage(usage.clone());
+        let mut args = Args {
+            positional: Vec::new(),
+            flags: Vec::new(),
+            options: Vec::new(),
+            usage: usage.clone(),
+        };
+
+        let mut rest = words;
+        let is_option = |word: &&&str| !self.raw && word.len() > 1 && word.starts_with('-');
+        while let Some(&word) = rest.first().filter(is_option) {
+            rest = &rest[1..];
+            if word == "--" {
+                break;
+            }
+            if let Some(long) = word.strip_prefix("--") {
+                let (name, value) = match long.split_once('=') {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (long, None),
+                };
+                let c = self.longs.iter().find(|(long, _)| long == name).ok_or_else(usage_error)?.1;
+                if self.options.contains(&c) {
+                    let value = match value {
+                        Some(value) => value,
+                        None => {
+                            let next = rest.first().ok_or_else(usage_error)?;
+                            rest = &rest[1..];
+                            *next
+                        }
+                    };
+                    args.options.push((c, value));
+                } else if value.is_none() {
+                    args.flags.push(c);
+                } else {
+                    return Err(usage_error());
+                }
+                continue;
+            }
+            for (index, c) in word.char_indices().skip(1) {
+                if self.flags.contains(&c) {
+                    args.flags.push(c);
+                } else if self.options.contains(&c) {
+                    let value = &word[index + c.len_utf8()..];
+                    let value = if value.is_empty() {
+                        let next = rest.first().ok_or_else(usage_error)?;
+                        rest = &rest[1..];
+                        *next
+                    } else {
+                        value
+                    };
+                    args.options.push((c, value));
+                    break;
+                } else {
+                    return Err(usage_error());
+                }
+            }
+        }
+
+        args.positional = rest.to_vec();
+        if args.positional.len() < self.min || self.max.is_some_and(|max| args.positional.len() > max) {
+            return Err(usage_error());
+        }
+        Ok(args)
+    }
+}
+
+// Parsed arguments of one invocation
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Args<'a> {
+    positional: Vec<&'a str>,
+    flags: Vec<char>,
+    options: Vec<(char, &'a str)>,
+    usage: String,
+}
+
+impl<'a> Args<'a> {
+    pub fn positional(&self) -> &[&'a str] {
+        &self.positional
+    }
+
+    pub fn get(&self, index: usize) -> Option<&'a str> {
+        self.positional.get(index).copied()
+    }
+
+    // Positional argument the spec guarantees to be present
+    pub fn required(&self, index: usize) -> Result<&'a str> {
+        self.get(index).ok_or_else(|| self.usage_error())
+    }
+
+    pub fn len(&self) -> usize {
+        self.positional.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positional.is_empty()
+    }
+
+    pub fn has(&self, flag: char) -> bool {
+        self.flags.contains(&flag)
+    }
+
+    // Value of the last occurrence of an option
+    pub fn value(&self, option: char) -> Option<&'a str> {
+        self.options.iter().rev().find(|(c, _)| *c == option).map(|(_, value)| *value)
+    }
+
+    // The command's usage error, for arguments the spec can't validate itself
+    pub fn usage_error(&self) -> RnixError {
+        RnixError::Usage(self.usage.clone())
+    }
+}
+
+// Everything a running command can reach
+pub struct Context<'a> {
+    pub system: &'a mut System,
+    pub console: &'a mut dyn Console,
+    pub session: &'a mut Session,
+    pub history: &'a mut History,
+    pub registry: &'a Registry,
+    // Exit status of the previous command line
+    pub status: i32,
+    // True when running under `sudo`
+    pub elevated: bool,
+    // Positional parameters ($1, $2, ...) of the running shell function
+    pub params: Vec<String>,
+    // Nesting depth of shell function calls
+    pub depth: usize,
+    // Output of the previous command in a pipeline, for commands that read input
+    pub stdin: Option<String>,
+    // True when pathname expansion supplied any of the running command's words
+    pub globbed: bool,
+}
+
+pub trait Command {
+    fn name(&self) -> &str;
+
+    fn aliases(&self) -> &[String] {
+        &[]
+    }
+
+    fn spec(&self) -> &Spec;
+
+    // One-line description for `help`
+    fn help(&self) -> &str;
+
+    fn privilege(&self) -> Privilege {
+        Privilege::User
+    }
+
+    fn run(&self, ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow>;
+}
+
+type Handler = Box<dyn Fn(&mut Context<'_>, &Args<'_>) -> Result<Flow>>;
+
+// A command made from a function or closure
+pub struct Builtin {
+    name: String,
+    aliases: Vec<String>,
+    spec: Spec,
+    help: String,
+    privilege: Privilege,
+    handler: Handler,
+}
+
+impl Builtin {
+    pub fn new<F>(name: &str, spec: Spec, help: &str, handler: F) -> Builtin
+    where
+        F: Fn(&mut Context<'_>, &Args<'_>) -> Result<Flow> + 'static,
+    {
+        Builtin {
+            name: name.to_string(),
+            aliases: Vec::new(),
+            spec,
+            help: help.to_string(),
+            privilege: Privilege::User,
+            handler: Box::new(handler),
+        }
+    }
+
+    pub fn alias(mut self, alias: &str) -> Builtin {
+        self.aliases.push(alias.to_string());
+        self
+    }
+
+    pub fn privilege(mut self, privilege: Privilege) -> Builtin {
+        self.privilege = privilege;
+        self
+    }
+}
+
+impl Command for Builtin {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn aliases(&self) -> &[String] {
+        &self.aliases
+    }
+
+    fn spec(&self) -> &Spec {
+        &self.spec
+    }
+
+    fn help(&self) -> &str {
+        &self.help
+    }
+
+    fn privilege(&self) -> Privilege {
+        self.privilege
+    }
+
+    fn run(&self, ctx: &mut Context<'_>, args: &Args<'_>) -> Result<Flow> {
+        (self.handler)(ctx, args)
+    }
+}
+
+// The commands a shell knows, in registration order
+#[derive(Default)]
+pub struct Registry {
+    commands: Vec<Box<dyn Command>>,
+}
+
+impl Registry {
+    // An empty registry
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    // A registry holding the standard rnix commands
+    pub fn with_builtins() -> Registry {
+        let mut registry = Registry::new();
+        crate::builtins::register(&mut registry);
+        registry
+    }
+
+    // Add a command, replacing any earlier one with the same name
+    pub fn register<T: Command + 'static>(&mut self, command: T) {
+        match self.commands.iter().position(|c| c.name() == command.name()) {
+            Some(index) => self.commands[index] = Box::new(command),
+            None => self.commands.push(Box::new(command)),
+        }
+    }
+
+    pub fn unregister(&mut self, name: &str) -> bool {
+        let before = self.commands.len();
+        self.commands.retain(|c| c.name() != name);
+        self.commands.len() != before
+    }
+
+    // Look a command up by name or alias
+    pub fn find(&self, name: &str) -> Option<&dyn Command> {
+        self.commands
+            .iter()
+            .find(|c| c.name() == name)
+            .or_else(|| self.commands.iter().find(|c| c.aliases().iter().any(|a| a == name)))
+            .map(|c| c.as_ref())
+    }
+
+    pub fn commands(&self) -> impl Iterator<Item = &dyn Command> {
+        self.commands.iter().map(|c| c.as_ref())
+    }
+
+    // Every name and alias, for completion
+    pub fn names(&self) -> Vec<String> {
+        let mut names = Vec::new();
+        for command in self.commands() {
+            names.push(command.name().to_string());
+            names.extend(command.aliases().iter().cloned());
+        }
+        names
+    }
+
+    // Run `words[0]` with the rest of `words` as its arguments
+    pub fn dispatch(&self, ctx: &mut Context<'_>, words: &[&str]) -> Result<Flow> {
+        let name = match words.first() {
+            Some(name) => *name,
+            None => return Ok(Flow::Continue),
+        };
+        let command = self
+            .find(name)
+            .ok_or_else(|| RnixError::UnknownCommand(name.to_string()))?;
+        match command.privilege() {
+            Privilege::Sudo if !ctx.elevated => {
+                return Err(RnixError::PermissionDenied(format!(
+                    "This command requires sudo privileges. Use 'sudo {}' to run this command.",
+                    command.name()
+                )));
+            }
+            Privilege::Root if !ctx.session.is_root() => {
+                return Err(RnixError::PermissionDenied(format!(
+                    "Only root can execute {} command.",
+                    command.name()
+                )));
+            }
+            _ => {}
+        }
+        let args = command.spec().parse(command.name(), &words[1..])?;
+        command.run(ctx, &args)
+    }
+}
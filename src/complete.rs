@@ -0,0 +1,170 @@
+// Tab completion for the shell prompt.
+
+use crate::system::{Session, System, MOUNTABLE_DISKS, ROOT_DISK};
+
+// Candidates for the word ending at the cursor, which starts at char index `start`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Completion {
+    pub start: usize,
+    pub candidates: Vec<String>,
+}
+
+pub trait Completer {
+    fn complete(&self, line: &str, cursor: usize) -> Completion;
+}
+
+// Completer that never offers anything
+pub struct NoCompletion;
+
+impl Completer for NoCompletion {
+    fn complete(&self, _line: &str, cursor: usize) -> Completion {
+        Completion {
+            start: cursor,
+            candidates: Vec::new(),
+        }
+    }
+}
+
+// Commands whose arguments are disk names rather than paths
+const DISK_COMMANDS: [&str; 3] = ["mount", "umount", "readdisk"];
+
+// Completes command names, disk names and paths inside the mounted disks
+pub struct ShellCompleter<'a> {
+    system: &'a System,
+    session: &'a Session,
+    commands: Vec<String>,
+}
+
+impl<'a> ShellCompleter<'a> {
+    pub fn new(system: &'a System, session: &'a Session, commands: Vec<String>) -> ShellCompleter<'a> {
+        let mut commands = commands;
+        commands.sort();
+        commands.dedup();
+        ShellCompleter {
+            system,
+            session,
+            commands,
+        }
+    }
+
+    fn disks(&self, command: &str) -> Vec<String> {
+        let mounted = self.system.mounted_disks();
+        match command {
+            "mount" => MOUNTABLE_DISKS
+                .iter()
+                .filter(|disk| !mounted.iter().any(|m| m == *disk))
+                .map(|disk| disk.to_string())
+                .collect(),
+            "umount" => mounted,
+            _ => std::iter::once(ROOT_DISK)
+                .chain(MOUNTABLE_DISKS)
+                .map(str::to_string)
+                .collect(),
+        }
+    }
+
+    // Entries of the directory part of `word` whose names start with the rest of it
+    fn paths(&self, word: &str) -> Vec<String> {
+        let (dir_part, prefix) = match word.rfind('/') {
+            Some(idx) => (&word[..idx + 1], &word[idx + 1..]),
+            None => ("", word),
+        };
+        let dir = if dir_part.is_empty() { "." } else { dir_part };
+        let entries = match self.system.list_dir(self.session, dir) {
+            Ok(entries) => entries,
+            Err(_) => return Vec::new(),
+        };
+        entries
+            .into_iter()
+            .filter(|entry| starts_with_ignore_case(&entry.name, prefix))
+            .filter(|entry| !entry.name.starts_with('.') || prefix.starts_with('.'))
+            .map(|entry| {
+                let name = format!("{}{}", escape(dir_part), escape(&entry.name));
+                if entry.is_dir {
+                    format!("{}/", name)
+                } else {
+                    name
+                }
+            })
+            .collect()
+    }
+}
+
+impl Completer for ShellCompleter<'_> {
+    fn complete(&self, line: &str, cursor: usize) -> Completion {
+        let before: String = line.chars().take(cursor).collect();
+        let start = word_start(&before);
+        let word: String = before.chars().skip(start).collect();
+        let head: String = before.chars().take(start).collect();
+        let mut words: Vec<&str> = head.split_whitespace().collect();
+        // `sudo cmd ...` completes like `cmd ...`
+        if words.first() == Some(&"sudo") {
+            words.remove(0);
+        }
+
+        let unescaped = word.replace('\\', "");
+        let candidates: Vec<String> = match words.first() {
+            None => self.commands.clone(),
+            Some(command) if DISK_COMMANDS.contains(command) => self.disks(command),
+            Some(_) => return Completion {
+                start,
+                candidates: self.paths(&unescaped),
+            },
+        };
+        Completion {
+            start,
+            candidates: candidates
+                .into_iter()
+                .filter(|candidate| candidate.starts_with(&unescaped))
+                .collect(),
+        }
+    }
+}
+
+// Char index where the word under the cursor begins (backslash-escaped spaces stay in the word)
+fn word_start(before: &str) -> usize {
+    let chars: Vec<char> = before.chars().collect();
+    let mut start = chars.len();
+    while start > 0 {
+        let c = chars[start - 1];
+        if c.is_whitespace() && !(start >= 2 && chars[start - 2] == '\\') {
+            break;
+        }
+        start -= 1;
+    }
+    start
+}
+
+fn starts_with_ignore_case(name: &str, prefix: &str) -> bool {
+    name.to_lowercase().starts_with(&prefix.to_lowercase())
+}
+
+// Backslash-escape characters the tokenizer would otherwise split, expand or
+// take as a glob or a pipe
+fn escape(name: &str) -> String {
+    let mut out = String::new();
+    for c in name.chars() {
+        if c.is_whitespace() || matches!(c, '\'' | '"' | '\\' | '*' | '?' | '[' | '|' | '$') {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+// Longest prefix shared by all candidates
+pub fn common_prefix(candidates: &[String]) -> String {
+    let mut iter = candidates.iter();
+    let first = match iter.next() {
+        Some(first) => first.clone(),
+        None => return String::new(),
+    };
+    iter.fold(first, |prefix, candidate| {
+        prefix
+            .chars()
+            .zip(candidate.chars())
+            .take_while(|(a, b)| a == b)
+            .map(|(a, _)| a)
+            .collect()
+    })
+}
@@ -0,0 +1,412 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::io::{self, BufRead, Write};
+
+use crate::complete::Completer;
+use crate::history::History;
+use crate::readline::{Key, KeyDecoder};
+#[cfg(unix)]
+use crate::readline::{LineEditor, Outcome};
+
+// Everything the shell needs from a terminal. Reads return `None` at end of input.
+pub trait Console {
+    fn read_line(&mut self) -> io::Result<Option<String>>;
+
+    // Read a command line after showing `prompt`; terminals add line editing, history recall and completion
+    fn read_command(&mut self, prompt: &str, _history: &History, _completer: &dyn Completer) -> io::Result<Option<String>> {
+        self.write(prompt)?;
+        self.read_line()
+    }
+
+    // Read a line without echoing it back
+    fn read_password(&mut self) -> io::Result<Option<String>>;
+
+    fn write(&mut self, text: &str) -> io::Result<()>;
+
+    // Write an error message; unlike `write`, this always reaches the
+    // terminal, even from a command whose output is piped
+    fn write_error(&mut self, text: &str) -> io::Result<()> {
+        self.write(text)
+    }
+
+    fn clear(&mut self) -> io::Result<()>;
+
+    // Size of the terminal as (rows, columns), or `None` when there is no
+    // terminal to draw a full-screen program on
+    fn screen_size(&self) -> Option<(usize, usize)> {
+        None
+    }
+
+    // Switch to the alternate screen and key-at-a-time input, and back
+    fn enter_screen(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn leave_screen(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    // Read one key while in screen mode
+    fn read_key(&mut self) -> io::Result<Option<Key>> {
+        Ok(None)
+    }
+
+    // Lets `write!`/`writeln!` target a console directly
+    fn write_fmt(&mut self, args: fmt::Arguments<'_>) -> io::Result<()> {
+        match args.as_str() {
+            Some(text) => self.write(text),
+            None => self.write(&args.to_string()),
+        }
+    }
+
+    // Write `message` and read the reply with surrounding whitespace trimmed
+    fn prompt(&mut self, message: &str) -> io::Result<Option<String>> {
+        self.write(message)?;
+        Ok(self.read_line()?.map(|line| line.trim().to_string()))
+    }
+
+    // Like `prompt`, but without echo
+    fn prompt_password(&mut self, message: &str) -> io::Result<Option<String>> {
+        self.write(message)?;
+        let password = self.read_password()?;
+        Ok(password.map(|line| line.trim().to_string()))
+    }
+}
+
+// Last path component of a completion match, keeping a trailing `/`
+#[cfg(unix)]
+fn display_name(candidate: &str) -> &str {
+    let trimmed = candidate.trim_end_matches('/');
+    match trimmed.rfind('/') {
+        Some(idx) => &candidate[idx + 1..],
+        None => candidate,
+    }
+}
+
+// Console over the process' stdin and stdout
+#[derive(Debug, Default)]
+pub struct TtyConsole {
+    // Raw mode, held while a full-screen program runs
+    screen: Option<TermGuard>,
+    decoder: KeyDecoder,
+}
+
+impl TtyConsole {
+    pub fn new() -> TtyConsole {
+        TtyConsole::default()
+    }
+}
+
+impl Console for TtyConsole {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        let mut line = String::new();
+        if io::stdin().lock().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()))
+    }
+
+    #[cfg(unix)]
+    fn read_command(&mut self, prompt: &str, history: &History, completer: &dyn Completer) -> io::Result<Option<String>> {
+        let raw = TermGuard::raw();
+        if !raw.is_active() || !is_tty(libc::STDOUT_FILENO) {
+            drop(raw);
+            self.write(prompt)?;
+            return self.read_line();
+        }
+
+        let mut editor = LineEditor::new(history);
+        let mut decoder = KeyDecoder::new();
+        self.write(&editor.render(prompt))?;
+        loop {
+            let key = match read_key(&mut decoder)? {
+                Some(key) => key,
+                None => return Ok(None),
+            };
+            match editor.feed(key) {
+                Outcome::Pending => {}
+                Outcome::Submit(line) => {
+                    self.write(&editor.render(prompt))?;
+                    self.write("\r\n")?;
+                    return Ok(Some(line));
+                }
+                Outcome::Eof => {
+                    self.write("\r\n")?;
+                    return Ok(None);
+                }
+                Outcome::Interrupted => {
+                    self.write("^C\r\n")?;
+                    editor = LineEditor::new(history);
+                }
+                Outcome::ClearScreen => self.clear()?,
+                Outcome::Complete => {
+                    let matches = editor.complete(completer);
+                    if !matches.is_empty() {
+                        let names: Vec<&str> = matches.iter().map(|m| display_name(m)).collect();
+                        self.write(&format!("\r\n{}\r\n", names.join("  ")))?;
+                    }
+                }
+            }
+            self.write(&editor.render(prompt))?;
+        }
+    }
+
+    fn read_password(&mut self) -> io::Result<Option<String>> {
+        let echo = TermGuard::no_echo();
+        let line = self.read_line();
+        if echo.is_active() {
+            self.write("\n")?;
+        }
+        line
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        let mut stdout = io::stdout().lock();
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.write("\x1b[2J\x1b[1;1H")
+    }
+
+    #[cfg(unix)]
+    fn screen_size(&self) -> Option<(usize, usize)> {
+        if !is_tty(libc::STDIN_FILENO) || !is_tty(libc::STDOUT_FILENO) {
+            return None;
+        }
+        // SAFETY: TIOCGWINSZ fills in the winsize struct passed to it
+        unsafe {
+            let mut size: libc::winsize = std::mem::zeroed();
+            if libc::ioctl(libc::STDOUT_FILENO, libc::TIOCGWINSZ, &mut size) != 0 || size.ws_row == 0 {
+                return None;
+            }
+            Some((size.ws_row as usize, size.ws_col as usize))
+        }
+    }
+
+    #[cfg(unix)]
+    fn enter_screen(&mut self) -> io::Result<()> {
+        self.screen = Some(TermGuard::raw());
+        self.write("\x1b[?1049h\x1b[H")
+    }
+
+    #[cfg(unix)]
+    fn leave_screen(&mut self) -> io::Result<()> {
+        self.write("\x1b[?1049l")?;
+        self.screen = None;
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn read_key(&mut self) -> io::Result<Option<Key>> {
+        read_key(&mut self.decoder)
+    }
+}
+
+// Changes stdin's terminal mode for as long as it is alive (no-op when stdin is not a tty)
+#[derive(Default)]
+struct TermGuard {
+    #[cfg(unix)]
+    saved: Option<libc::termios>,
+}
+
+impl TermGuard {
+    // Turn echo off, for password prompts
+    fn no_echo() -> TermGuard {
+        #[cfg(unix)]
+        return TermGuard::set(|term| term.c_lflag &= !libc::ECHO);
+        #[cfg(not(unix))]
+        return TermGuard {};
+    }
+
+    // Byte-at-a-time input with no echo or signal keys, for the line editor
+    #[cfg(unix)]
+    fn raw() -> TermGuard {
+        TermGuard::set(|term| {
+            term.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+            term.c_iflag &= !(libc::IXON | libc::ICRNL);
+            term.c_cc[libc::VMIN] = 1;
+            term.c_cc[libc::VTIME] = 0;
+        })
+    }
+
+    #[cfg(unix)]
+    fn set(change: impl FnOnce(&mut libc::termios)) -> TermGuard {
+        // SAFETY: termios is plain data and the calls only touch stdin's terminal state
+        unsafe {
+            let mut term: libc::termios = std::mem::zeroed();
+            if libc::tcgetattr(libc::STDIN_FILENO, &mut term) != 0 {
+                return TermGuard { saved: None };
+            }
+            let saved = term;
+            change(&mut term);
+            if libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &term) != 0 {
+                return TermGuard { saved: None };
+            }
+            TermGuard { saved: Some(saved) }
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        #[cfg(unix)]
+        return self.saved.is_some();
+        #[cfg(not(unix))]
+        return false;
+    }
+}
+
+impl fmt::Debug for TermGuard {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("TermGuard").field("active", &self.is_active()).finish()
+    }
+}
+
+impl Drop for TermGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(saved) = self.saved {
+            // SAFETY: restores the attributes read in `set`
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &saved);
+            }
+        }
+    }
+}
+
+#[cfg(unix)]
+fn is_tty(fd: libc::c_int) -> bool {
+    // SAFETY: isatty only inspects the descriptor
+    unsafe { libc::isatty(fd) == 1 }
+}
+
+// Read one key from the raw terminal, or `None` at end of input
+#[cfg(unix)]
+fn read_key(decoder: &mut KeyDecoder) -> io::Result<Option<Key>> {
+    loop {
+        match read_byte(decoder.is_pending())? {
+            Some(byte) => {
+                if let Some(key) = decoder.feed(byte) {
+                    return Ok(Some(key));
+                }
+            }
+            // A lone Esc, or a sequence cut short
+            None if decoder.is_pending() => {
+                if let Some(key) = decoder.flush() {
+                    return Ok(Some(key));
+                }
+            }
+            None => return Ok(None),
+        }
+    }
+}
+
+// Read one byte from the raw terminal. While an escape sequence is pending,
+// give up after a short pause so that a lone Esc is still seen.
+#[cfg(unix)]
+fn read_byte(pending: bool) -> io::Result<Option<u8>> {
+    // SAFETY: poll and read operate on stdin with properly sized local buffers
+    unsafe {
+        if pending {
+            let mut fds = libc::pollfd {
+                fd: libc::STDIN_FILENO,
+                events: libc::POLLIN,
+                revents: 0,
+            };
+            if libc::poll(&mut fds, 1, 50) == 0 {
+                return Ok(None);
+            }
+        }
+        let mut byte = 0u8;
+        loop {
+            let n = libc::read(libc::STDIN_FILENO, &mut byte as *mut u8 as *mut libc::c_void, 1);
+            match n {
+                1 => return Ok(Some(byte)),
+                0 => return Ok(None),
+                _ => {
+                    let err = io::Error::last_os_error();
+                    if err.kind() != io::ErrorKind::Interrupted {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+    }
+}
+
+// Scripted console for tests: input is fed up front, output is captured
+#[derive(Debug, Default)]
+pub struct MemoryConsole {
+    input: VecDeque<String>,
+    output: String,
+    screen_size: Option<(usize, usize)>,
+    keys: VecDeque<Key>,
+}
+
+impl MemoryConsole {
+    // Build a console whose input is `script`, one line per read
+    pub fn new(script: &str) -> MemoryConsole {
+        let mut console = MemoryConsole::default();
+        console.push_input(script);
+        console
+    }
+
+    pub fn push_input(&mut self, script: &str) {
+        self.input.extend(script.lines().map(str::to_string));
+    }
+
+    // Pretend to be a terminal of this size, so full-screen programs run.
+    // They read keys from the input lines, decoded as terminal bytes: `\r` is
+    // Enter and control characters are Ctrl keys.
+    pub fn set_screen_size(&mut self, size: Option<(usize, usize)>) {
+        self.screen_size = size;
+    }
+
+    // Everything written so far
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    // Return the captured output and start over with an empty buffer
+    pub fn take_output(&mut self) -> String {
+        std::mem::take(&mut self.output)
+    }
+}
+
+impl Console for MemoryConsole {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        Ok(self.input.pop_front())
+    }
+
+    fn read_password(&mut self) -> io::Result<Option<String>> {
+        Ok(self.input.pop_front())
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.output.push_str(text);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn screen_size(&self) -> Option<(usize, usize)> {
+        self.screen_size
+    }
+
+    fn read_key(&mut self) -> io::Result<Option<Key>> {
+        while self.keys.is_empty() {
+            let line = match self.input.pop_front() {
+                Some(line) => line,
+                None => return Ok(None),
+            };
+            let mut decoder = KeyDecoder::new();
+            for byte in line.bytes() {
+                self.keys.extend(decoder.feed(byte));
+            }
+            self.keys.extend(decoder.flush());
+        }
+        Ok(self.keys.pop_front())
+    }
+}
@@ -0,0 +1,187 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read};
+use std::path::Path;
+
+use fatfs::{DateTime, Dir, FatType, FileAttributes, FileSystem, FormatVolumeOptions, FsOptions};
+
+use crate::error::{IoResultExt, Result, RnixError};
+use crate::fat::Geometry;
+use crate::path;
+use crate::sparse::punch_zeros;
+
+pub const DISK_IMAGE_SIZE: u64 = 128 * 1024 * 1024; // 128 MB
+pub const VOLUME_LABEL: &str = "RNIX";
+
+// Function to open a disk image, creating it with the given size if it
+// doesn't exist. Setting the length leaves the new file sparse on hosts that
+// support it.
+pub fn ocdi<P: AsRef<Path>>(path: P, size: u64) -> io::Result<File> {
+    let path = path.as_ref();
+    if path.exists() {
+        OpenOptions::new().read(true).write(true).open(path)
+    } else {
+        let file = OpenOptions::new().read(true).write(true).create(true).truncate(true).open(path)?;
+        file.set_len(size)?;
+        Ok(file)
+    }
+}
+
+// Function to check whether a disk image holds a readable FAT filesystem
+pub fn dformatq<P: AsRef<Path>>(path: P) -> io::Result<bool> {
+    let file = OpenOptions::new().read(true).write(true).open(path)?;
+    let options = FsOptions::new();
+    match FileSystem::new(file, options) {
+        Ok(_) => Ok(true),
+        Err(_) => Ok(false),
+    }
+}
+
+// Function to format a disk image as FAT32 unless it is already formatted
+pub fn dformat<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    if !dformatq(path)? {
+        let mut file = OpenOptions::new().read(true).write(true).open(path)?;
+        let mut volume_label_bytes = [b' '; 11];
+        let label_bytes = VOLUME_LABEL.as_bytes();
+        let len = label_bytes.len().min(11);
+        volume_label_bytes[..len].copy_from_slice(&label_bytes[..len]);
+        let format_options = FormatVolumeOptions::new()
+            .fat_type(FatType::Fat32)
+            .volume_label(volume_label_bytes);
+        fatfs::format_volume(&mut file, format_options)?;
+
+        // Formatting writes the FATs out whole, mostly zeros, which need not
+        // take up space
+        let mut sector = [0u8; 512];
+        file.read_exact(&mut sector)?;
+        if let Ok(geometry) = Geometry::parse(sector) {
+            let end = geometry.cluster_offset(geometry.root_cluster.max(2)) + geometry.cluster_bytes;
+            punch_zeros(&mut file, 0..end)?;
+        }
+    }
+    Ok(())
+}
+
+// Function to create and format a new disk image
+pub fn createdisk<P: AsRef<Path>>(disk_path: P) -> Result<()> {
+    let disk_path = disk_path.as_ref();
+    let file = ocdi(disk_path, DISK_IMAGE_SIZE)?;
+    drop(file); // Ensure the file is closed before formatting
+    dformat(disk_path)?;
+    Ok(())
+}
+
+// One file or directory found while walking a disk image
+#[derive(Debug, Clone)]
+pub struct DiskEntry {
+    // Absolute path inside the image
+    pub path: String,
+    // Directories below the starting point, so its children are at depth 0
+    pub depth: usize,
+    pub is_dir: bool,
+    pub len: u64,
+    pub attributes: FileAttributes,
+    pub modified: DateTime,
+}
+
+impl DiskEntry {
+    pub fn name(&self) -> &str {
+        self.path.rsplit('/').next().unwrap_or_default()
+    }
+
+    pub fn flags(&self) -> String {
+        attribute_flags(self.attributes)
+    }
+}
+
+// A byte count in 1K blocks (rounded up), or with `human` in the largest
+// unit that keeps it under 1024, to one decimal place below 10
+pub fn format_size(bytes: u64, human: bool) -> String {
+    if !human {
+        return bytes.div_ceil(1024).to_string();
+    }
+    if bytes < 1024 {
+        return bytes.to_string();
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < 3 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    let suffix = ["K", "M", "G", "T"][unit];
+    let tenths = (value * 10.0).ceil() / 10.0;
+    if tenths < 10.0 {
+        format!("{:.1}{}", tenths, suffix)
+    } else {
+        format!("{}{}", value.ceil(), suffix)
+    }
+}
+
+// Attribute letters as `drhsa`, with `-` for the ones not set
+pub fn attribute_flags(attributes: FileAttributes) -> String {
+    [
+        (FileAttributes::DIRECTORY, 'd'),
+        (FileAttributes::READ_ONLY, 'r'),
+        (FileAttributes::HIDDEN, 'h'),
+        (FileAttributes::SYSTEM, 's'),
+        (FileAttributes::ARCHIVE, 'a'),
+    ]
+    .iter()
+    .map(|&(flag, letter)| if attributes.contains(flag) { letter } else { '-' })
+    .collect()
+}
+
+// Function to open the filesystem on a disk image. Sectors are read from the
+// file as they are needed, so the image is never loaded whole.
+pub fn opendisk<P: AsRef<Path>>(path: P) -> Result<FileSystem<File>> {
+    let path = path.as_ref();
+    let file = File::open(path)?;
+    FileSystem::new(file, FsOptions::new()).map_err(|err| RnixError::CorruptImage(format!("{}: {}", path.display(), err)))
+}
+
+// Function to visit every file and directory below `start`, parents before their contents
+pub fn walkdisk(
+    fs: &FileSystem<File>,
+    start: &str,
+    visit: &mut dyn FnMut(&DiskEntry) -> Result<()>,
+) -> Result<()> {
+    let start = path::resolve("/", start);
+    let dir = fs.root_dir();
+    let dir = if start == "/" { dir } else { dir.open_dir(&start[1..]).at(&start)? };
+    walk(&dir, &start, 0, visit)
+}
+
+fn walk(dir: &Dir<'_, File>, prefix: &str, depth: usize, visit: &mut dyn FnMut(&DiskEntry) -> Result<()>) -> Result<()> {
+    for entry in dir.iter() {
+        let entry = entry.at(prefix)?;
+        let name = entry.file_name();
+        if name == "." || name == ".." {
+            continue;
+        }
+        let found = DiskEntry {
+            path: path::join(prefix, &name),
+            depth,
+            is_dir: entry.is_dir(),
+            len: entry.len(),
+            attributes: entry.attributes(),
+            modified: entry.modified(),
+        };
+        visit(&found)?;
+        if found.is_dir {
+            walk(&entry.to_dir(), &found.path, depth + 1, visit)?;
+        }
+    }
+    Ok(())
+}
+
+// Function to list every file and directory on a disk image
+pub fn displaydisk<P: AsRef<Path>>(path: P) -> Result<Vec<DiskEntry>> {
+    let fs = opendisk(path)?;
+    let mut entries = Vec::new();
+    walkdisk(&fs, "/", &mut |entry| {
+        entries.push(entry.clone());
+        Ok(())
+    })?;
+    Ok(entries)
+}
@@ -0,0 +1,437 @@
+// A line editor for files in the image, modelled on ed(1). Lines are
+// addressed by number (`3`, `.`, `$`, `+2`, `/re/`, `?re?`) or range (`1,5`,
+// `,`, `;`), and input for `a`, `i` and `c` ends with a line holding a lone `.`.
+// Input is read one line at a time through the console, so the shell keeps
+// working afterwards.
+
+use regex::Regex;
+
+use crate::console::Console;
+use crate::error::{Result, RnixError};
+use crate::system::{Session, System};
+
+// Summary printed by `h`
+pub const HELP: &str = "\
+Commands (addresses default to the current line; `,` means every line):
+  [n]            move to line n and print it (Enter prints the next line)
+  [a,b]p  [a,b]n print lines, n with line numbers
+  [n]a  [n]i     append after / insert before line n; end input with `.`
+  [a,b]c         change lines; end input with `.`
+  [a,b]d         delete lines
+  [a,b]s/re/rep/[g]  substitute (`&` is the match, \\1..\\9 groups)
+  =              print the number of the addressed line ($ by default)
+  u              undo the last change (u again redoes it)
+  w [file]  wq   write the buffer, then quit with wq
+  q  Q           quit (q warns once about unsaved changes), quit regardless
+";
+
+// How a line ended the command loop
+enum Step {
+    Continue,
+    Quit,
+}
+
+pub struct Ed<'a> {
+    system: &'a System,
+    session: &'a Session,
+    console: &'a mut dyn Console,
+    path: String,
+    lines: Vec<String>,
+    // 1-based; 0 only when the buffer is empty
+    current: usize,
+    dirty: bool,
+    // Set after `q` refused to drop changes, so a second `q` goes through
+    warned: bool,
+    undo: Option<(Vec<String>, usize)>,
+    last_regex: Option<Regex>,
+}
+
+impl<'a> Ed<'a> {
+    // Load `path` into a new buffer. A missing file gives an empty buffer
+    // that `w` will create.
+    pub fn open(system: &'a System, session: &'a Session, console: &'a mut dyn Console, path: &str) -> Result<Ed<'a>> {
+        let mut lines = Vec::new();
+        if system.is_file(session, path) {
+            let contents = system.read_file(session, path)?;
+            writeln!(console, "{}", contents.len())?;
+            lines = String::from_utf8_lossy(&contents).lines().map(str::to_string).collect();
+        } else if system.is_dir(session, path) {
+            return Err(RnixError::InvalidArgument(format!("{}: Is a directory", path)));
+        } else {
+            writeln!(console, "{}: No such file", path)?;
+        }
+        Ok(Ed {
+            system,
+            session,
+            console,
+            path: path.to_string(),
+            current: lines.len(),
+            lines,
+            dirty: false,
+            warned: false,
+            undo: None,
+            last_regex: None,
+        })
+    }
+
+    // Read and run commands until `q`, `Q`, `wq` or the end of input
+    pub fn run(&mut self) -> Result<()> {
+        while let Some(line) = self.console.read_line()? {
+            let warned = self.warned;
+            match self.command(&line) {
+                Ok(Step::Continue) => {}
+                Ok(Step::Quit) => return Ok(()),
+                Err(RnixError::Io(err)) => return Err(RnixError::Io(err)),
+                Err(err) => writeln!(self.console, "? {}", err)?,
+            }
+            // The warning only holds for the command right after it
+            if warned {
+                self.warned = false;
+            }
+        }
+        Ok(())
+    }
+
+    fn command(&mut self, line: &str) -> Result<Step> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut pos = 0;
+        let range = self.range(&chars, &mut pos)?;
+        let rest: String = chars[pos..].iter().collect();
+        let mut rest_chars = rest.chars();
+        let name = rest_chars.next();
+        let arg = rest_chars.as_str();
+
+        match name {
+            None => {
+                let line = match range {
+                    Some((_, end)) => end,
+                    None => self.current + 1,
+                };
+                self.check(line)?;
+                self.current = line;
+                self.print(line, line, false)?;
+            }
+            Some(c @ ('p' | 'n')) => {
+                let (start, end) = self.lines_or_current(range)?;
+                self.print(start, end, c == 'n')?;
+                self.current = end;
+            }
+            Some(c @ ('a' | 'i')) => {
+                let at = range.map_or(self.current, |(_, end)| end);
+                if at > self.lines.len() {
+                    return Err(invalid_address());
+                }
+                let input = self.input()?;
+                self.snapshot();
+                // `0i` and `1i` both insert at the top
+                let index = if c == 'a' { at } else { at.saturating_sub(1) };
+                self.insert(index, input);
+            }
+            Some('c') => {
+                let (start, end) = self.lines_or_current(range)?;
+                let input = self.input()?;
+                self.snapshot();
+                self.lines.drain(start - 1..end);
+                self.insert(start - 1, input);
+            }
+            Some('d') => {
+                let (start, end) = self.lines_or_current(range)?;
+                self.snapshot();
+                self.lines.drain(start - 1..end);
+                self.current = if start <= self.lines.len() { start } else { self.lines.len() };
+                self.dirty = true;
+            }
+            Some('s') => {
+                let (start, end) = self.lines_or_current(range)?;
+                self.substitute(start, end, arg)?;
+            }
+            Some('=') => {
+                let line = range.map_or(self.lines.len(), |(_, end)| end);
+                writeln!(self.console, "{}", line)?;
+            }
+            Some('u') => match self.undo.take() {
+                Some((lines, current)) => {
+                    let replaced = std::mem::replace(&mut self.lines, lines);
+                    self.undo = Some((replaced, self.current));
+                    self.current = current;
+                    self.dirty = true;
+                }
+                None => return Err(RnixError::InvalidArgument("nothing to undo".to_string())),
+            },
+            Some('w') => {
+                let (quit, file) = match arg.strip_prefix('q') {
+                    Some(file) => (true, file),
+                    None => (false, arg),
+                };
+                self.write(file.trim())?;
+                if quit {
+                    return Ok(Step::Quit);
+                }
+            }
+            Some('q') if arg.is_empty() => {
+                if self.dirty && !self.warned {
+                    self.warned = true;
+                    return Err(RnixError::InvalidArgument(
+                        "warning: buffer modified (w to save, q again to discard)".to_string(),
+                    ));
+                }
+                return Ok(Step::Quit);
+            }
+            Some('Q') if arg.is_empty() => return Ok(Step::Quit),
+            Some('h') if arg.is_empty() => self.console.write(HELP)?,
+            Some(c) => return Err(RnixError::InvalidArgument(format!("unknown command `{}'", c))),
+        }
+        Ok(Step::Continue)
+    }
+
+    // Parse the addresses at the start of a command, if any
+    fn range(&mut self, chars: &[char], pos: &mut usize) -> Result<Option<(usize, usize)>> {
+        let first = self.address(chars, pos)?;
+        match chars.get(*pos) {
+            Some(&separator @ (',' | ';')) => {
+                *pos += 1;
+                let start = match (first, separator) {
+                    (Some(line), _) => line,
+                    (None, ',') => 1,
+                    (None, _) => self.current,
+                };
+                if separator == ';' {
+                    self.check(start)?;
+                    self.current = start;
+                }
+                let end = self.address(chars, pos)?;
+                let end = match end {
+                    Some(end) => end,
+                    None if first.is_some() => start,
+                    None => self.lines.len(),
+                };
+                if start > end {
+                    return Err(invalid_address());
+                }
+                Ok(Some((start, end)))
+            }
+            _ => Ok(first.map(|line| (line, line))),
+        }
+    }
+
+    // Parse one address: a base (`n`, `.`, `$`, `/re/`, `?re?`) followed by
+    // any number of `+n`/`-n` offsets
+    fn address(&mut self, chars: &[char], pos: &mut usize) -> Result<Option<usize>> {
+        let mut line = match chars.get(*pos) {
+            Some(c) if c.is_ascii_digit() => Some(number(chars, pos).unwrap_or(0) as isize),
+            Some('.') => {
+                *pos += 1;
+                Some(self.current as isize)
+            }
+            Some('$') => {
+                *pos += 1;
+                Some(self.lines.len() as isize)
+            }
+            Some(&delimiter @ ('/' | '?')) => {
+                *pos += 1;
+                let pattern = delimited(chars, pos, delimiter);
+                Some(self.search(&pattern, delimiter == '/')? as isize)
+            }
+            _ => None,
+        };
+        while let Some(&sign @ ('+' | '-')) = chars.get(*pos) {
+            *pos += 1;
+            let offset = number(chars, pos).unwrap_or(1) as isize;
+            let base = line.unwrap_or(self.current as isize);
+            line = Some(if sign == '+' { base + offset } else { base - offset });
+        }
+        match line {
+            Some(line) if line < 0 || line as usize > self.lines.len() => Err(invalid_address()),
+            Some(line) => Ok(Some(line as usize)),
+            None => Ok(None),
+        }
+    }
+
+    // Find the next (or previous) line matching `pattern`, wrapping around
+    fn search(&mut self, pattern: &str, forward: bool) -> Result<usize> {
+        let regex = self.regex(pattern)?;
+        let count = self.lines.len();
+        for step in 1..=count {
+            let line = if forward {
+                (self.current + step - 1) % count + 1
+            } else {
+                (self.current + count * 2 - step - 1) % count + 1
+            };
+            if regex.is_match(&self.lines[line - 1]) {
+                return Ok(line);
+            }
+        }
+        Err(RnixError::InvalidArgument("no match".to_string()))
+    }
+
+    // Compile `pattern`, or reuse the previous one when it is empty
+    fn regex(&mut self, pattern: &str) -> Result<Regex> {
+        if pattern.is_empty() {
+            return self
+                .last_regex
+                .clone()
+                .ok_or_else(|| RnixError::InvalidArgument("no previous pattern".to_string()));
+        }
+        let regex = Regex::new(pattern).map_err(|err| RnixError::InvalidArgument(format!("bad pattern: {}", err)))?;
+        self.last_regex = Some(regex.clone());
+        Ok(regex)
+    }
+
+    fn substitute(&mut self, start: usize, end: usize, arg: &str) -> Result<()> {
+        let chars: Vec<char> = arg.chars().collect();
+        let delimiter = match chars.first() {
+            Some(c) if !c.is_alphanumeric() && !c.is_whitespace() => *c,
+            _ => return Err(RnixError::InvalidArgument("usage: s/re/replacement/[g]".to_string())),
+        };
+        let mut pos = 1;
+        let pattern = delimited(&chars, &mut pos, delimiter);
+        let replacement = replacement(&delimited(&chars, &mut pos, delimiter));
+        let flags: String = chars[pos..].iter().collect();
+        let global = match flags.as_str() {
+            "" => false,
+            "g" => true,
+            _ => return Err(RnixError::InvalidArgument(format!("unknown flags `{}'", flags))),
+        };
+        let regex = self.regex(&pattern)?;
+
+        let mut changed = self.lines.clone();
+        let mut last = None;
+        for (index, line) in changed.iter_mut().enumerate().take(end).skip(start - 1) {
+            if regex.is_match(line) {
+                let limit = if global { 0 } else { 1 };
+                *line = regex.replacen(line, limit, replacement.as_str()).into_owned();
+                last = Some(index + 1);
+            }
+        }
+        let last = last.ok_or_else(|| RnixError::InvalidArgument("no match".to_string()))?;
+        self.snapshot();
+        self.lines = changed;
+        self.current = last;
+        self.dirty = true;
+        Ok(())
+    }
+
+    fn write(&mut self, file: &str) -> Result<()> {
+        if !file.is_empty() {
+            self.path = file.to_string();
+        }
+        let mut contents = self.lines.join("\n");
+        if !self.lines.is_empty() {
+            contents.push('\n');
+        }
+        if !self.system.is_file(self.session, &self.path) {
+            self.system.touch(self.session, &self.path)?;
+        }
+        self.system.write_file(self.session, &self.path, contents.as_bytes())?;
+        self.dirty = false;
+        writeln!(self.console, "{}", contents.len())?;
+        Ok(())
+    }
+
+    // Read input lines up to a lone `.` or the end of input
+    fn input(&mut self) -> Result<Vec<String>> {
+        let mut input = Vec::new();
+        while let Some(line) = self.console.read_line()? {
+            if line == "." {
+                break;
+            }
+            input.push(line);
+        }
+        Ok(input)
+    }
+
+    // Put `input` before the line at `index` (0-based) and make its last line current
+    fn insert(&mut self, index: usize, input: Vec<String>) {
+        let count = input.len();
+        self.lines.splice(index..index, input);
+        self.current = if count == 0 { index.min(self.lines.len()) } else { index + count };
+        self.dirty = true;
+    }
+
+    fn print(&mut self, start: usize, end: usize, numbered: bool) -> Result<()> {
+        for line in start..=end {
+            if numbered {
+                writeln!(self.console, "{}\t{}", line, self.lines[line - 1])?;
+            } else {
+                writeln!(self.console, "{}", self.lines[line - 1])?;
+            }
+        }
+        Ok(())
+    }
+
+    fn lines_or_current(&self, range: Option<(usize, usize)>) -> Result<(usize, usize)> {
+        let (start, end) = range.unwrap_or((self.current, self.current));
+        self.check(start)?;
+        self.check(end)?;
+        Ok((start, end))
+    }
+
+    // Fail unless `line` names a line in the buffer
+    fn check(&self, line: usize) -> Result<()> {
+        if line == 0 || line > self.lines.len() {
+            return Err(invalid_address());
+        }
+        Ok(())
+    }
+
+    fn snapshot(&mut self) {
+        self.undo = Some((self.lines.clone(), self.current));
+    }
+}
+
+fn invalid_address() -> RnixError {
+    RnixError::InvalidArgument("invalid address".to_string())
+}
+
+fn number(chars: &[char], pos: &mut usize) -> Option<usize> {
+    let start = *pos;
+    while chars.get(*pos).is_some_and(char::is_ascii_digit) {
+        *pos += 1;
+    }
+    chars[start..*pos].iter().collect::<String>().parse().ok()
+}
+
+// Read up to an unescaped `delimiter` (or the end), consuming the delimiter.
+// `\delimiter` stands for the delimiter itself; other escapes are kept.
+pub(crate) fn delimited(chars: &[char], pos: &mut usize, delimiter: char) -> String {
+    let mut out = String::new();
+    while let Some(&c) = chars.get(*pos) {
+        *pos += 1;
+        match c {
+            c if c == delimiter => break,
+            '\\' if chars.get(*pos) == Some(&delimiter) => {
+                out.push(delimiter);
+                *pos += 1;
+            }
+            '\\' => {
+                out.push('\\');
+                if let Some(&next) = chars.get(*pos) {
+                    out.push(next);
+                    *pos += 1;
+                }
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Translate an ed replacement (`&`, `\1`, `\&`) into the regex crate's syntax
+pub(crate) fn replacement(text: &str) -> String {
+    let mut out = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => out.push_str("${0}"),
+            '$' => out.push_str("$$"),
+            '\\' => match chars.next() {
+                Some(digit @ '0'..='9') => out.push_str(&format!("${{{}}}", digit)),
+                Some('$') => out.push_str("$$"),
+                Some(c) => out.push(c),
+                None => out.push('\\'),
+            },
+            c => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+
+use crate::error::{Result, RnixError};
+
+// Prompt used when PS1 is unset: the classic `user(rnix) > `
+pub const DEFAULT_PS1: &str = "\\u(rnix) > ";
+
+// Search path for programs kept on the root disk
+pub const DEFAULT_PATH: &str = "/internal/bin";
+
+// A session's variables, kept sorted by name
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Environment {
+    vars: BTreeMap<String, String>,
+}
+
+impl Environment {
+    pub fn new() -> Environment {
+        Environment::default()
+    }
+
+    // The variables every login starts with
+    pub fn standard(user: &str, home: &str) -> Environment {
+        let mut env = Environment::new();
+        for (name, value) in [
+            ("USER", user),
+            ("HOME", home),
+            ("PWD", home),
+            ("PATH", DEFAULT_PATH),
+            ("PS1", DEFAULT_PS1),
+        ] {
+            env.vars.insert(name.to_string(), value.to_string());
+        }
+        env
+    }
+
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.vars.get(name).map(String::as_str)
+    }
+
+    pub fn set(&mut self, name: &str, value: &str) -> Result<()> {
+        if !is_valid_name(name) {
+            return Err(RnixError::InvalidArgument(format!("`{}': not a valid identifier", name)));
+        }
+        self.vars.insert(name.to_string(), value.to_string());
+        Ok(())
+    }
+
+    pub fn unset(&mut self, name: &str) -> bool {
+        self.vars.remove(name).is_some()
+    }
+
+    // Variables sorted by name
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.vars.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+}
+
+// Variable names are a letter or underscore followed by letters, digits and underscores
+pub fn is_valid_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+// What the PS1 escapes refer to
+pub struct PromptInfo<'a> {
+    pub user: &'a str,
+    pub cwd: &'a str,
+    pub home: &'a str,
+    pub disk: &'a str,
+    pub status: i32,
+}
+
+// Function to expand the PS1 escapes: \u user, \w cwd (with ~ for home), \W last
+// component of cwd, \D disk holding cwd, \? last exit status, \h host name,
+// \$ `#` for root and `$` otherwise, \e escape (for colours), \n newline, \\ backslash.
+pub fn expand_prompt(ps1: &str, info: &PromptInfo<'_>) -> String {
+    let mut out = String::new();
+    let mut chars = ps1.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('u') => out.push_str(info.user),
+            Some('w') => out.push_str(&tilde(info.cwd, info.home)),
+            Some('W') => {
+                let base = info.cwd.rsplit('/').find(|part| !part.is_empty()).unwrap_or("/");
+                out.push_str(if info.cwd == info.home && info.home != "/" { "~" } else { base });
+            }
+            Some('D') => out.push_str(info.disk),
+            Some('?') => out.push_str(&info.status.to_string()),
+            Some('h') => out.push_str("rnix"),
+            Some('$') => out.push(if info.user == "root" { '#' } else { '$' }),
+            Some('e') => out.push('\x1b'),
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some(other) => {
+                out.push('\\');
+                out.push(other);
+            }
+            None => out.push('\\'),
+        }
+    }
+    out
+}
+
+// Abbreviate the home directory to `~`
+fn tilde(cwd: &str, home: &str) -> String {
+    if home == "/" {
+        return cwd.to_string();
+    }
+    match cwd.strip_prefix(home) {
+        Some("") => "~".to_string(),
+        Some(rest) if rest.starts_with('/') => format!("~{}", rest),
+        _ => cwd.to_string(),
+    }
+}
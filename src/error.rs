@@ -0,0 +1,117 @@
+use std::fmt;
+use std::io;
+
+// Errors returned by every rnix operation
+#[derive(Debug)]
+pub enum RnixError {
+    NotFound(String),
+    AlreadyExists(String),
+    NotADirectory(String),
+    IsADirectory(String),
+    DirectoryNotEmpty(String),
+    PermissionDenied(String),
+    NotMounted(String),
+    AlreadyMounted(String),
+    // A write to a disk mounted read-only
+    ReadOnly(String),
+    AuthFailed,
+    CorruptImage(String),
+    NoSpace,
+    InvalidArgument(String),
+    Usage(String),
+    UnknownCommand(String),
+    Crypto(String),
+    Io(io::Error),
+    // A failure the command has already described on the console; carries the exit status
+    Reported(i32),
+}
+
+pub type Result<T> = std::result::Result<T, RnixError>;
+
+impl RnixError {
+    // Exit status a shell reports for this error, following the usual sh conventions
+    pub fn status(&self) -> i32 {
+        match self {
+            RnixError::Usage(_) => 2,
+            RnixError::PermissionDenied(_) => 126,
+            RnixError::UnknownCommand(_) => 127,
+            RnixError::Reported(status) => *status,
+            _ => 1,
+        }
+    }
+}
+
+impl fmt::Display for RnixError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RnixError::NotFound(path) => write!(f, "{}: No such file or directory", path),
+            RnixError::AlreadyExists(path) => write!(f, "{}: File exists", path),
+            RnixError::NotADirectory(path) => write!(f, "{}: Not a directory", path),
+            RnixError::IsADirectory(path) => write!(f, "{}: Is a directory", path),
+            RnixError::DirectoryNotEmpty(path) => write!(f, "{}: Directory not empty", path),
+            RnixError::PermissionDenied(msg) => write!(f, "Permission denied: {}", msg),
+            RnixError::NotMounted(disk) => write!(f, "{}: not mounted", disk),
+            RnixError::AlreadyMounted(disk) => write!(f, "{}: already mounted", disk),
+            RnixError::ReadOnly(path) => write!(f, "{}: read-only filesystem", path),
+            RnixError::AuthFailed => write!(f, "Authentication failed"),
+            RnixError::CorruptImage(msg) => write!(f, "corrupt disk image: {}", msg),
+            RnixError::NoSpace => write!(f, "No space left on device"),
+            RnixError::InvalidArgument(msg) => write!(f, "{}", msg),
+            RnixError::Usage(usage) => write!(f, "usage: {}", usage),
+            RnixError::UnknownCommand(cmd) => write!(f, "{}: command not found", cmd),
+            RnixError::Crypto(msg) => write!(f, "crypto error: {}", msg),
+            RnixError::Io(err) => write!(f, "{}", err),
+            RnixError::Reported(status) => write!(f, "exit status {}", status),
+        }
+    }
+}
+
+impl std::error::Error for RnixError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RnixError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for RnixError {
+    fn from(err: io::Error) -> Self {
+        RnixError::Io(err)
+    }
+}
+
+impl From<bcrypt::BcryptError> for RnixError {
+    fn from(err: bcrypt::BcryptError) -> Self {
+        RnixError::Crypto(err.to_string())
+    }
+}
+
+// Function to classify an io::Error coming out of fatfs for the entry at `path`.
+// fatfs reports most conditions as ErrorKind::Other, so the message is inspected too.
+pub fn from_io(err: io::Error, path: &str) -> RnixError {
+    match err.kind() {
+        io::ErrorKind::NotFound => return RnixError::NotFound(path.to_string()),
+        io::ErrorKind::AlreadyExists => return RnixError::AlreadyExists(path.to_string()),
+        io::ErrorKind::PermissionDenied => return RnixError::PermissionDenied(path.to_string()),
+        _ => {}
+    }
+    match err.to_string().as_str() {
+        "Is a directory" => RnixError::IsADirectory(path.to_string()),
+        "Not a directory" => RnixError::NotADirectory(path.to_string()),
+        "Directory not empty" => RnixError::DirectoryNotEmpty(path.to_string()),
+        "No space left on device" => RnixError::NoSpace,
+        _ => RnixError::Io(err),
+    }
+}
+
+// Attach the path an io::Result was operating on when converting it
+pub trait IoResultExt<T> {
+    fn at(self, path: &str) -> Result<T>;
+}
+
+impl<T> IoResultExt<T> for io::Result<T> {
+    fn at(self, path: &str) -> Result<T> {
+        self.map_err(|err| from_io(err, path))
+    }
+}
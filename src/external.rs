@@ -0,0 +1,133 @@
+// Editing image files with an editor on the host. The file is copied to a
+// private temporary file, the editor runs on that copy, and the result is
+// written back into the image only if it changed and the image copy did not
+// change in the meantime.
+
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+#[cfg(unix)]
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::error::{IoResultExt, Result, RnixError};
+use crate::system::{Session, System};
+use crate::tokenizer::tokenize;
+
+// Editor used when neither the session nor the host names one
+pub const DEFAULT_EDITOR: &str = "vi";
+
+// How an external edit ended
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Unchanged,
+    // Number of bytes written back into the image
+    Saved(usize),
+}
+
+// Function to pick the editor command: `$EDITOR` from the session, then
+// `$VISUAL` and `$EDITOR` from the host, then `vi`. Quotes in the value are
+// honoured, so `EDITOR="code --wait"` works.
+pub fn editor_command(session: &Session) -> Result<Vec<String>> {
+    let value = session
+        .env()
+        .get("EDITOR")
+        .map(str::to_string)
+        .or_else(|| std::env::var("VISUAL").ok())
+        .or_else(|| std::env::var("EDITOR").ok())
+        .filter(|value| !value.trim().is_empty())
+        .unwrap_or_else(|| DEFAULT_EDITOR.to_string());
+    tokenize(&value)
+}
+
+// Function to run `command` on `file` and wait for it to exit
+pub fn launch(command: &[String], file: &Path) -> Result<()> {
+    let (program, args) = command
+        .split_first()
+        .ok_or_else(|| RnixError::InvalidArgument("no editor set".to_string()))?;
+    let status = process::Command::new(program).args(args).arg(file).status().at(program)?;
+    if !status.success() {
+        return Err(RnixError::InvalidArgument(format!("{} exited with {}; nothing saved", program, status)));
+    }
+    Ok(())
+}
+
+// Function to edit `path` through a host copy. `run_editor` is handed the
+// copy's path and returns once editing is done. A missing file starts out empty.
+pub fn edit(
+    system: &System,
+    session: &Session,
+    path: &str,
+    run_editor: impl FnOnce(&Path) -> Result<()>,
+) -> Result<Outcome> {
+    if system.is_dir(session, path) {
+        return Err(RnixError::InvalidArgument(format!("{}: Is a directory", path)));
+    }
+    let original = read(system, session, path)?;
+    let mut copy = TempCopy::create(path, original.as_deref().unwrap_or_default())?;
+
+    run_editor(&copy.path)?;
+
+    let edited = fs::read(&copy.path).at(&copy.path.to_string_lossy())?;
+    if original.as_deref() == Some(edited.as_slice()) || (original.is_none() && edited.is_empty()) {
+        return Ok(Outcome::Unchanged);
+    }
+    if read(system, session, path)? != original {
+        // Keep the user's work rather than overwrite someone else's
+        copy.keep = true;
+        return Err(RnixError::InvalidArgument(format!(
+            "{}: changed in the image while it was being edited; your version is in {}",
+            path,
+            copy.path.display()
+        )));
+    }
+    if original.is_none() {
+        system.touch(session, path)?;
+    }
+    system.write_file(session, path, &edited)?;
+    Ok(Outcome::Saved(edited.len()))
+}
+
+fn read(system: &System, session: &Session, path: &str) -> Result<Option<Vec<u8>>> {
+    if system.is_file(session, path) {
+        Ok(Some(system.read_file(session, path)?))
+    } else {
+        Ok(None)
+    }
+}
+
+// A host file only the current user can read, removed when dropped unless kept
+struct TempCopy {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl TempCopy {
+    // The file name ends with the image file's, so editors pick the right syntax
+    fn create(image_path: &str, contents: &[u8]) -> Result<TempCopy> {
+        let name = image_path.rsplit('/').next().unwrap_or("file");
+        let path = std::env::temp_dir().join(format!(
+            "rnix-{}-{:08x}-{}",
+            process::id(),
+            rand::random::<u32>(),
+            name
+        ));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let display = path.to_string_lossy().to_string();
+        let mut file = options.open(&path).at(&display)?;
+        let copy = TempCopy { path, keep: false };
+        file.write_all(contents).at(&display)?;
+        Ok(copy)
+    }
+}
+
+impl Drop for TempCopy {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
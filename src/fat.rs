@@ -0,0 +1,414 @@
+// Raw access to the on-disk structures of a FAT volume, for what fatfs
+// doesn't offer: fsck checks the structures fatfs trusts, and attributes and
+// timestamps are set by patching directory entries in place.
+
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use crate::error::{IoResultExt, Result, RnixError};
+
+pub(crate) const ENTRY_SIZE: usize = 32;
+pub(crate) const DELETED: u8 = 0xE5;
+pub(crate) const ATTR_VOLUME_ID: u8 = 0x08;
+pub(crate) const ATTR_DIRECTORY: u8 = 0x10;
+pub(crate) const ATTR_LFN: u8 = 0x0F;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum FatKind {
+    Fat12,
+    Fat16,
+    Fat32,
+}
+
+// Where everything is on the volume, from the boot sector
+pub(crate) struct Geometry {
+    pub(crate) sector: [u8; 512],
+    pub(crate) bytes_per_sector: u64,
+    pub(crate) cluster_bytes: u64,
+    pub(crate) reserved: u64,
+    pub(crate) fats: u64,
+    pub(crate) fat_sectors: u64,
+    pub(crate) root_entries: u64,
+    pub(crate) total_sectors: u64,
+    pub(crate) media: u8,
+    pub(crate) kind: FatKind,
+    pub(crate) root_cluster: u32,
+    pub(crate) fs_info: u64,
+    pub(crate) backup_boot: u64,
+    pub(crate) clusters: u32,
+}
+
+impl Geometry {
+    pub(crate) fn parse(sector: [u8; 512]) -> std::result::Result<Geometry, String> {
+        let u16_at = |at: usize| u16::from_le_bytes([sector[at], sector[at + 1]]) as u64;
+        let u32_at = |at: usize| u32::from_le_bytes([sector[at], sector[at + 1], sector[at + 2], sector[at + 3]]);
+        if sector[510..512] != [0x55, 0xAA] {
+            return Err("boot sector signature is missing".to_string());
+        }
+        let bytes_per_sector = u16_at(11);
+        if ![512, 1024, 2048, 4096].contains(&bytes_per_sector) {
+            return Err(format!("invalid sector size {}", bytes_per_sector));
+        }
+        let sectors_per_cluster = sector[13] as u64;
+        if !sectors_per_cluster.is_power_of_two() {
+            return Err(format!("invalid cluster size of {} sectors", sectors_per_cluster));
+        }
+        let reserved = u16_at(14);
+        let fats = sector[16] as u64;
+        if reserved == 0 || fats == 0 {
+            return Err("no reserved sectors or no FATs".to_string());
+        }
+        let root_entries = u16_at(17);
+        let total_sectors = match u16_at(19) {
+            0 => u32_at(32) as u64,
+            total => total,
+        };
+        let fat32 = u16_at(22) == 0;
+        let fat_sectors = if fat32 { u32_at(36) as u64 } else { u16_at(22) };
+        if fat_sectors == 0 {
+            return Err("FAT size is zero".to_string());
+        }
+        let root_sectors = (root_entries * ENTRY_SIZE as u64).div_ceil(bytes_per_sector);
+        let data_start = reserved + fats * fat_sectors + root_sectors;
+        if data_start >= total_sectors {
+            return Err("no room for data after the FATs".to_string());
+        }
+        let clusters = ((total_sectors - data_start) / sectors_per_cluster) as u32;
+        let kind = match clusters {
+            0..=4084 => FatKind::Fat12,
+            4085..=65524 => FatKind::Fat16,
+            _ => FatKind::Fat32,
+        };
+        if (kind == FatKind::Fat32) != fat32 {
+            return Err(format!("{} clusters do not match the FAT layout", clusters));
+        }
+        let fat_bits = match kind {
+            FatKind::Fat12 => 12,
+            FatKind::Fat16 => 16,
+            FatKind::Fat32 => 32,
+        };
+        if (clusters as u64 + 2) * fat_bits > fat_sectors * bytes_per_sector * 8 {
+            return Err(format!("FAT is too small for {} clusters", clusters));
+        }
+        let geometry = Geometry {
+            sector,
+            bytes_per_sector,
+            cluster_bytes: bytes_per_sector * sectors_per_cluster,
+            reserved,
+            fats,
+            fat_sectors,
+            root_entries,
+            total_sectors,
+            media: sector[21],
+            kind,
+            root_cluster: if fat32 { u32_at(44) } else { 0 },
+            fs_info: if fat32 { u16_at(48) } else { 0 },
+            backup_boot: if fat32 { u16_at(50) } else { 0 },
+            clusters,
+        };
+        if fat32 && !geometry.in_range(geometry.root_cluster) {
+            return Err(format!("root directory cluster {} is outside the volume", geometry.root_cluster));
+        }
+        Ok(geometry)
+    }
+
+    pub(crate) fn fat_offset(&self, copy: u64) -> u64 {
+        (self.reserved + copy * self.fat_sectors) * self.bytes_per_sector
+    }
+
+    pub(crate) fn fat_bytes(&self) -> usize {
+        (self.fat_sectors * self.bytes_per_sector) as usize
+    }
+
+    // Start of the fixed root directory of FAT12 and FAT16
+    pub(crate) fn root_offset(&self) -> u64 {
+        self.fat_offset(self.fats)
+    }
+
+    pub(crate) fn cluster_offset(&self, cluster: u32) -> u64 {
+        self.root_offset() + self.root_entries * ENTRY_SIZE as u64 + (cluster as u64 - 2) * self.cluster_bytes
+    }
+
+    // Offset of the boot sector byte fatfs keeps its dirty flag in
+    pub(crate) fn flags_offset(&self) -> usize {
+        if self.kind == FatKind::Fat32 {
+            0x41
+        } else {
+            0x25
+        }
+    }
+
+    pub(crate) fn in_range(&self, cluster: u32) -> bool {
+        cluster >= 2 && cluster < self.clusters + 2
+    }
+
+    pub(crate) fn end_of_chain(&self) -> u32 {
+        match self.kind {
+            FatKind::Fat12 => 0xFFF,
+            FatKind::Fat16 => 0xFFFF,
+            FatKind::Fat32 => 0x0FFF_FFFF,
+        }
+    }
+
+    pub(crate) fn is_end(&self, value: u32) -> bool {
+        value >= self.end_of_chain() - 7
+    }
+
+    pub(crate) fn is_bad(&self, value: u32) -> bool {
+        value == self.end_of_chain() - 8
+    }
+}
+
+// A FAT held as the bytes of the table
+pub(crate) struct Fat {
+    pub(crate) kind: FatKind,
+    pub(crate) raw: Vec<u8>,
+}
+
+impl Fat {
+    pub(crate) fn get(&self, cluster: u32) -> u32 {
+        let n = cluster as usize;
+        match self.kind {
+            FatKind::Fat12 => {
+                let at = n + n / 2;
+                let value = u16::from_le_bytes([self.raw[at], self.raw[at + 1]]) as u32;
+                if n % 2 == 1 {
+                    value >> 4
+                } else {
+                    value & 0xFFF
+                }
+            }
+            FatKind::Fat16 => u16::from_le_bytes([self.raw[2 * n], self.raw[2 * n + 1]]) as u32,
+            FatKind::Fat32 => {
+                let bytes = [self.raw[4 * n], self.raw[4 * n + 1], self.raw[4 * n + 2], self.raw[4 * n + 3]];
+                u32::from_le_bytes(bytes) & 0x0FFF_FFFF
+            }
+        }
+    }
+
+    pub(crate) fn set(&mut self, cluster: u32, value: u32) {
+        let n = cluster as usize;
+        match self.kind {
+            FatKind::Fat12 => {
+                let at = n + n / 2;
+                let old = u16::from_le_bytes([self.raw[at], self.raw[at + 1]]);
+                let new = if n % 2 == 1 {
+                    (old & 0x000F) | ((value as u16) << 4)
+                } else {
+                    (old & 0xF000) | (value as u16 & 0x0FFF)
+                };
+                self.raw[at..at + 2].copy_from_slice(&new.to_le_bytes());
+            }
+            FatKind::Fat16 => self.raw[2 * n..2 * n + 2].copy_from_slice(&(value as u16).to_le_bytes()),
+            FatKind::Fat32 => {
+                // The top four bits are reserved and kept as they are
+                let old = u32::from_le_bytes([self.raw[4 * n], self.raw[4 * n + 1], self.raw[4 * n + 2], self.raw[4 * n + 3]]);
+                let new = (old & 0xF000_0000) | (value & 0x0FFF_FFFF);
+                self.raw[4 * n..4 * n + 4].copy_from_slice(&new.to_le_bytes());
+            }
+        }
+    }
+}
+
+pub(crate) fn first_cluster(entry: &[u8]) -> u32 {
+    let high = u16::from_le_bytes([entry[20], entry[21]]) as u32;
+    let low = u16::from_le_bytes([entry[26], entry[27]]) as u32;
+    (high << 16) | low
+}
+
+pub(crate) fn set_first_cluster(entry: &mut [u8], cluster: u32) {
+    entry[20..22].copy_from_slice(&((cluster >> 16) as u16).to_le_bytes());
+    entry[26..28].copy_from_slice(&(cluster as u16).to_le_bytes());
+}
+
+pub(crate) fn entry_size(entry: &[u8]) -> u32 {
+    u32::from_le_bytes([entry[28], entry[29], entry[30], entry[31]])
+}
+
+pub(crate) fn set_entry_size(entry: &mut [u8], size: u32) {
+    entry[28..32].copy_from_slice(&size.to_le_bytes());
+}
+
+// The 8.3 name of a short entry as `NAME.EXT`
+pub(crate) fn short_name(entry: &[u8]) -> String {
+    let mut bytes = entry[..11].to_vec();
+    if bytes[0] == 0x05 {
+        bytes[0] = DELETED;
+    }
+    let base = String::from_utf8_lossy(&bytes[..8]).trim_end().to_string();
+    let ext = String::from_utf8_lossy(&bytes[8..]).trim_end().to_string();
+    if ext.is_empty() {
+        base
+    } else {
+        format!("{}.{}", base, ext)
+    }
+}
+
+// The UTF-16 characters of a long name entry, up to the terminating zero
+pub(crate) fn long_part(entry: &[u8]) -> Vec<u16> {
+    let mut part: Vec<u16> = [1, 3, 5, 7, 9, 14, 16, 18, 20, 22, 24, 28, 30]
+        .iter()
+        .map(|&at| u16::from_le_bytes([entry[at], entry[at + 1]]))
+        .collect();
+    if let Some(end) = part.iter().position(|&unit| unit == 0) {
+        part.truncate(end);
+    }
+    part
+}
+
+// Checksum of a short name, stored in each of its long name entries
+pub(crate) fn checksum(entry: &[u8]) -> u8 {
+    entry[..11]
+        .iter()
+        .fold(0u8, |sum, &byte| ((sum & 1) << 7).wrapping_add(sum >> 1).wrapping_add(byte))
+}
+
+// Function to pack a date the way directory entries hold it
+pub(crate) fn encode_date(date: fatfs::Date) -> u16 {
+    ((date.year - 1980) << 9) | (date.month << 5) | date.day
+}
+
+// Function to pack a time of day, to two seconds
+pub(crate) fn encode_time(time: fatfs::Time) -> u16 {
+    (time.hour << 11) | (time.min << 5) | (time.sec / 2)
+}
+
+// A volume opened for patching directory entries behind fatfs' back. fatfs
+// reads entries from the image every time, so it sees the changes.
+pub(crate) struct Volume {
+    file: File,
+    display: String,
+    geometry: Geometry,
+    fat: Fat,
+}
+
+impl Volume {
+    pub(crate) fn open(image: &Path) -> Result<Volume> {
+        let display = image.display().to_string();
+        let mut file = OpenOptions::new().read(true).write(true).open(image).at(&display)?;
+        let mut sector = [0u8; 512];
+        file.read_exact(&mut sector).at(&display)?;
+        let geometry = Geometry::parse(sector).map_err(|err| RnixError::CorruptImage(format!("{}: {}", display, err)))?;
+        let mut raw = vec![0; geometry.fat_bytes()];
+        file.seek(SeekFrom::Start(geometry.fat_offset(0))).at(&display)?;
+        file.read_exact(&mut raw).at(&display)?;
+        let fat = Fat { kind: geometry.kind, raw };
+        Ok(Volume {
+            file,
+            display,
+            geometry,
+            fat,
+        })
+    }
+
+    // Function to find the offset of the short entry for `rel`, a path from
+    // the root of the volume, matching names without regard to case
+    pub(crate) fn locate(&mut self, rel: &str) -> Result<u64> {
+        let mut regions = match self.geometry.kind {
+            FatKind::Fat32 => self.regions(self.geometry.root_cluster),
+            _ => vec![(self.geometry.root_offset(), self.geometry.root_entries as usize * ENTRY_SIZE)],
+        };
+        let mut components = rel.split('/').filter(|part| !part.is_empty()).peekable();
+        while let Some(name) = components.next() {
+            let (offset, entry) = self
+                .find(&regions, name)?
+                .ok_or_else(|| RnixError::NotFound(rel.to_string()))?;
+            if components.peek().is_none() {
+                return Ok(offset);
+            }
+            if entry[11] & ATTR_DIRECTORY == 0 {
+                return Err(RnixError::NotADirectory(rel.to_string()));
+            }
+            regions = self.regions(first_cluster(&entry));
+        }
+        Err(RnixError::InvalidArgument("the root directory has no entry of its own".to_string()))
+    }
+
+    pub(crate) fn read_entry(&mut self, offset: u64) -> Result<[u8; ENTRY_SIZE]> {
+        let mut entry = [0; ENTRY_SIZE];
+        self.file.seek(SeekFrom::Start(offset)).at(&self.display)?;
+        self.file.read_exact(&mut entry).at(&self.display)?;
+        Ok(entry)
+    }
+
+    pub(crate) fn write_entry(&mut self, offset: u64, entry: &[u8; ENTRY_SIZE]) -> Result<()> {
+        self.file.seek(SeekFrom::Start(offset)).at(&self.display)?;
+        self.file.write_all(entry).at(&self.display)?;
+        self.file.flush().at(&self.display)
+    }
+
+    // Where the runs of free clusters are on the image, as offsets and lengths
+    pub(crate) fn free_runs(&self) -> Vec<(u64, u64)> {
+        let mut runs: Vec<(u64, u64)> = Vec::new();
+        for cluster in 2..self.geometry.clusters + 2 {
+            if self.fat.get(cluster) != 0 {
+                continue;
+            }
+            let offset = self.geometry.cluster_offset(cluster);
+            match runs.last_mut() {
+                Some((start, len)) if *start + *len == offset => *len += self.geometry.cluster_bytes,
+                _ => runs.push((offset, self.geometry.cluster_bytes)),
+            }
+        }
+        runs
+    }
+
+    // Where the clusters of a directory starting at `first` are on the image
+    fn regions(&self, first: u32) -> Vec<(u64, usize)> {
+        let mut regions = Vec::new();
+        let mut cluster = first;
+        // A damaged chain can loop; no directory is longer than the volume
+        while self.geometry.in_range(cluster) && regions.len() <= self.geometry.clusters as usize {
+            regions.push((self.geometry.cluster_offset(cluster), self.geometry.cluster_bytes as usize));
+            let next = self.fat.get(cluster);
+            if self.geometry.is_end(next) {
+                break;
+            }
+            cluster = next;
+        }
+        regions
+    }
+
+    // Function to look for `name` among the entries of a directory, by its
+    // long name or its short one
+    fn find(&mut self, regions: &[(u64, usize)], name: &str) -> Result<Option<(u64, Vec<u8>)>> {
+        let wanted = name.to_uppercase();
+        let mut long: Vec<u16> = Vec::new();
+        let mut long_checksum = None;
+        for &(start, size) in regions {
+            let mut bytes = vec![0; size];
+            self.file.seek(SeekFrom::Start(start)).at(&self.display)?;
+            self.file.read_exact(&mut bytes).at(&self.display)?;
+            for (index, entry) in bytes.chunks(ENTRY_SIZE).enumerate() {
+                match entry[0] {
+                    0 => return Ok(None),
+                    DELETED => long_checksum = None,
+                    _ if entry[11] & 0x3F == ATTR_LFN => {
+                        // Parts come last first, so each is put in front
+                        if entry[0] & 0x40 != 0 {
+                            long.clear();
+                        }
+                        let mut part = long_part(entry);
+                        part.append(&mut long);
+                        long = part;
+                        long_checksum = Some(entry[13]);
+                    }
+                    _ if entry[11] & ATTR_VOLUME_ID != 0 => long_checksum = None,
+                    _ => {
+                        let long_name = match long_checksum.take() {
+                            Some(sum) if sum == checksum(entry) => Some(String::from_utf16_lossy(&long)),
+                            _ => None,
+                        };
+                        let matches = long_name.is_some_and(|long| long.to_uppercase() == wanted)
+                            || short_name(entry).to_uppercase() == wanted;
+                        if matches {
+                            return Ok(Some((start + (index * ENTRY_SIZE) as u64, entry.to_vec())));
+                        }
+                    }
+                }
+            }
+        }
+        Ok(None)
+    }
+}
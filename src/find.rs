@@ -0,0 +1,244 @@
+// The `find` expression and the walk behind it. Every test in the expression
+// has to hold for an entry to match (there is no -o or !). Paths are walked
+// by name through the system, so the walk carries on into mounted disks.
+
+use std::time::{Duration, SystemTime};
+
+use crate::error::{Result, RnixError};
+use crate::glob;
+use crate::path;
+use crate::system::{Session, System};
+use crate::transfer::from_fat;
+
+const DAY: u64 = 24 * 60 * 60;
+
+// How a number in a test compares: `+N` is more than N, `-N` less, `N` exactly N
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compare {
+    Less(u64),
+    More(u64),
+    Exactly(u64),
+}
+
+impl Compare {
+    fn parse(text: &str) -> Option<Compare> {
+        let (make, digits): (fn(u64) -> Compare, &str) = match text.as_bytes().first() {
+            Some(b'+') => (Compare::More, &text[1..]),
+            Some(b'-') => (Compare::Less, &text[1..]),
+            _ => (Compare::Exactly, text),
+        };
+        digits.parse().ok().map(make)
+    }
+
+    fn holds(self, value: u64) -> bool {
+        match self {
+            Compare::Less(n) => value < n,
+            Compare::More(n) => value > n,
+            Compare::Exactly(n) => value == n,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Test {
+    Name(String),
+    // True for directories
+    Type(bool),
+    // Size counted in units of the given number of bytes, rounded up
+    Size(Compare, u64),
+    // Age in whole days
+    MTime(Compare),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    Print,
+    Delete,
+    // Words of a command, with `{}` standing for the path
+    Exec(Vec<String>),
+}
+
+// A parsed `find` command line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Query {
+    pub paths: Vec<String>,
+    pub tests: Vec<Test>,
+    pub actions: Vec<Action>,
+    pub max_depth: Option<usize>,
+}
+
+// An entry the walk came across
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Found {
+    // Built on the starting path as given, as find prints it
+    pub path: String,
+    pub depth: usize,
+    pub is_dir: bool,
+    pub len: u64,
+    // Not known for the root of a disk
+    pub modified: Option<fatfs::DateTime>,
+}
+
+impl Query {
+    // Function to parse `[path...] [expression]`
+    pub fn parse(words: &[&str]) -> Result<Query> {
+        let start = words.iter().position(|word| word.starts_with('-')).unwrap_or(words.len());
+        let mut query = Query {
+            paths: words[..start].iter().map(|word| word.to_string()).collect(),
+            tests: Vec::new(),
+            actions: Vec::new(),
+            max_depth: None,
+        };
+        if query.paths.is_empty() {
+            query.paths.push(".".to_string());
+        }
+
+        let mut rest = words[start..].iter();
+        while let Some(&word) = rest.next() {
+            let mut argument = || {
+                rest.next()
+                    .copied()
+                    .ok_or_else(|| RnixError::InvalidArgument(format!("missing argument to '{}'", word)))
+            };
+            let invalid = |value: &str| RnixError::InvalidArgument(format!("invalid argument '{}' to '{}'", value, word));
+            match word {
+                "-name" => query.tests.push(Test::Name(argument()?.to_string())),
+                "-type" => match argument()? {
+                    "f" => query.tests.push(Test::Type(false)),
+                    "d" => query.tests.push(Test::Type(true)),
+                    other => return Err(invalid(other)),
+                },
+                "-size" => {
+                    let value = argument()?;
+                    let (number, unit) = match value.char_indices().last() {
+                        Some((at, 'c')) => (&value[..at], 1),
+                        Some((at, 'w')) => (&value[..at], 2),
+                        Some((at, 'b')) => (&value[..at], 512),
+                        Some((at, 'k')) => (&value[..at], 1024),
+                        Some((at, 'M')) => (&value[..at], 1024 * 1024),
+                        Some((at, 'G')) => (&value[..at], 1024 * 1024 * 1024),
+                        _ => (value, 512),
+                    };
+                    let compare = Compare::parse(number).ok_or_else(|| invalid(value))?;
+                    query.tests.push(Test::Size(compare, unit));
+                }
+                "-mtime" => {
+                    let value = argument()?;
+                    query.tests.push(Test::MTime(Compare::parse(value).ok_or_else(|| invalid(value))?));
+                }
+                "-maxdepth" => {
+                    let value = argument()?;
+                    query.max_depth = Some(value.parse().map_err(|_| invalid(value))?);
+                }
+                "-print" => query.actions.push(Action::Print),
+                "-delete" => query.actions.push(Action::Delete),
+                "-exec" => {
+                    let mut command = Vec::new();
+                    loop {
+                        match rest.next() {
+                            Some(&";") => break,
+                            Some(word) => command.push(word.to_string()),
+                            None => return Err(RnixError::InvalidArgument("missing ';' after -exec".to_string())),
+                        }
+                    }
+                    if command.is_empty() {
+                        return Err(RnixError::InvalidArgument("-exec needs a command".to_string()));
+                    }
+                    query.actions.push(Action::Exec(command));
+                }
+                other => return Err(RnixError::InvalidArgument(format!("unknown predicate '{}'", other))),
+            }
+        }
+        if query.actions.is_empty() {
+            query.actions.push(Action::Print);
+        }
+        Ok(query)
+    }
+
+    // Whether a found entry passes every test, `now` being the time ages count from
+    pub fn matches(&self, found: &Found, now: SystemTime) -> bool {
+        self.tests.iter().all(|test| match test {
+            Test::Name(pattern) => {
+                let name = found.path.trim_end_matches('/').rsplit('/').next().unwrap_or_default();
+                glob::matches(pattern, name)
+            }
+            Test::Type(is_dir) => found.is_dir == *is_dir,
+            Test::Size(compare, unit) => compare.holds(found.len.div_ceil(*unit)),
+            Test::MTime(compare) => match found.modified.and_then(from_fat) {
+                Some(modified) => {
+                    let age = now.duration_since(modified).unwrap_or(Duration::ZERO);
+                    compare.holds(age.as_secs() / DAY)
+                }
+                None => false,
+            },
+        })
+    }
+
+    // Whether entries are wanted after their contents, so a directory can be
+    // deleted once it has been emptied
+    pub fn depth_first(&self) -> bool {
+        self.actions.contains(&Action::Delete)
+    }
+}
+
+// Function to collect what matches `query` below `start`, in the order find
+// would act on it
+pub fn search(system: &System, session: &Session, query: &Query, start: &str, now: SystemTime) -> Result<Vec<Found>> {
+    let found = if system.is_dir(session, start) {
+        Found {
+            path: start.to_string(),
+            depth: 0,
+            is_dir: true,
+            len: 0,
+            modified: None,
+        }
+    } else {
+        let abs = session.resolve(start);
+        let (parent, name) = path::split(&abs);
+        let entry = system
+            .list_dir(session, parent)?
+            .into_iter()
+            .find(|entry| entry.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| RnixError::NotFound(start.to_string()))?;
+        Found {
+            path: start.to_string(),
+            depth: 0,
+            is_dir: false,
+            len: entry.len,
+            modified: Some(entry.modified),
+        }
+    };
+    let mut matched = Vec::new();
+    visit(system, session, query, found, now, &mut matched)?;
+    Ok(matched)
+}
+
+fn visit(
+    system: &System,
+    session: &Session,
+    query: &Query,
+    found: Found,
+    now: SystemTime,
+    matched: &mut Vec<Found>,
+) -> Result<()> {
+    let wanted = query.matches(&found, now);
+    if wanted && !query.depth_first() {
+        matched.push(found.clone());
+    }
+    if found.is_dir && query.max_depth.is_none_or(|max| found.depth < max) {
+        for entry in system.list_dir(session, &found.path)? {
+            let child = Found {
+                path: path::join(&found.path, &entry.name),
+                depth: found.depth + 1,
+                is_dir: entry.is_dir,
+                len: entry.len,
+                modified: Some(entry.modified),
+            };
+            visit(system, session, query, child, now, matched)?;
+        }
+    }
+    if wanted && query.depth_first() {
+        matched.push(found);
+    }
+    Ok(())
+}
@@ -0,0 +1,663 @@
+// Checking and repairing the FAT filesystem on a disk image, in the spirit of
+// dosfsck. The image is read directly rather than through fatfs, which trusts
+// the structures being checked. Repairs are made to an in-memory copy of the
+// FAT and to queued directory entry patches, and written out at the end.
+// Images holding an `internal` directory are also checked against the layout
+// rnix expects of a root disk.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+use fatfs::{FileSystem, FsOptions};
+
+use crate::auth::decode_accounts;
+use crate::error::{IoResultExt, Result, RnixError};
+use crate::fat::{
+    checksum, entry_size, first_cluster, long_part, set_entry_size, set_first_cluster, short_name, Fat, FatKind, Geometry,
+    ATTR_DIRECTORY, ATTR_LFN, ATTR_VOLUME_ID, DELETED, ENTRY_SIZE,
+};
+use crate::path;
+use crate::system::{LAYOUT, ROOT_ACCOUNT, SETUP_FLAG, USER_ACCOUNTS};
+
+const FSINFO_FREE_UNKNOWN: u32 = 0xFFFF_FFFF;
+
+// How to check an image
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Options {
+    // Fix what can be fixed instead of only reporting it
+    pub repair: bool,
+    // The image is in use, so its free count and clean-unmount flag are expected to lag
+    pub mounted: bool,
+}
+
+// Something wrong with the image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Problem {
+    pub message: String,
+    pub fixed: bool,
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.message, if self.fixed { " (fixed)" } else { "" })
+    }
+}
+
+// What a check found
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Report {
+    pub problems: Vec<Problem>,
+    pub files: usize,
+    pub dirs: usize,
+    pub used_clusters: u32,
+    pub total_clusters: u32,
+}
+
+impl Report {
+    pub fn is_clean(&self) -> bool {
+        self.problems.is_empty()
+    }
+
+    // Exit status in the style of fsck: 0 clean, 1 everything was repaired, 4 problems remain
+    pub fn status(&self) -> i32 {
+        if self.is_clean() {
+            0
+        } else if self.problems.iter().all(|problem| problem.fixed) {
+            1
+        } else {
+            4
+        }
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |count: usize, one: &str, many: &str| format!("{} {}", count, if count == 1 { one } else { many });
+        write!(
+            f,
+            "{}, {}, {}/{} clusters used; ",
+            plural(self.files, "file", "files"),
+            plural(self.dirs, "directory", "directories"),
+            self.used_clusters,
+            self.total_clusters
+        )?;
+        if self.is_clean() {
+            return write!(f, "no problems");
+        }
+        let fixed = self.problems.iter().filter(|problem| problem.fixed).count();
+        write!(f, "{}, {} fixed", plural(self.problems.len(), "problem", "problems"), fixed)
+    }
+}
+
+// Function to check the image at `path`, repairing it if `options.repair` is set
+pub fn check(path: &Path, options: Options) -> Result<Report> {
+    let display = path.display().to_string();
+    let file = OpenOptions::new().read(true).write(options.repair).open(path).at(&display)?;
+    let mut checker = Checker::new(file, &display, options)?;
+    checker.check_volume()?;
+    checker.finish()?;
+    let Checker { file, mut report, .. } = checker;
+    check_layout(file, &display, options, &mut report)?;
+    Ok(report)
+}
+
+// Long name entries seen so far, waiting for the short entry they belong to
+#[derive(Default)]
+struct LongName {
+    offsets: Vec<u64>,
+    next: u8,
+    checksum: u8,
+    name: Vec<u16>,
+}
+
+impl LongName {
+    fn is_complete(&self) -> bool {
+        !self.offsets.is_empty() && self.next == 0
+    }
+}
+
+struct Checker {
+    file: File,
+    display: String,
+    options: Options,
+    geometry: Geometry,
+    fat: Fat,
+    // Which path owns each cluster, as an index into `owners` plus one
+    claimed: Vec<u32>,
+    owners: Vec<String>,
+    // Directory entry bytes to write back when repairing
+    patches: Vec<(u64, Vec<u8>)>,
+    report: Report,
+}
+
+impl Checker {
+    fn new(mut file: File, display: &str, options: Options) -> Result<Checker> {
+        let mut sector = [0u8; 512];
+        file.seek(SeekFrom::Start(0)).at(display)?;
+        file.read_exact(&mut sector)
+            .map_err(|_| RnixError::CorruptImage(format!("{}: too short to hold a boot sector", display)))?;
+        let geometry = Geometry::parse(sector).map_err(|err| RnixError::CorruptImage(format!("{}: {}", display, err)))?;
+        let fat = Fat {
+            kind: geometry.kind,
+            raw: Vec::new(),
+        };
+        let clusters = geometry.clusters as usize + 2;
+        let mut checker = Checker {
+            file,
+            display: display.to_string(),
+            options,
+            geometry,
+            fat,
+            claimed: vec![0; clusters],
+            owners: Vec::new(),
+            patches: Vec::new(),
+            report: Report::default(),
+        };
+        checker.report.total_clusters = checker.geometry.clusters;
+        Ok(checker)
+    }
+
+    // Record a problem; returns whether to repair it
+    fn problem(&mut self, message: String, repairable: bool) -> bool {
+        let fixed = repairable && self.options.repair;
+        self.report.problems.push(Problem { message, fixed });
+        fixed
+    }
+
+    // Read `len` bytes at `offset`; anything past the end of the image reads as zeroes
+    fn read_at(&mut self, offset: u64, len: usize) -> Result<Vec<u8>> {
+        let mut buffer = vec![0; len];
+        self.file.seek(SeekFrom::Start(offset)).at(&self.display)?;
+        let mut filled = 0;
+        while filled < len {
+            match self.file.read(&mut buffer[filled..]).at(&self.display)? {
+                0 => break,
+                read => filled += read,
+            }
+        }
+        Ok(buffer)
+    }
+
+    fn check_volume(&mut self) -> Result<()> {
+        let needed = self.geometry.total_sectors * self.geometry.bytes_per_sector;
+        let len = self.file.metadata().at(&self.display)?.len();
+        if len < needed && self.problem(format!("image is {} bytes but the volume needs {}", len, needed), true) {
+            self.file.set_len(needed).at(&self.display)?;
+        }
+
+        if self.geometry.kind == FatKind::Fat32 && self.geometry.backup_boot != 0 {
+            let offset = self.geometry.backup_boot * self.geometry.bytes_per_sector;
+            let backup = self.read_at(offset, 512)?;
+            let flags = self.geometry.flags_offset();
+            let differs = (0..512).any(|at| at != flags && backup[at] != self.geometry.sector[at]);
+            if differs && self.problem("backup boot sector differs from the boot sector".to_string(), true) {
+                self.patches.push((offset, self.geometry.sector.to_vec()));
+            }
+        }
+
+        let fat_bytes = self.geometry.fat_bytes();
+        self.fat.raw = self.read_at(self.geometry.fat_offset(0), fat_bytes)?;
+        for copy in 1..self.geometry.fats {
+            if self.read_at(self.geometry.fat_offset(copy), fat_bytes)? != self.fat.raw {
+                // Every copy is rewritten from the first one when repairing
+                self.problem(format!("FAT {} differs from FAT 1", copy + 1), true);
+            }
+        }
+        if self.fat.get(0) & 0xFF != self.geometry.media as u32
+            && self.problem("FAT 1 does not start with the media byte".to_string(), true)
+        {
+            let value = (self.geometry.end_of_chain() & !0xFF) | self.geometry.media as u32;
+            self.fat.set(0, value);
+        }
+
+        if self.geometry.kind == FatKind::Fat32 {
+            let root = self.geometry.root_cluster;
+            let chain = self.claim_chain("/", root);
+            self.check_dir("/", &chain, root, 0)?;
+        } else {
+            self.check_dir("/", &[], 0, 0)?;
+        }
+        self.check_lost();
+        Ok(())
+    }
+
+    // Follow the chain from `first`, claiming its clusters for `path`. A chain
+    // that breaks, loops or runs into another file's clusters is cut short.
+    fn claim_chain(&mut self, path: &str, first: u32) -> Vec<u32> {
+        self.owners.push(path.to_string());
+        let owner = self.owners.len() as u32;
+        let mut chain: Vec<u32> = Vec::new();
+        let mut cluster = first;
+        loop {
+            let claimed_by = self.claimed[cluster as usize];
+            if claimed_by != 0 {
+                let message = if claimed_by == owner {
+                    format!("{}: cluster chain loops back to cluster {}", path, cluster)
+                } else {
+                    let other = &self.owners[claimed_by as usize - 1];
+                    format!("{}: cross-linked with {} at cluster {}", path, other, cluster)
+                };
+                // Cutting the chain at its first cluster is left to the caller
+                if self.problem(message, true) && !chain.is_empty() {
+                    let end = self.geometry.end_of_chain();
+                    self.fat.set(chain[chain.len() - 1], end);
+                }
+                break;
+            }
+            self.claimed[cluster as usize] = owner;
+            chain.push(cluster);
+            let next = self.fat.get(cluster);
+            if self.geometry.is_end(next) {
+                break;
+            }
+            if next == 0 || self.geometry.is_bad(next) || !self.geometry.in_range(next) {
+                let what = match next {
+                    0 => "a free cluster".to_string(),
+                    next if self.geometry.is_bad(next) => "a bad cluster".to_string(),
+                    next => format!("cluster {} outside the volume", next),
+                };
+                if self.problem(format!("{}: cluster chain runs into {} after cluster {}", path, what, cluster), true) {
+                    let end = self.geometry.end_of_chain();
+                    self.fat.set(cluster, end);
+                }
+                break;
+            }
+            cluster = next;
+        }
+        chain
+    }
+
+    // Give back the clusters of a chain cut short during repair
+    fn release(&mut self, clusters: &[u32]) {
+        for &cluster in clusters {
+            self.claimed[cluster as usize] = 0;
+            self.fat.set(cluster, 0);
+        }
+    }
+
+    // Check the entries of the directory at `path`, held in `chain` (or the
+    // fixed root directory when `chain` is empty), and everything below it
+    fn check_dir(&mut self, path: &str, chain: &[u32], cluster: u32, parent: u32) -> Result<()> {
+        let regions: Vec<(u64, usize)> = if chain.is_empty() && path == "/" {
+            let size = self.geometry.root_entries as usize * ENTRY_SIZE;
+            vec![(self.geometry.root_offset(), size)]
+        } else {
+            let size = self.geometry.cluster_bytes as usize;
+            chain.iter().map(|&cluster| (self.geometry.cluster_offset(cluster), size)).collect()
+        };
+        let is_root = path == "/";
+        let mut long = LongName::default();
+        let mut names: HashMap<[u8; 11], String> = HashMap::new();
+        let (mut dot, mut dot_dot) = (false, false);
+        let mut subdirs = Vec::new();
+
+        'regions: for (start, size) in regions {
+            let bytes = self.read_at(start, size)?;
+            for (index, raw) in bytes.chunks(ENTRY_SIZE).enumerate() {
+                let offset = start + (index * ENTRY_SIZE) as u64;
+                let mut entry = raw.to_vec();
+                if entry[0] == 0 {
+                    self.orphaned(path, &mut long);
+                    break 'regions;
+                }
+                if entry[0] == DELETED {
+                    self.orphaned(path, &mut long);
+                    continue;
+                }
+                if entry[11] & 0x3F == ATTR_LFN {
+                    self.long_entry(path, offset, &entry, &mut long);
+                    continue;
+                }
+                if entry[11] & ATTR_VOLUME_ID != 0 {
+                    self.orphaned(path, &mut long);
+                    if !is_root && self.problem(format!("{}: volume label entry inside a directory", path), true) {
+                        self.patches.push((offset, vec![DELETED]));
+                    }
+                    continue;
+                }
+
+                let short = short_name(&entry);
+                if &entry[..11] == b".          " || &entry[..11] == b"..         " {
+                    let dots = if entry[1] == b'.' { &mut dot_dot } else { &mut dot };
+                    *dots = true;
+                    // fatfs gives these a long name too, which is harmless when it matches
+                    if long.is_complete() && long.checksum == checksum(&entry) {
+                        long = LongName::default();
+                    }
+                    self.orphaned(path, &mut long);
+                    self.check_dot(path, offset, &mut entry, cluster, parent);
+                    continue;
+                }
+
+                // Pair the entry with its long name, if the long name belongs to it
+                let mut name = short.clone();
+                if !long.offsets.is_empty() {
+                    if long.is_complete() && long.checksum == checksum(&entry) {
+                        name = String::from_utf16_lossy(&long.name);
+                    } else if self.problem(format!("{}: long name before {} does not belong to it", path, short), true) {
+                        for &at in &long.offsets {
+                            self.patches.push((at, vec![DELETED]));
+                        }
+                    }
+                }
+                let long_entries = std::mem::take(&mut long).offsets;
+                let full = path::join(path, &name);
+
+                if !valid_short_name(&entry) && self.problem(format!("{}: invalid short name {:?}", full, short), true) {
+                    sanitize(&mut entry);
+                    // The long name's checksum covers the short name
+                    let sum = checksum(&entry);
+                    if name != short {
+                        for &at in &long_entries {
+                            self.patches.push((at + 13, vec![sum]));
+                        }
+                    }
+                }
+                let mut key = [0u8; 11];
+                key.copy_from_slice(&entry[..11]);
+                key.make_ascii_uppercase();
+                if let Some(first) = names.insert(key, full.clone()) {
+                    self.problem(format!("{}: same short name as {}", full, first), false);
+                }
+
+                let delete = if entry[11] & ATTR_DIRECTORY != 0 {
+                    self.check_subdir_entry(&full, &mut entry, &mut subdirs)
+                } else {
+                    self.check_file_entry(&full, &mut entry);
+                    false
+                };
+                if delete {
+                    self.patches.push((offset, vec![DELETED]));
+                    for &at in &long_entries {
+                        self.patches.push((at, vec![DELETED]));
+                    }
+                } else if entry != raw {
+                    self.patches.push((offset, entry));
+                }
+            }
+        }
+        self.orphaned(path, &mut long);
+        if !(is_root || dot && dot_dot) {
+            self.problem(format!("{}: '.' or '..' entry is missing", path), false);
+        }
+
+        for (full, chain) in subdirs {
+            self.check_dir(&full, &chain, chain[0], cluster)?;
+        }
+        Ok(())
+    }
+
+    fn long_entry(&mut self, path: &str, offset: u64, entry: &[u8], long: &mut LongName) {
+        let order = entry[0] & 0x3F;
+        let mut part = long_part(entry);
+        if entry[0] & 0x40 != 0 && order > 0 {
+            self.orphaned(path, long);
+            *long = LongName {
+                offsets: vec![offset],
+                next: order - 1,
+                checksum: entry[13],
+                name: part,
+            };
+        } else if !long.offsets.is_empty() && order == long.next && order > 0 && entry[13] == long.checksum {
+            long.offsets.push(offset);
+            long.next -= 1;
+            part.append(&mut long.name);
+            long.name = part;
+        } else {
+            long.offsets.push(offset);
+            self.orphaned(path, long);
+        }
+    }
+
+    // Long name entries not followed by the short entry they belong to
+    fn orphaned(&mut self, path: &str, long: &mut LongName) {
+        let offsets = std::mem::take(long).offsets;
+        if !offsets.is_empty() && self.problem(format!("{}: {} orphaned long name entries", path, offsets.len()), true) {
+            for at in offsets {
+                self.patches.push((at, vec![DELETED]));
+            }
+        }
+    }
+
+    fn check_dot(&mut self, path: &str, offset: u64, entry: &mut [u8], cluster: u32, parent: u32) {
+        let name = if entry[1] == b'.' { ".." } else { "." };
+        if path == "/" {
+            if self.problem(format!("/: '{}' entry in the root directory", name), true) {
+                self.patches.push((offset, vec![DELETED]));
+            }
+            return;
+        }
+        let target = first_cluster(entry);
+        // fatfs points '..' at the FAT32 root's own cluster instead of 0
+        let expected = if name == "." { cluster } else { parent };
+        let fine = target == expected || (name == ".." && parent == self.geometry.root_cluster && target == 0);
+        if !fine && self.problem(format!("{}: '{}' points to cluster {} instead of {}", path, name, target, expected), true) {
+            set_first_cluster(entry, expected);
+            self.patches.push((offset, entry.to_vec()));
+        }
+    }
+
+    // Returns whether the entry should be deleted
+    fn check_subdir_entry(&mut self, path: &str, entry: &mut [u8], subdirs: &mut Vec<(String, Vec<u32>)>) -> bool {
+        self.report.dirs += 1;
+        let first = first_cluster(entry);
+        if first == 0 || !self.geometry.in_range(first) {
+            let message = format!("{}: directory starts at invalid cluster {}", path, first);
+            return self.problem(message, true);
+        }
+        if entry_size(entry) != 0 && self.problem(format!("{}: directory has a size of {}", path, entry_size(entry)), true) {
+            set_entry_size(entry, 0);
+        }
+        let chain = self.claim_chain(path, first);
+        if chain.is_empty() {
+            return self.options.repair;
+        }
+        subdirs.push((path.to_string(), chain));
+        false
+    }
+
+    fn check_file_entry(&mut self, path: &str, entry: &mut [u8]) {
+        self.report.files += 1;
+        let size = entry_size(entry) as u64;
+        let first = first_cluster(entry);
+        if first == 0 {
+            if size > 0 && self.problem(format!("{}: has a size of {} but no clusters", path, size), true) {
+                set_entry_size(entry, 0);
+            }
+            return;
+        }
+        if !self.geometry.in_range(first) {
+            if self.problem(format!("{}: starts at invalid cluster {}", path, first), true) {
+                set_first_cluster(entry, 0);
+                set_entry_size(entry, 0);
+            }
+            return;
+        }
+        let chain = self.claim_chain(path, first);
+        if chain.is_empty() {
+            if self.options.repair {
+                set_first_cluster(entry, 0);
+                set_entry_size(entry, 0);
+            }
+            return;
+        }
+        let needed = size.div_ceil(self.geometry.cluster_bytes) as usize;
+        if chain.len() > needed {
+            let extra = chain.len() - needed;
+            if self.problem(format!("{}: {} clusters past the end of the file", path, extra), true) {
+                if needed == 0 {
+                    set_first_cluster(entry, 0);
+                } else {
+                    let end = self.geometry.end_of_chain();
+                    self.fat.set(chain[needed - 1], end);
+                }
+                self.release(&chain[needed..]);
+            }
+        } else if chain.len() < needed {
+            let holds = chain.len() as u64 * self.geometry.cluster_bytes;
+            if self.problem(format!("{}: size {} is more than its clusters hold ({})", path, size, holds), true) {
+                set_entry_size(entry, holds as u32);
+            }
+        }
+    }
+
+    // Clusters marked in use that no file or directory reaches
+    fn check_lost(&mut self) {
+        let clusters = self.geometry.clusters + 2;
+        let lost: Vec<u32> = (2..clusters)
+            .filter(|&cluster| {
+                let value = self.fat.get(cluster);
+                value != 0 && !self.geometry.is_bad(value) && self.claimed[cluster as usize] == 0
+            })
+            .collect();
+        if !lost.is_empty() {
+            // Lost clusters another lost cluster points to are not the start of a chain
+            let continued = lost
+                .iter()
+                .map(|&cluster| self.fat.get(cluster))
+                .filter(|next| lost.binary_search(next).is_ok())
+                .count();
+            let chains = lost.len() - continued;
+            let message = format!("{} lost clusters in {} chain{}", lost.len(), chains, if chains == 1 { "" } else { "s" });
+            if self.problem(message, true) {
+                self.release(&lost);
+            }
+        }
+        self.report.used_clusters = (2..clusters).filter(|&cluster| self.fat.get(cluster) != 0).count() as u32;
+    }
+
+    // Check the FSInfo sector and clean-unmount flag, then write any repairs
+    fn finish(&mut self) -> Result<()> {
+        let mut sector = self.geometry.sector;
+        if !self.options.mounted {
+            let flags = self.geometry.flags_offset();
+            if sector[flags] & 0x03 != 0 && self.problem("volume was not cleanly unmounted".to_string(), true) {
+                sector[flags] &= !0x03;
+                self.patches.push((flags as u64, vec![sector[flags]]));
+            }
+            if self.geometry.kind == FatKind::Fat32 && self.geometry.fs_info != 0 {
+                self.check_fs_info()?;
+            }
+        }
+        if !self.options.repair {
+            return Ok(());
+        }
+
+        for (offset, bytes) in std::mem::take(&mut self.patches) {
+            self.file.seek(SeekFrom::Start(offset)).at(&self.display)?;
+            self.file.write_all(&bytes).at(&self.display)?;
+        }
+        for copy in 0..self.geometry.fats {
+            self.file.seek(SeekFrom::Start(self.geometry.fat_offset(copy))).at(&self.display)?;
+            self.file.write_all(&self.fat.raw).at(&self.display)?;
+        }
+        self.file.sync_all().at(&self.display)?;
+        Ok(())
+    }
+
+    fn check_fs_info(&mut self) -> Result<()> {
+        let offset = self.geometry.fs_info * self.geometry.bytes_per_sector;
+        let mut info = self.read_at(offset, 512)?;
+        let u32_at = |info: &[u8], at: usize| u32::from_le_bytes([info[at], info[at + 1], info[at + 2], info[at + 3]]);
+        let free = self.report.total_clusters - self.report.used_clusters;
+        let signed = u32_at(&info, 0) == 0x4161_5252 && u32_at(&info, 484) == 0x6141_7272 && u32_at(&info, 508) == 0xAA55_0000;
+        if !signed {
+            if self.problem("FSInfo sector is damaged".to_string(), true) {
+                info = vec![0; 512];
+                info[0..4].copy_from_slice(&0x4161_5252u32.to_le_bytes());
+                info[484..488].copy_from_slice(&0x6141_7272u32.to_le_bytes());
+                info[488..492].copy_from_slice(&free.to_le_bytes());
+                info[492..496].copy_from_slice(&FSINFO_FREE_UNKNOWN.to_le_bytes());
+                info[508..512].copy_from_slice(&0xAA55_0000u32.to_le_bytes());
+                self.patches.push((offset, info));
+            }
+            return Ok(());
+        }
+        let recorded = u32_at(&info, 488);
+        if recorded != FSINFO_FREE_UNKNOWN
+            && recorded != free
+            && self.problem(format!("free cluster count is {} but {} are free", recorded, free), true)
+        {
+            self.patches.push((offset + 488, free.to_le_bytes().to_vec()));
+        }
+        Ok(())
+    }
+}
+
+fn is_invalid_byte(byte: u8) -> bool {
+    byte < 0x20 || byte == 0x7F || b"\"*+,./:;<=>?[\\]|".contains(&byte)
+}
+
+fn valid_short_name(entry: &[u8]) -> bool {
+    entry[0] != b' ' && entry[..11].iter().enumerate().all(|(at, &byte)| !is_invalid_byte(byte) || (at == 0 && byte == 0x05))
+}
+
+fn sanitize(entry: &mut [u8]) {
+    for (at, byte) in entry[..11].iter_mut().enumerate() {
+        if (is_invalid_byte(*byte) && !(at == 0 && *byte == 0x05)) || (at == 0 && *byte == b' ') {
+            *byte = b'_';
+        }
+    }
+}
+
+// Check what rnix itself relies on, for images that look like a root disk
+fn check_layout(mut file: File, display: &str, options: Options, report: &mut Report) -> Result<()> {
+    file.seek(SeekFrom::Start(0)).at(display)?;
+    let fs = FileSystem::new(file, FsOptions::new()).map_err(|err| RnixError::CorruptImage(format!("{}: {}", display, err)))?;
+    let root = fs.root_dir();
+    if root.open_dir(LAYOUT[0]).is_err() {
+        return Ok(());
+    }
+    let mut problem = |message: String, repairable: bool| {
+        let fixed = repairable && options.repair;
+        report.problems.push(Problem { message, fixed });
+        fixed
+    };
+
+    for dir in LAYOUT {
+        if root.open_dir(dir).is_ok() {
+            continue;
+        }
+        if root.open_file(dir).is_ok() {
+            problem(format!("/{}: should be a directory", dir), false);
+        } else if problem(format!("/{}: required directory is missing", dir), true) {
+            root.create_dir(dir).at(dir)?;
+        }
+    }
+
+    if root.open_file(SETUP_FLAG).is_err() {
+        return Ok(());
+    }
+    let accounts = |file: &str| -> Vec<String> {
+        let mut contents = Vec::new();
+        let read = root.open_file(file).and_then(|mut f| f.read_to_end(&mut contents));
+        if read.is_err() {
+            return Vec::new();
+        }
+        decode_accounts(&contents).into_iter().map(|account| account.username).collect()
+    };
+    let users = accounts(USER_ACCOUNTS);
+    let broken = if !accounts(ROOT_ACCOUNT).iter().any(|user| user == "root") {
+        Some(format!("/{}: no root account", ROOT_ACCOUNT))
+    } else if users.is_empty() {
+        Some(format!("/{}: no user accounts", USER_ACCOUNTS))
+    } else {
+        None
+    };
+    if let Some(message) = broken {
+        if problem(format!("{}; setup will run again", message), true) {
+            root.remove(SETUP_FLAG).at(SETUP_FLAG)?;
+        }
+        return Ok(());
+    }
+    for user in users {
+        let home = format!("home/{}", user);
+        if root.open_dir(&home).is_err() && problem(format!("/{}: home directory is missing", home), true) {
+            root.create_dir(&home).at(&home)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,176 @@
+// Pathname expansion: `*`, `?`, `[abc]`, `[!a-z]` and `**` against the
+// directory trees of the mounted disks. Matching ignores case, as FAT lookups do.
+
+use crate::system::{Session, System};
+
+// Function to test one path component against one pattern component. A
+// backslash makes the next character literal. As in sh, `*`, `?` and
+// brackets never match a leading `.`.
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().flat_map(char::to_lowercase).collect();
+    let name: Vec<char> = name.chars().flat_map(char::to_lowercase).collect();
+    if name.first() == Some(&'.') && pattern.first() != Some(&'.') {
+        return false;
+    }
+    match_from(&pattern, &name)
+}
+
+fn match_from(pattern: &[char], name: &[char]) -> bool {
+    match pattern.first() {
+        None => name.is_empty(),
+        Some('*') => (0..=name.len()).any(|skip| match_from(&pattern[1..], &name[skip..])),
+        Some('?') => !name.is_empty() && match_from(&pattern[1..], &name[1..]),
+        Some('[') => match (bracket(&pattern[1..]), name.first()) {
+            (Some((set, negated, len)), Some(c)) => {
+                set.contains(c) != negated && match_from(&pattern[1 + len..], &name[1..])
+            }
+            // An unclosed `[` is an ordinary character
+            (None, Some('[')) => match_from(&pattern[1..], &name[1..]),
+            _ => false,
+        },
+        Some('\\') if pattern.len() > 1 => name.first() == Some(&pattern[1]) && match_from(&pattern[2..], &name[1..]),
+        Some(c) => name.first() == Some(c) && match_from(&pattern[1..], &name[1..]),
+    }
+}
+
+// Parse a bracket expression after its `[`: the characters it accepts, whether
+// it is negated, and how many pattern characters it used including the `]`
+fn bracket(pattern: &[char]) -> Option<(Vec<char>, bool, usize)> {
+    let mut i = 0;
+    let negated = matches!(pattern.first(), Some('!' | '^'));
+    if negated {
+        i += 1;
+    }
+    let mut set = Vec::new();
+    let mut first = true;
+    while i < pattern.len() {
+        let c = pattern[i];
+        if c == ']' && !first {
+            return Some((set, negated, i + 1));
+        }
+        first = false;
+        let c = if c == '\\' && i + 1 < pattern.len() {
+            i += 1;
+            pattern[i]
+        } else {
+            c
+        };
+        if pattern.get(i + 1) == Some(&'-') && pattern.get(i + 2).is_some_and(|end| *end != ']') {
+            let end = pattern[i + 2];
+            set.extend((c..=end).flat_map(char::to_lowercase));
+            i += 3;
+        } else {
+            set.push(c);
+            i += 1;
+        }
+    }
+    None
+}
+
+// Function to tell whether a pattern component contains unescaped glob characters
+pub fn has_magic(component: &str) -> bool {
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '*' | '?' | '[' => return true,
+            _ => {}
+        }
+    }
+    false
+}
+
+// Function to remove the escaping backslashes from a pattern component
+pub fn unescape(component: &str) -> String {
+    let mut out = String::new();
+    let mut chars = component.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => out.extend(chars.next()),
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// Function to expand `pattern` into the matching paths, written the way the
+// pattern was (relative patterns give relative paths), sorted ignoring case.
+// An empty result means nothing matched.
+pub fn expand(system: &System, session: &Session, pattern: &str) -> Vec<String> {
+    let absolute = pattern.starts_with('/');
+    let components: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let mut paths = vec![if absolute { "/".to_string() } else { String::new() }];
+
+    for (index, component) in components.iter().enumerate() {
+        let last = index + 1 == components.len();
+        let mut next = Vec::new();
+        for path in &paths {
+            let dir = if path.is_empty() { "." } else { path.as_str() };
+            if *component == "**" {
+                // Zero or more directories; on its own at the end, everything below
+                if !last {
+                    next.push(path.clone());
+                }
+                walk(system, session, dir, path, last, &mut next);
+            } else if !has_magic(component) {
+                let candidate = join(path, &unescape(component));
+                let exists = if last {
+                    system.is_dir(session, &candidate) || system.is_file(session, &candidate)
+                } else {
+                    system.is_dir(session, &candidate)
+                };
+                if exists {
+                    next.push(candidate);
+                }
+            } else if let Ok(entries) = system.list_dir(session, dir) {
+                for entry in entries {
+                    if (last || entry.is_dir) && matches(component, &entry.name) {
+                        next.push(join(path, &entry.name));
+                    }
+                }
+            }
+        }
+        paths = next;
+        if paths.is_empty() {
+            break;
+        }
+    }
+
+    if pattern.ends_with('/') {
+        paths.retain(|path| system.is_dir(session, path));
+        paths.iter_mut().for_each(|path| path.push('/'));
+    }
+    paths.sort_by_key(|path| path.to_lowercase());
+    paths.dedup();
+    paths
+}
+
+// Collect the directories below `dir` (and, with `files`, everything else too), skipping hidden names
+fn walk(system: &System, session: &Session, dir: &str, prefix: &str, files: bool, out: &mut Vec<String>) {
+    let entries = match system.list_dir(session, dir) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries {
+        if entry.name.starts_with('.') || (!files && !entry.is_dir) {
+            continue;
+        }
+        let path = join(prefix, &entry.name);
+        out.push(path.clone());
+        if entry.is_dir {
+            walk(system, session, &path, &path, files, out);
+        }
+    }
+}
+
+fn join(prefix: &str, name: &str) -> String {
+    if prefix.is_empty() {
+        name.to_string()
+    } else if prefix.ends_with('/') {
+        format!("{}{}", prefix, name)
+    } else {
+        format!("{}/{}", prefix, name)
+    }
+}
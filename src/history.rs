@@ -0,0 +1,170 @@
+use crate::error::{Result, RnixError};
+
+// Name of the per-user history file, relative to the user's home directory
+pub const HISTORY_FILE: &str = ".rnix_history";
+
+// Number of entries kept in memory and on disk
+pub const HISTORY_SIZE: usize = 500;
+
+// Command lines entered in a session, oldest first. Entries are numbered from 1.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct History {
+    entries: Vec<String>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History::default()
+    }
+
+    // Rebuild a history from the contents of a history file
+    pub fn parse(contents: &str) -> History {
+        let mut history = History::new();
+        for line in contents.lines() {
+            history.push(line);
+        }
+        history
+    }
+
+    // Serialize for the history file, one entry per line
+    pub fn serialize(&self) -> String {
+        let mut contents = self.entries.join("\n");
+        if !contents.is_empty() {
+            contents.push('\n');
+        }
+        contents
+    }
+
+    // Record a line, skipping blanks and immediate repeats
+    pub fn push(&mut self, line: &str) {
+        let line = line.trim_end();
+        if line.trim().is_empty() || self.entries.last().map(String::as_str) == Some(line) {
+            return;
+        }
+        self.entries.push(line.to_string());
+        if self.entries.len() > HISTORY_SIZE {
+            let excess = self.entries.len() - HISTORY_SIZE;
+            self.entries.drain(..excess);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Entry by its 1-based history number
+    pub fn get(&self, number: usize) -> Option<&str> {
+        number
+            .checked_sub(1)
+            .and_then(|index| self.entries.get(index))
+            .map(String::as_str)
+    }
+
+    // Entries with their history numbers, oldest first
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (usize, &str)> {
+        self.entries.iter().enumerate().map(|(index, line)| (index + 1, line.as_str()))
+    }
+
+    // Most recent entry numbered below `before` that contains `query`
+    pub fn search_back(&self, query: &str, before: usize) -> Option<usize> {
+        let end = before.saturating_sub(1).min(self.entries.len());
+        self.entries[..end]
+            .iter()
+            .rposition(|line| line.contains(query))
+            .map(|index| index + 1)
+    }
+
+    // Perform `!!`, `!n`, `!-n` and `!prefix` expansion on a command line.
+    // Returns `None` when the line contains no history reference.
+    // Nothing inside single quotes is expanded, and `\!` stays a literal `!`.
+    pub fn expand(&self, line: &str) -> Result<Option<String>> {
+        let chars: Vec<char> = line.chars().collect();
+        let mut out = String::new();
+        let mut expanded = false;
+        let mut in_single = false;
+        let mut i = 0;
+        while i < chars.len() {
+            let c = chars[i];
+            if c == '\'' {
+                in_single = !in_single;
+            } else if c == '\\' && !in_single && i + 1 < chars.len() {
+                out.push(c);
+                out.push(chars[i + 1]);
+                i += 2;
+                continue;
+            } else if c == '!' && !in_single {
+                let (event, len) = event_designator(&chars[i + 1..]);
+                if len > 0 {
+                    out.push_str(self.resolve_event(&event)?);
+                    expanded = true;
+                    i += 1 + len;
+                    continue;
+                }
+            }
+            out.push(c);
+            i += 1;
+        }
+        Ok(if expanded { Some(out) } else { None })
+    }
+
+    fn resolve_event(&self, event: &str) -> Result<&str> {
+        let not_found = || RnixError::InvalidArgument(format!("!{}: event not found", event));
+        let found = if event == "!" {
+            self.entries.last().map(String::as_str)
+        } else if let Some(back) = event.strip_prefix('-') {
+            back.parse::<usize>()
+                .ok()
+                .and_then(|back| self.len().checked_sub(back))
+                .and_then(|index| self.get(index + 1))
+        } else if let Ok(number) = event.parse::<usize>() {
+            self.get(number)
+        } else {
+            self.entries
+                .iter()
+                .rev()
+                .find(|line| line.starts_with(event))
+                .map(String::as_str)
+        };
+        found.ok_or_else(not_found)
+    }
+}
+
+// Read the event after a `!`: "!" for `!!`, "-3", "12" or a command prefix.
+// Returns the designator and how many characters it used (0 if `!` is literal).
+fn event_designator(rest: &[char]) -> (String, usize) {
+    match rest.first() {
+        None => (String::new(), 0),
+        Some('!') => ("!".to_string(), 1),
+        Some(c) if c.is_whitespace() || matches!(c, '=' | '(' | '"' | '\'') => (String::new(), 0),
+        Some('-') => {
+            let digits: String = rest[1..].iter().take_while(|c| c.is_ascii_digit()).collect();
+            if digits.is_empty() {
+                (String::new(), 0)
+            } else {
+                let len = digits.len() + 1;
+                (format!("-{}", digits), len)
+            }
+        }
+        Some(c) if c.is_ascii_digit() => {
+            let digits: String = rest.iter().take_while(|c| c.is_ascii_digit()).collect();
+            let len = digits.len();
+            (digits, len)
+        }
+        Some(_) => {
+            let word: String = rest
+                .iter()
+                .take_while(|c| !c.is_whitespace() && !matches!(c, ';' | '|' | '&' | '"' | '\''))
+                .collect();
+            let len = word.chars().count();
+            (word, len)
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// An intent journal, so that a change made in several steps either happens
+// in full or not at all, even if rnix stops halfway. FAT keeps no journal of
+// its own.
+//
+// The steps of a change to a disk are written to `internal/<disk>.journal` on
+// the root disk, one per line with tab-separated fields, before any of them
+// is made. Steps that replace a file move a temporary file, already holding
+// the new contents, over the original. Once the temporary files are written a
+// `commit` line is added, then the steps are carried out and the journal
+// removed:
+//
+//     replace	docs/.notes.rnix-tmp	docs/notes
+//     mkdir	home/alice
+//     commit
+//
+// When rnix starts, and when a disk is mounted, a journal left behind is
+// carried out again if it was committed and rolled back if it was not.
+
+use std::io;
+
+use crate::error::{IoResultExt, Result};
+use crate::system::VDir;
+
+const COMMIT: &str = "commit";
+
+// One step of a change, with paths relative to the root of its disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Step {
+    // Move the finished temporary file over the target, which need not exist
+    Replace { temp: String, target: String },
+    CreateDir(String),
+    CreateFile(String),
+    Remove(String),
+}
+
+impl Step {
+    fn encode(&self) -> String {
+        match self {
+            Step::Replace { temp, target } => format!("replace\t{}\t{}", temp, target),
+            Step::CreateDir(p) => format!("mkdir\t{}", p),
+            Step::CreateFile(p) => format!("create\t{}", p),
+            Step::Remove(p) => format!("remove\t{}", p),
+        }
+    }
+
+    fn parse(line: &str) -> Option<Step> {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let step = match fields.as_slice() {
+            ["replace", temp, target] => Step::Replace {
+                temp: temp.to_string(),
+                target: target.to_string(),
+            },
+            ["mkdir", p] => Step::CreateDir(p.to_string()),
+            ["create", p] => Step::CreateFile(p.to_string()),
+            ["remove", p] => Step::Remove(p.to_string()),
+            _ => return None,
+        };
+        Some(step)
+    }
+
+    // Function to carry out the step in `root`, the root directory of its
+    // disk. A step that was already carried out is left as it is, so a
+    // journal can be replayed any number of times.
+    pub(crate) fn apply(&self, root: &VDir<'_>) -> Result<()> {
+        match self {
+            Step::Replace { temp, target } => {
+                // Without the temporary file the replacement already happened
+                if root.open_file(temp).is_err() {
+                    return Ok(());
+                }
+                ignore_missing(root.remove(target)).at(target)?;
+                root.rename(temp, root, target).at(target)
+            }
+            Step::CreateDir(p) if root.open_dir(p).is_err() => root.create_dir(p).map(drop).at(p),
+            Step::CreateFile(p) if root.open_file(p).is_err() => root.create_file(p).map(drop).at(p),
+            Step::Remove(p) => ignore_missing(root.remove(p)).at(p),
+            Step::CreateDir(_) | Step::CreateFile(_) => Ok(()),
+        }
+    }
+
+    // Function to undo what is done before a journal is committed, which is
+    // only writing temporary files
+    pub(crate) fn roll_back(&self, root: &VDir<'_>) -> Result<()> {
+        match self {
+            Step::Replace { temp, .. } => ignore_missing(root.remove(temp)).at(temp),
+            _ => Ok(()),
+        }
+    }
+}
+
+fn ignore_missing(result: io::Result<()>) -> io::Result<()> {
+    match result {
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        result => result,
+    }
+}
+
+// Path of the journal for `disk`, on the root disk
+pub(crate) fn path(disk: &str) -> String {
+    format!("internal/{}.journal", disk)
+}
+
+// Path of the temporary file that stands in for `target` until it replaces it
+pub(crate) fn temp_path(target: &str) -> String {
+    match target.rsplit_once('/') {
+        Some((dir, name)) => format!("{}/.{}.rnix-tmp", dir, name),
+        None => format!(".{}.rnix-tmp", target),
+    }
+}
+
+pub(crate) fn encode(steps: &[Step]) -> String {
+    steps.iter().map(|step| step.encode() + "\n").collect()
+}
+
+pub(crate) fn encode_commit() -> String {
+    format!("{}\n", COMMIT)
+}
+
+// Function to read a journal back into its steps and whether it was
+// committed. Reading stops at a line cut short, which can only be the last
+// of a journal that was never committed.
+pub(crate) fn parse(text: &str) -> (Vec<Step>, bool) {
+    let mut steps = Vec::new();
+    for line in text.lines() {
+        if line == COMMIT {
+            return (steps, true);
+        }
+        match Step::parse(line) {
+            Some(step) => steps.push(step),
+            None => break,
+        }
+    }
+    (steps, false)
+}
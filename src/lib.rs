@@ -0,0 +1,41 @@
+//! Rnix simulates a small UNIX-like system on top of FAT disk images.
+//!
+//! [`System`] owns the root disk, the mount table and the account store;
+//! every operation returns a [`Result`] and never prints. The interactive
+//! shell in [`shell`] is a thin front end over it, running the commands
+//! held in a [`Registry`].
+
+pub mod alias;
+pub mod auth;
+pub mod builtins;
+pub mod clock;
+pub mod command;
+pub mod complete;
+pub mod console;
+pub mod disk;
+pub mod ed;
+pub mod env;
+pub mod error;
+pub mod external;
+mod fat;
+pub mod find;
+pub mod fsck;
+pub mod glob;
+pub mod history;
+mod journal;
+pub mod path;
+pub mod readline;
+pub mod shell;
+pub mod sparse;
+pub mod system;
+pub mod text;
+pub mod tokenizer;
+pub mod transfer;
+pub mod version;
+pub mod visual;
+
+pub use command::{Builtin, Command, Registry};
+pub use console::{Console, MemoryConsole, TtyConsole};
+pub use error::{Result, RnixError};
+pub use shell::Shell;
+pub use system::{DiskUsage, EntryInfo, Metadata, Session, System};
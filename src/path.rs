@@ -0,0 +1,45 @@
+// Path handling for the virtual filesystem.
+//
+// Paths inside rnix are always '/' separated. They are resolved against the
+// session's working directory into a normalized absolute form ("/", "/home",
+// "/volumes/disk1/notes") before being routed to a mounted filesystem.
+
+// Function to resolve `path` against `cwd` into a normalized absolute path
+pub fn resolve(cwd: &str, path: &str) -> String {
+    let mut parts: Vec<&str> = Vec::new();
+    let joined;
+    let full = if path.starts_with('/') {
+        path
+    } else {
+        joined = format!("{}/{}", cwd, path);
+        &joined
+    };
+    for part in full.split('/') {
+        match part {
+            "" | "." => {}
+            ".." => {
+                parts.pop();
+            }
+            _ => parts.push(part),
+        }
+    }
+    format!("/{}", parts.join("/"))
+}
+
+// Function to split a normalized absolute path into its parent and final name
+pub fn split(path: &str) -> (&str, &str) {
+    match path.rfind('/') {
+        Some(0) => ("/", &path[1..]),
+        Some(idx) => (&path[..idx], &path[idx + 1..]),
+        None => ("/", path),
+    }
+}
+
+// Function to join a directory and a name without doubling separators
+pub fn join(dir: &str, name: &str) -> String {
+    if dir.ends_with('/') {
+        format!("{}{}", dir, name)
+    } else {
+        format!("{}/{}", dir, name)
+    }
+}
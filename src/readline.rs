@@ -0,0 +1,377 @@
+// Interactive line editing for the shell prompt: cursor movement, history
+// recall and reverse-i-search. The editor is a pure state machine fed with
+// decoded keys, so it can be driven by the terminal or by tests alike.
+
+use crate::complete::{common_prefix, Completer};
+use crate::history::History;
+
+// A decoded keypress
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Ctrl(char),
+    Enter,
+    Tab,
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Up,
+    Down,
+    Home,
+    End,
+    PageUp,
+    PageDown,
+    Esc,
+    Unknown,
+}
+
+// Turns raw terminal bytes into keys, buffering partial escape and UTF-8 sequences
+#[derive(Debug, Default)]
+pub struct KeyDecoder {
+    pending: Vec<u8>,
+}
+
+impl KeyDecoder {
+    pub fn new() -> KeyDecoder {
+        KeyDecoder::default()
+    }
+
+    // Feed one byte; returns a key once a full sequence has been seen
+    pub fn feed(&mut self, byte: u8) -> Option<Key> {
+        self.pending.push(byte);
+        let key = decode(&self.pending)?;
+        self.pending.clear();
+        Some(key)
+    }
+
+    // True while the decoder is in the middle of a sequence
+    pub fn is_pending(&self) -> bool {
+        !self.pending.is_empty()
+    }
+
+    // Give up on a partial sequence (a lone Esc press)
+    pub fn flush(&mut self) -> Option<Key> {
+        if self.pending.is_empty() {
+            return None;
+        }
+        let key = if self.pending == [0x1b] { Key::Esc } else { Key::Unknown };
+        self.pending.clear();
+        Some(key)
+    }
+}
+
+fn decode(bytes: &[u8]) -> Option<Key> {
+    match bytes {
+        [b'\r'] | [b'\n'] => Some(Key::Enter),
+        [b'\t'] => Some(Key::Tab),
+        [0x7f] | [0x08] => Some(Key::Backspace),
+        [c] if *c < 0x20 && *c != 0x1b => Some(Key::Ctrl((c + b'a' - 1) as char)),
+        [0x1b] => None,
+        [0x1b, b'[' | b'O'] => None,
+        [0x1b, b'[' | b'O', b'A'] => Some(Key::Up),
+        [0x1b, b'[' | b'O', b'B'] => Some(Key::Down),
+        [0x1b, b'[' | b'O', b'C'] => Some(Key::Right),
+        [0x1b, b'[' | b'O', b'D'] => Some(Key::Left),
+        [0x1b, b'[' | b'O', b'H'] => Some(Key::Home),
+        [0x1b, b'[' | b'O', b'F'] => Some(Key::End),
+        [0x1b, b'[', rest @ ..] => {
+            let last = *rest.last()?;
+            if last.is_ascii_digit() || last == b';' {
+                return None;
+            }
+            Some(match rest {
+                [b'1' | b'7', b'~'] => Key::Home,
+                [b'4' | b'8', b'~'] => Key::End,
+                [b'3', b'~'] => Key::Delete,
+                [b'5', b'~'] => Key::PageUp,
+                [b'6', b'~'] => Key::PageDown,
+                _ => Key::Unknown,
+            })
+        }
+        [0x1b, _, ..] => Some(Key::Unknown),
+        [first, ..] if *first >= 0x80 => {
+            let expected = match first {
+                0xc0..=0xdf => 2,
+                0xe0..=0xef => 3,
+                0xf0..=0xf7 => 4,
+                _ => return Some(Key::Unknown),
+            };
+            if bytes.len() < expected {
+                return None;
+            }
+            match std::str::from_utf8(bytes) {
+                Ok(s) => s.chars().next().map(Key::Char),
+                Err(_) => Some(Key::Unknown),
+            }
+        }
+        [c] => Some(Key::Char(*c as char)),
+        _ => Some(Key::Unknown),
+    }
+}
+
+// What the caller should do after feeding a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Pending,
+    Submit(String),
+    Eof,
+    Interrupted,
+    ClearScreen,
+    // Tab was pressed; the caller should call `complete`
+    Complete,
+}
+
+// State of an active Ctrl-R search
+#[derive(Debug, Clone)]
+struct Search {
+    query: String,
+    found: Option<usize>,
+}
+
+// Editing state for one prompt
+pub struct LineEditor<'h> {
+    history: &'h History,
+    buffer: Vec<char>,
+    cursor: usize,
+    // History number being shown while browsing with Up/Down
+    browsing: Option<usize>,
+    // The line being typed before history browsing started
+    draft: Vec<char>,
+    search: Option<Search>,
+}
+
+impl<'h> LineEditor<'h> {
+    pub fn new(history: &'h History) -> LineEditor<'h> {
+        LineEditor {
+            history,
+            buffer: Vec::new(),
+            cursor: 0,
+            browsing: None,
+            draft: Vec::new(),
+            search: None,
+        }
+    }
+
+    pub fn line(&self) -> String {
+        self.buffer.iter().collect()
+    }
+
+    // Cursor position in characters
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn is_searching(&self) -> bool {
+        self.search.is_some()
+    }
+
+    pub fn feed(&mut self, key: Key) -> Outcome {
+        if self.search.is_some() {
+            return self.feed_search(key);
+        }
+        match key {
+            Key::Char(c) => self.insert(c),
+            Key::Enter => return Outcome::Submit(self.line()),
+            Key::Backspace | Key::Ctrl('h') if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.buffer.remove(self.cursor);
+            }
+            Key::Delete if self.cursor < self.buffer.len() => {
+                self.buffer.remove(self.cursor);
+            }
+            Key::Ctrl('d') => {
+                if self.buffer.is_empty() {
+                    return Outcome::Eof;
+                }
+                if self.cursor < self.buffer.len() {
+                    self.buffer.remove(self.cursor);
+                }
+            }
+            Key::Left | Key::Ctrl('b') => self.cursor = self.cursor.saturating_sub(1),
+            Key::Right | Key::Ctrl('f') => self.cursor = (self.cursor + 1).min(self.buffer.len()),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.buffer.len(),
+            Key::Ctrl('k') => self.buffer.truncate(self.cursor),
+            Key::Ctrl('u') => {
+                self.buffer.drain(..self.cursor);
+                self.cursor = 0;
+            }
+            Key::Ctrl('w') => {
+                let mut start = self.cursor;
+                while start > 0 && self.buffer[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                while start > 0 && !self.buffer[start - 1].is_whitespace() {
+                    start -= 1;
+                }
+                self.buffer.drain(start..self.cursor);
+                self.cursor = start;
+            }
+            Key::Up | Key::Ctrl('p') => self.recall_older(),
+            Key::Down | Key::Ctrl('n') => self.recall_newer(),
+            Key::Ctrl('r') => {
+                self.search = Some(Search {
+                    query: String::new(),
+                    found: None,
+                })
+            }
+            Key::Ctrl('c') => return Outcome::Interrupted,
+            Key::Ctrl('l') => return Outcome::ClearScreen,
+            Key::Tab => return Outcome::Complete,
+            _ => {}
+        }
+        Outcome::Pending
+    }
+
+    // Complete the word under the cursor. A single match is inserted in full,
+    // several matches are narrowed to their common prefix; when that adds
+    // nothing the matches are returned so the caller can list them.
+    pub fn complete(&mut self, completer: &dyn Completer) -> Vec<String> {
+        let line = self.line();
+        let completion = completer.complete(&line, self.cursor);
+        let start = completion.start.min(self.cursor);
+        let word: String = self.buffer[start..self.cursor].iter().collect();
+        let replacement = match completion.candidates.as_slice() {
+            [] => return Vec::new(),
+            [only] if only.ends_with('/') => only.clone(),
+            [only] => format!("{} ", only),
+            many => {
+                let prefix = common_prefix(many);
+                if prefix.chars().count() <= word.chars().count() {
+                    return completion.candidates;
+                }
+                prefix
+            }
+        };
+        self.buffer.splice(start..self.cursor, replacement.chars());
+        self.cursor = start + replacement.chars().count();
+        Vec::new()
+    }
+
+    fn insert(&mut self, c: char) {
+        self.buffer.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn show(&mut self, line: &str) {
+        self.buffer = line.chars().collect();
+        self.cursor = self.buffer.len();
+    }
+
+    fn recall_older(&mut self) {
+        let next = match self.browsing {
+            None if self.history.is_empty() => return,
+            None => {
+                self.draft = self.buffer.clone();
+                self.history.len()
+            }
+            Some(1) => return,
+            Some(number) => number - 1,
+        };
+        if let Some(line) = self.history.get(next) {
+            self.browsing = Some(next);
+            self.show(line);
+        }
+    }
+
+    fn recall_newer(&mut self) {
+        match self.browsing {
+            None => {}
+            Some(number) if number >= self.history.len() => {
+                self.browsing = None;
+                self.buffer = std::mem::take(&mut self.draft);
+                self.cursor = self.buffer.len();
+            }
+            Some(number) => {
+                if let Some(line) = self.history.get(number + 1) {
+                    self.browsing = Some(number + 1);
+                    self.show(line);
+                }
+            }
+        }
+    }
+
+    fn feed_search(&mut self, key: Key) -> Outcome {
+        let mut search = self.search.take().expect("search state");
+        match key {
+            Key::Char(c) => {
+                search.query.push(c);
+                let before = search.found.map_or(self.history.len() + 1, |n| n + 1);
+                search.found = self.history.search_back(&search.query, before);
+            }
+            Key::Backspace => {
+                search.query.pop();
+                search.found = if search.query.is_empty() {
+                    None
+                } else {
+                    self.history.search_back(&search.query, self.history.len() + 1)
+                };
+            }
+            Key::Ctrl('r') => {
+                let before = search.found.unwrap_or(self.history.len() + 1);
+                if let Some(found) = self.history.search_back(&search.query, before) {
+                    search.found = Some(found);
+                }
+            }
+            Key::Ctrl('g') | Key::Ctrl('c') => return Outcome::Pending,
+            Key::Enter => {
+                if let Some(line) = search.found.and_then(|n| self.history.get(n)) {
+                    self.show(line);
+                }
+                return Outcome::Submit(self.line());
+            }
+            other => {
+                // Any other key accepts the match and is then handled normally
+                if let Some(line) = search.found.and_then(|n| self.history.get(n)) {
+                    self.show(line);
+                }
+                return self.feed(other);
+            }
+        }
+        self.search = Some(search);
+        Outcome::Pending
+    }
+
+    // Escape sequence that redraws the current line after `prompt`
+    pub fn render(&self, prompt: &str) -> String {
+        let (text, cursor): (String, usize) = match &self.search {
+            Some(search) => {
+                let found = search.found.and_then(|n| self.history.get(n)).unwrap_or("");
+                let head = format!("(reverse-i-search)`{}': ", search.query);
+                let cursor = head.chars().count() + found.chars().count();
+                (format!("{}{}", head, found), cursor)
+            }
+            None => (
+                format!("{}{}", prompt, self.line()),
+                visible_width(prompt) + self.cursor,
+            ),
+        };
+        let total = visible_width(&text);
+        let mut out = format!("\r{}\x1b[K", text);
+        if total > cursor {
+            out.push_str(&format!("\x1b[{}D", total - cursor));
+        }
+        out
+    }
+}
+
+// Number of terminal columns a string takes, ignoring ANSI escape sequences
+pub fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            if chars.next() == Some('[') {
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() || c == '~' {
+                        break;
+                    }
+                }
+            }
+        } else if !c.is_control() {
+            width += 1;
+        }
+    }
+    width
+}
@@ -0,0 +1,494 @@
+use std::io;
+
+use crate::alias::{parse_definition, split_commands, Definition, MAX_FUNCTION_DEPTH};
+use crate::command::{Command, Context, Registry};
+use crate::complete::ShellCompleter;
+use crate::console::Console;
+use crate::env::{expand_prompt, PromptInfo, DEFAULT_PS1};
+use crate::error::{Result, RnixError};
+use crate::history::{History, HISTORY_FILE};
+use crate::path;
+use crate::system::{Session, System};
+use crate::glob;
+use crate::tokenizer::{pipeline_with, Word};
+
+// System-wide startup script, run on every login before the user's own
+pub const PROFILE: &str = "/internal/etc/profile";
+
+// Per-user scripts in the home directory, run after login and at logout
+pub const RC_FILE: &str = ".rnixrc";
+pub const LOGOUT_FILE: &str = ".rnix_logout";
+
+// What the REPL should do after a command line has run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flow {
+    Continue,
+    Exit(i32),
+}
+
+// Interactive front end over a `System`, talking to the user through a `Console`
+pub struct Shell<C: Console> {
+    system: System,
+    console: C,
+    history: History,
+    registry: Registry,
+    status: i32,
+}
+
+impl<C: Console> Shell<C> {
+    pub fn new(system: System, console: C) -> Shell<C> {
+        Shell {
+            system,
+            console,
+            history: History::new(),
+            registry: Registry::with_builtins(),
+            status: 0,
+        }
+    }
+
+    pub fn system(&self) -> &System {
+        &self.system
+    }
+
+    pub fn system_mut(&mut self) -> &mut System {
+        &mut self.system
+    }
+
+    pub fn console(&self) -> &C {
+        &self.console
+    }
+
+    pub fn console_mut(&mut self) -> &mut C {
+        &mut self.console
+    }
+
+    // Commands this shell understands
+    pub fn registry(&self) -> &Registry {
+        &self.registry
+    }
+
+    pub fn registry_mut(&mut self) -> &mut Registry {
+        &mut self.registry
+    }
+
+    // Add a command, replacing any built-in with the same name
+    pub fn register<T: Command + 'static>(&mut self, command: T) {
+        self.registry.register(command);
+    }
+
+    // Exit status of the last command line, 0 on success
+    pub fn last_status(&self) -> i32 {
+        self.status
+    }
+
+    // Command history of the current (or last) session
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    // Run first-time setup if needed, then loop over login and command prompts
+    // until `exit` or end of input. Returns the exit status the process should end with.
+    pub fn run(&mut self) -> io::Result<i32> {
+        self.console.clear()?;
+        if self.system.needs_setup() && !self.setup()? {
+            return Ok(self.status);
+        }
+
+        loop {
+            let mut session = match self.login()? {
+                Some(Some(session)) => session,
+                Some(None) => continue,
+                None => return Ok(self.status),
+            };
+            self.console.clear()?;
+            writeln!(self.console, "Rnix Terminal --> /dev/disk0 mounted as root")?;
+
+            self.load_history(&session);
+            let rc = path::join(&session.home(), RC_FILE);
+            let mut flow = self.source(&mut session, PROFILE)?;
+            if flow == Flow::Continue {
+                flow = self.source(&mut session, &rc)?;
+            }
+            let status = loop {
+                if let Flow::Exit(status) = flow {
+                    break status;
+                }
+                let prompt = self.prompt(&session);
+                let mut commands = self.registry.names();
+                commands.extend(session.aliases().iter().map(|(name, _)| name.to_string()));
+                commands.extend(session.functions().names().map(str::to_string));
+                let completer = ShellCompleter::new(&self.system, &session, commands);
+                let input = match self.console.read_command(&prompt, &self.history, &completer)? {
+                    Some(input) => input,
+                    None => break self.status,
+                };
+                flow = self.interactive(&mut session, &input)?;
+            };
+            let logout = path::join(&session.home(), LOGOUT_FILE);
+            self.source(&mut session, &logout)?;
+            self.save_history(&session)?;
+            return Ok(status);
+        }
+    }
+
+    // The command prompt: PS1 with its escapes expanded
+    fn prompt(&self, session: &Session) -> String {
+        let ps1 = session.env().get("PS1").unwrap_or(DEFAULT_PS1);
+        let home = session.home();
+        let info = PromptInfo {
+            user: session.user(),
+            cwd: session.cwd(),
+            home: &home,
+            disk: self.system.disk_of(session.cwd()),
+            status: self.status,
+        };
+        expand_prompt(ps1, &info)
+    }
+
+    // Run a line typed at the prompt: expand history references, record it, then execute it
+    fn interactive(&mut self, session: &mut Session, input: &str) -> io::Result<Flow> {
+        let line = match self.history.expand(input) {
+            Ok(Some(expanded)) => {
+                writeln!(self.console, "{}", expanded)?;
+                expanded
+            }
+            Ok(None) => input.to_string(),
+            Err(err) => {
+                writeln!(self.console, "rnix: {}", err)?;
+                self.status = err.status();
+                return Ok(Flow::Continue);
+            }
+        };
+        self.history.push(&line);
+        self.execute(session, &line)
+    }
+
+    fn load_history(&mut self, session: &Session) {
+        self.history = match self.system.read_file(session, &history_path(session)) {
+            Ok(contents) => History::parse(&String::from_utf8_lossy(&contents)),
+            Err(_) => History::new(),
+        };
+    }
+
+    fn save_history(&mut self, session: &Session) -> io::Result<()> {
+        if let Err(err) = save_history(&self.system, session, &self.history) {
+            writeln!(self.console, "history: {}", err)?;
+        }
+        Ok(())
+    }
+
+    // Prompt for the first user account. Returns false if input ended.
+    fn setup(&mut self) -> io::Result<bool> {
+        writeln!(self.console, "RNIX | Setting up user account:")?;
+        let username = match self.console.prompt("Enter user username: ")? {
+            Some(username) => username,
+            None => return Ok(false),
+        };
+        let password = match self.console.prompt_password("Enter user password: ")? {
+            Some(password) => password,
+            None => return Ok(false),
+        };
+        match self.system.setup(&username, &password) {
+            Ok(()) => writeln!(self.console, "User account created.")?,
+            Err(err) => {
+                self.status = err.status();
+                writeln!(self.console, "setup: {}", err)?;
+            }
+        }
+        Ok(true)
+    }
+
+    // One login attempt: `None` if input ended, `Some(None)` if the credentials were rejected
+    fn login(&mut self) -> io::Result<Option<Option<Session>>> {
+        write!(self.console, "-----------------\nRNIX | LogIn\n-----------------\n")?;
+        let username = match self.console.prompt("Enter username: ")? {
+            Some(username) => username,
+            None => return Ok(None),
+        };
+        let password = match self.console.prompt_password("Enter password: ")? {
+            Some(password) => password,
+            None => return Ok(None),
+        };
+        match self.system.authenticate(&username, &password) {
+            Ok(session) => Ok(Some(Some(session))),
+            Err(RnixError::AuthFailed) => {
+                writeln!(self.console, "Invalid username or password. Please try again.")?;
+                Ok(Some(None))
+            }
+            Err(err) => {
+                writeln!(self.console, "login: {}", err)?;
+                Ok(Some(None))
+            }
+        }
+    }
+
+    // Run a single command line for `session`, reporting any error and recording the exit status
+    pub fn execute(&mut self, session: &mut Session, input: &str) -> io::Result<Flow> {
+        let mut ctx = self.context(session);
+        let flow = run_line(&mut ctx, input)?;
+        self.status = ctx.status;
+        Ok(flow)
+    }
+
+    // Run the commands in a script file from the image, if it exists
+    fn source(&mut self, session: &mut Session, path: &str) -> io::Result<Flow> {
+        if !self.system.is_file(session, path) {
+            return Ok(Flow::Continue);
+        }
+        let mut ctx = self.context(session);
+        let flow = match source(&mut ctx, path) {
+            Ok(flow) => flow,
+            Err(err) => {
+                writeln!(ctx.console, "{}: {}", path, err)?;
+                ctx.status = err.status();
+                Flow::Continue
+            }
+        };
+        self.status = ctx.status;
+        Ok(flow)
+    }
+
+    fn context<'a>(&'a mut self, session: &'a mut Session) -> Context<'a> {
+        Context {
+            system: &mut self.system,
+            console: &mut self.console,
+            session,
+            history: &mut self.history,
+            registry: &self.registry,
+            status: self.status,
+            elevated: false,
+            params: Vec::new(),
+            depth: 0,
+            stdin: None,
+            globbed: false,
+        }
+    }
+}
+
+// Function to tokenize and run one command line, reporting any error on the
+// console and leaving the line's exit status in `ctx.status`
+pub(crate) fn run_line(ctx: &mut Context<'_>, input: &str) -> io::Result<Flow> {
+    match parse_definition(input) {
+        Ok(Definition::None) => {}
+        Ok(Definition::Complete(name, body)) => {
+            ctx.session.functions_mut().define(&name, body);
+            ctx.status = 0;
+            return Ok(Flow::Continue);
+        }
+        Ok(Definition::Open(..)) => {
+            return report(ctx, "rnix", RnixError::InvalidArgument("syntax error: missing `}'".to_string()));
+        }
+        Err(err) => return report(ctx, "rnix", err),
+    }
+
+    let input = ctx.session.aliases().expand(input);
+    let status = ctx.status;
+    let env = ctx.session.env();
+    let params = &ctx.params;
+    let lookup = |name: &str| match name {
+        "?" => Some(status.to_string()),
+        "#" => Some(params.len().to_string()),
+        "@" | "*" => Some(params.join(" ")),
+        _ if name.bytes().all(|b| b.is_ascii_digit()) => {
+            let index: usize = name.parse().ok()?;
+            index.checked_sub(1).and_then(|index| params.get(index)).cloned()
+        }
+        _ => env.get(name).map(str::to_string),
+    };
+    let commands = match pipeline_with(&input, &lookup) {
+        Ok(commands) => commands,
+        Err(err) => {
+            ctx.console.write_error(&format!("rnix: {}\n", err))?;
+            ctx.status = err.status();
+            return Ok(Flow::Continue);
+        }
+    };
+
+    // As in sh, every command but the last runs for its output alone: how it
+    // exits is ignored, and the line's status is the last command's
+    let (last, first) = commands.split_last().expect("a line has at least one command");
+    let mut input = None;
+    for words in first {
+        let mut pipe = Pipe {
+            console: &mut *ctx.console,
+            output: String::new(),
+        };
+        let mut stage = Context {
+            system: &mut *ctx.system,
+            console: &mut pipe,
+            session: &mut *ctx.session,
+            history: &mut *ctx.history,
+            registry: ctx.registry,
+            status: ctx.status,
+            elevated: ctx.elevated,
+            params: ctx.params.clone(),
+            depth: ctx.depth,
+            stdin: input.take(),
+            globbed: false,
+        };
+        run_command(&mut stage, words)?;
+        input = Some(pipe.output);
+    }
+    let saved = std::mem::replace(&mut ctx.stdin, input);
+    let flow = run_command(ctx, last);
+    ctx.stdin = saved;
+    flow
+}
+
+// Function to glob-expand and run one simple command
+fn run_command(ctx: &mut Context<'_>, words: &[Word]) -> io::Result<Flow> {
+    // Pathname expansion; a pattern that matches nothing is passed on as typed
+    let mut expanded = Vec::new();
+    ctx.globbed = false;
+    for word in words {
+        let matches = match &word.pattern {
+            Some(pattern) => glob::expand(ctx.system, ctx.session, pattern),
+            None => Vec::new(),
+        };
+        if matches.is_empty() {
+            expanded.push(word.text.clone());
+        } else {
+            ctx.globbed = true;
+            expanded.extend(matches);
+        }
+    }
+    let words = expanded;
+    let words: Vec<&str> = words.iter().map(String::as_str).collect();
+    let command = words.first().copied().unwrap_or("");
+
+    if let Some(body) = ctx.session.functions().get(command) {
+        let body = body.to_vec();
+        return call_function(ctx, command, &body, &words[1..]);
+    }
+
+    let registry = ctx.registry;
+    match registry.dispatch(ctx, &words) {
+        Ok(flow) => {
+            ctx.status = 0;
+            Ok(flow)
+        }
+        Err(err) => {
+            match err {
+                RnixError::Reported(_) => {}
+                RnixError::UnknownCommand(_) => ctx.console.write_error(&format!("rnix: {}\n", err))?,
+                _ => ctx.console.write_error(&format!("{}: {}\n", command, err))?,
+            }
+            ctx.status = err.status();
+            Ok(Flow::Continue)
+        }
+    }
+}
+
+// Console for a command whose output feeds the next one in a pipeline:
+// writes are kept, while errors, reading and prompting still go to the terminal
+struct Pipe<'a> {
+    console: &'a mut dyn Console,
+    output: String,
+}
+
+impl Console for Pipe<'_> {
+    fn read_line(&mut self) -> io::Result<Option<String>> {
+        self.console.read_line()
+    }
+
+    fn read_password(&mut self) -> io::Result<Option<String>> {
+        self.console.read_password()
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.output.push_str(text);
+        Ok(())
+    }
+
+    fn write_error(&mut self, text: &str) -> io::Result<()> {
+        self.console.write_error(text)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn prompt(&mut self, message: &str) -> io::Result<Option<String>> {
+        self.console.prompt(message)
+    }
+
+    fn prompt_password(&mut self, message: &str) -> io::Result<Option<String>> {
+        self.console.prompt_password(message)
+    }
+}
+
+// Function to print a command's error and record its exit status
+fn report(ctx: &mut Context<'_>, command: &str, err: RnixError) -> io::Result<Flow> {
+    ctx.console.write_error(&format!("{}: {}\n", command, err))?;
+    ctx.status = err.status();
+    Ok(Flow::Continue)
+}
+
+// Function to run a shell function's body with `args` as its positional parameters
+fn call_function(ctx: &mut Context<'_>, name: &str, body: &[String], args: &[&str]) -> io::Result<Flow> {
+    if ctx.depth >= MAX_FUNCTION_DEPTH {
+        let err = RnixError::InvalidArgument(format!("maximum function nesting level exceeded ({})", MAX_FUNCTION_DEPTH));
+        return report(ctx, name, err);
+    }
+    let saved = std::mem::replace(&mut ctx.params, args.iter().map(|arg| arg.to_string()).collect());
+    ctx.depth += 1;
+    let mut flow = Ok(Flow::Continue);
+    for line in body {
+        flow = run_line(ctx, line);
+        if !matches!(flow, Ok(Flow::Continue)) {
+            break;
+        }
+    }
+    ctx.depth -= 1;
+    ctx.params = saved;
+    flow
+}
+
+// Function to run each line of a script file in the image; blank lines and
+// `#` comments are skipped, function definitions may span lines, and `exit`
+// stops the script
+pub(crate) fn source(ctx: &mut Context<'_>, path: &str) -> Result<Flow> {
+    let contents = ctx.system.read_file(ctx.session, path)?;
+    let contents = String::from_utf8_lossy(&contents);
+    let mut lines = contents.lines();
+    while let Some(line) = lines.next() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if let Ok(Definition::Open(name, mut body)) = parse_definition(line) {
+            let mut closed = false;
+            for line in lines.by_ref() {
+                let line = line.trim();
+                if let Some(last) = line.strip_suffix('}') {
+                    body.extend(split_commands(last));
+                    closed = true;
+                    break;
+                }
+                body.extend(split_commands(line));
+            }
+            if !closed {
+                return Err(RnixError::InvalidArgument(format!("{}: missing `}}' at end of file", name)));
+            }
+            ctx.session.functions_mut().define(&name, body);
+            continue;
+        }
+        if let Flow::Exit(status) = run_line(ctx, line)? {
+            return Ok(Flow::Exit(status));
+        }
+    }
+    Ok(Flow::Continue)
+}
+
+fn history_path(session: &Session) -> String {
+    path::join(&session.home(), HISTORY_FILE)
+}
+
+// Function to write `history` to the user's history file, creating it if needed
+pub(crate) fn save_history(system: &System, session: &Session, history: &History) -> Result<()> {
+    let path = history_path(session);
+    if !system.is_file(session, &path) {
+        system.touch(session, &path)?;
+    }
+    system.write_file(session, &path, history.serialize().as_bytes())
+}
@@ -0,0 +1,193 @@
+// How disk images take up space on the host. Images are sparse files where
+// the host allows it, so the parts of a disk never written cost nothing, and
+// `compact` gives back the space of clusters that have been freed since.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::disk::format_size;
+use crate::error::{IoResultExt, Result};
+use crate::fat::Volume;
+
+// Holes are punched a host block at a time
+const BLOCK: usize = 4096;
+// How much of an image is read at once when looking for zeros
+const CHUNK: usize = 1024 * 1024;
+
+// How big an image looks, and how much of the host's storage it really takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImageSize {
+    pub apparent: u64,
+    pub actual: u64,
+}
+
+pub fn image_size(path: &Path) -> io::Result<ImageSize> {
+    let metadata = fs::metadata(path)?;
+    #[cfg(unix)]
+    let actual = std::os::unix::fs::MetadataExt::blocks(&metadata) * 512;
+    #[cfg(not(unix))]
+    let actual = metadata.len();
+    Ok(ImageSize {
+        apparent: metadata.len(),
+        actual,
+    })
+}
+
+// Function to list the disk images (`*.img`) in `dir` by name, with their sizes
+pub fn list_images(dir: &Path) -> Result<Vec<(String, ImageSize)>> {
+    let display = dir.display().to_string();
+    let mut images = Vec::new();
+    for entry in fs::read_dir(dir).at(&display)? {
+        let path = entry.at(&display)?.path();
+        if path.is_file() && path.extension().is_some_and(|ext| ext.eq_ignore_ascii_case("img")) {
+            let name = path.file_name().unwrap_or_default().to_string_lossy().to_string();
+            images.push((name, image_size(&path).at(&path.display().to_string())?));
+        }
+    }
+    images.sort_by(|(a, _), (b, _)| a.cmp(b));
+    Ok(images)
+}
+
+// Function to lay out `list_images` as a table, in 1K blocks or with `human`
+// sizes as df does
+pub fn image_table(dir: &Path, human: bool) -> Result<Vec<String>> {
+    let mut lines = vec![format!(
+        "{:<16} {:>10} {:>10}",
+        "Image",
+        if human { "Apparent" } else { "Apparent-K" },
+        if human { "Actual" } else { "Actual-K" }
+    )];
+    for (name, size) in list_images(dir)? {
+        lines.push(format!(
+            "{:<16} {:>10} {:>10}",
+            name,
+            format_size(size.apparent, human),
+            format_size(size.actual, human)
+        ));
+    }
+    Ok(lines)
+}
+
+// Function to give the host's storage for `len` bytes of `file` at `offset`
+// back, leaving zeros. Returns false where the host can't.
+pub fn punch_hole(file: &File, offset: u64, len: u64) -> io::Result<bool> {
+    #[cfg(target_os = "linux")]
+    {
+        use std::os::unix::io::AsRawFd;
+        let mode = libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE;
+        // SAFETY: fallocate only reads its arguments
+        let result = unsafe { libc::fallocate(file.as_raw_fd(), mode, offset as libc::off_t, len as libc::off_t) };
+        if result == 0 {
+            return Ok(true);
+        }
+        let err = io::Error::last_os_error();
+        match err.raw_os_error() {
+            Some(libc::EOPNOTSUPP) | Some(libc::ENOSYS) => Ok(false),
+            _ => Err(err),
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = (file, offset, len);
+        Ok(false)
+    }
+}
+
+// Function to punch holes where `range` of `file` holds whole blocks of
+// zeros, stopping early where the host can't
+pub fn punch_zeros(file: &mut File, range: Range<u64>) -> io::Result<()> {
+    let mut buffer = vec![0; CHUNK];
+    let mut zeros: Option<u64> = None;
+    let mut offset = range.start;
+    file.seek(SeekFrom::Start(offset))?;
+    while offset < range.end {
+        let len = CHUNK.min((range.end - offset) as usize);
+        file.read_exact(&mut buffer[..len])?;
+        for (index, block) in buffer[..len].chunks(BLOCK).enumerate() {
+            let at = offset + (index * BLOCK) as u64;
+            match (block.iter().all(|&byte| byte == 0), zeros) {
+                (true, None) => zeros = Some(at),
+                (false, Some(start)) => {
+                    if !punch_hole(file, start, at - start)? {
+                        return Ok(());
+                    }
+                    zeros = None;
+                }
+                _ => {}
+            }
+        }
+        offset += len as u64;
+    }
+    if let Some(start) = zeros {
+        punch_hole(file, start, range.end - start)?;
+    }
+    Ok(())
+}
+
+// Function to overwrite the parts of `range` that aren't zeros yet
+fn zero_fill(file: &mut File, range: Range<u64>) -> io::Result<()> {
+    let mut buffer = vec![0; CHUNK];
+    let mut offset = range.start;
+    while offset < range.end {
+        let len = CHUNK.min((range.end - offset) as usize);
+        file.seek(SeekFrom::Start(offset))?;
+        file.read_exact(&mut buffer[..len])?;
+        if buffer[..len].iter().any(|&byte| byte != 0) {
+            buffer[..len].fill(0);
+            file.seek(SeekFrom::Start(offset))?;
+            file.write_all(&buffer[..len])?;
+        }
+        offset += len as u64;
+    }
+    Ok(())
+}
+
+// What compacting an image did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Compacted {
+    // Bytes in free clusters, now zeros
+    pub free: u64,
+    pub before: ImageSize,
+    pub after: ImageSize,
+}
+
+impl fmt::Display for Compacted {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of free clusters zeroed; {} -> {} on the host ({} apparent)",
+            format_size(self.free, true),
+            format_size(self.before.actual, true),
+            format_size(self.after.actual, true),
+            format_size(self.after.apparent, true)
+        )
+    }
+}
+
+// Function to zero the free clusters of a disk image and punch holes
+// wherever it holds zeros, so the host only stores the clusters in use.
+// What the disk holds doesn't change, so this is safe on a mounted disk.
+pub fn compact(image: &Path) -> Result<Compacted> {
+    let display = image.display().to_string();
+    let before = image_size(image).at(&display)?;
+    let runs = Volume::open(image)?.free_runs();
+    let mut file = OpenOptions::new().read(true).write(true).open(image).at(&display)?;
+    let mut free = 0;
+    for (offset, len) in runs {
+        free += len;
+        if !punch_hole(&file, offset, len).at(&display)? {
+            zero_fill(&mut file, offset..offset + len).at(&display)?;
+        }
+    }
+    let len = file.metadata().at(&display)?.len();
+    punch_zeros(&mut file, 0..len).at(&display)?;
+    file.sync_all().at(&display)?;
+    Ok(Compacted {
+        free,
+        before,
+        after: image_size(image).at(&display)?,
+    })
+}
@@ -0,0 +1,993 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{File, OpenOptions};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use fatfs::{Dir, FatType, FileAttributes, FileSystem, FsOptions};
+
+use crate::alias::{Aliases, Functions};
+use crate::auth::{decode_accounts, encode_accounts, Account};
+use crate::clock::{Clock, TimeProvider};
+use crate::disk::{attribute_flags, createdisk, dformat, ocdi, DISK_IMAGE_SIZE};
+use crate::env::Environment;
+use crate::error::{IoResultExt, Result, RnixError};
+use crate::fat::{encode_date, encode_time, Volume, ENTRY_SIZE};
+use crate::journal::{self, Step};
+use crate::path;
+use crate::transfer::to_fat;
+
+// A FAT filesystem backed by a disk image on the host
+pub type DiskFs = FileSystem<File>;
+
+// A directory handle inside one of the mounted filesystems
+pub type VDir<'a> = Dir<'a, File>;
+
+// A file handle inside one of the mounted filesystems
+pub type VFile<'a> = fatfs::File<'a, File>;
+
+pub const ROOT_DISK: &str = "disk0";
+pub const MOUNTABLE_DISKS: [&str; 2] = ["disk1", "disk2"];
+pub const VOLUMES_DIR: &str = "/volumes";
+
+// Directories every root image has, parents first
+pub(crate) const LAYOUT: [&str; 5] = ["internal", "internal/bin", "internal/etc", "home", "volumes"];
+pub(crate) const SETUP_FLAG: &str = "internal/setup_completed.flag";
+pub(crate) const ROOT_ACCOUNT: &str = "internal/root";
+pub(crate) const USER_ACCOUNTS: &str = "internal/rnix";
+const DEFAULT_ROOT_PASSWORD: &str = "iloveapple";
+
+// A logged-in user, their working directory, variables, aliases and functions
+#[derive(Debug, Clone)]
+pub struct Session {
+    user: String,
+    cwd: String,
+    env: Environment,
+    aliases: Aliases,
+    functions: Functions,
+}
+
+impl Session {
+    pub fn user(&self) -> &str {
+        &self.user
+    }
+
+    pub fn cwd(&self) -> &str {
+        &self.cwd
+    }
+
+    pub fn env(&self) -> &Environment {
+        &self.env
+    }
+
+    pub fn env_mut(&mut self) -> &mut Environment {
+        &mut self.env
+    }
+
+    pub fn aliases(&self) -> &Aliases {
+        &self.aliases
+    }
+
+    pub fn aliases_mut(&mut self) -> &mut Aliases {
+        &mut self.aliases
+    }
+
+    pub fn functions(&self) -> &Functions {
+        &self.functions
+    }
+
+    pub fn functions_mut(&mut self) -> &mut Functions {
+        &mut self.functions
+    }
+
+    pub fn is_root(&self) -> bool {
+        self.user == "root"
+    }
+
+    // Root lives at "/", everyone else gets a directory under /home
+    pub fn home(&self) -> String {
+        if self.is_root() {
+            "/".to_string()
+        } else {
+            format!("/home/{}", self.user)
+        }
+    }
+
+    // Function to resolve a path typed by this user into an absolute path
+    pub fn resolve(&self, p: &str) -> String {
+        path::resolve(&self.cwd, p)
+    }
+}
+
+// Space on one disk, as `df` shows it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiskUsage {
+    pub disk: String,
+    pub mount_point: String,
+    pub fat_type: &'static str,
+    pub cluster_size: u64,
+    pub total: u64,
+    pub free: u64,
+}
+
+impl DiskUsage {
+    pub fn used(&self) -> u64 {
+        self.total - self.free
+    }
+
+    // Share of the disk in use, rounded up as df does
+    pub fn use_percent(&self) -> u64 {
+        if self.total == 0 {
+            return 0;
+        }
+        (self.used() * 100).div_ceil(self.total)
+    }
+}
+
+// A single directory listing entry
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EntryInfo {
+    pub name: String,
+    pub is_dir: bool,
+    pub len: u64,
+    pub attributes: FileAttributes,
+    pub modified: fatfs::DateTime,
+}
+
+impl EntryInfo {
+    pub fn flags(&self) -> String {
+        attribute_flags(self.attributes)
+    }
+}
+
+// What the directory entry of a file or directory records. The root of a
+// disk has no entry, so no attributes of its own or times.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    // Absolute path
+    pub path: String,
+    pub short_name: String,
+    pub is_dir: bool,
+    pub len: u64,
+    pub attributes: FileAttributes,
+    pub created: Option<fatfs::DateTime>,
+    pub modified: Option<fatfs::DateTime>,
+    pub accessed: Option<fatfs::Date>,
+}
+
+impl Metadata {
+    pub fn flags(&self) -> String {
+        attribute_flags(self.attributes)
+    }
+}
+
+// The attributes `attrib` may change
+pub const CHANGEABLE_ATTRIBUTES: FileAttributes = FileAttributes::from_bits_truncate(
+    FileAttributes::READ_ONLY.bits() | FileAttributes::HIDDEN.bits() | FileAttributes::SYSTEM.bits() | FileAttributes::ARCHIVE.bits(),
+);
+
+// The simulated machine: the root disk, the mount table and the account store
+pub struct System {
+    image_path: PathBuf,
+    image_dir: PathBuf,
+    root: DiskFs,
+    mounts: BTreeMap<String, DiskFs>,
+    // Mounted disks whose images were opened read-only
+    read_only: BTreeSet<String>,
+    clock: &'static Clock,
+}
+
+impl System {
+    // Open (creating and formatting if needed) the root disk image at `image_path`
+    pub fn open<P: AsRef<Path>>(image_path: P) -> Result<System> {
+        let image_path = image_path.as_ref();
+        let file = ocdi(image_path, DISK_IMAGE_SIZE)?;
+        drop(file);
+        dformat(image_path)?;
+
+        let file = OpenOptions::new().read(true).write(true).open(image_path)?;
+        let clock = Clock::leak();
+        let root = FileSystem::new(file, FsOptions::new().time_provider(clock))
+            .map_err(|err| RnixError::CorruptImage(format!("{}: {}", image_path.display(), err)))?;
+        let image_dir = match image_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir.to_path_buf(),
+            _ => PathBuf::from("."),
+        };
+
+        let system = System {
+            image_path: image_path.to_path_buf(),
+            image_dir,
+            root,
+            mounts: BTreeMap::new(),
+            read_only: BTreeSet::new(),
+            clock,
+        };
+        system.ensure_layout()?;
+        system.recover(ROOT_DISK)?;
+        Ok(system)
+    }
+
+    // Create the standard top-level directories if they don't exist
+    fn ensure_layout(&self) -> Result<()> {
+        let root_dir = self.root.root_dir();
+        for dir in LAYOUT {
+            if root_dir.open_dir(dir).is_err() {
+                root_dir.create_dir(dir)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Use `provider` for the current time from now on, for new timestamps on
+    // every disk as well as for `now`
+    pub fn set_time_provider(&self, provider: Arc<dyn TimeProvider>) {
+        self.clock.set(provider);
+    }
+
+    pub fn now(&self) -> SystemTime {
+        self.clock.now()
+    }
+
+    // Directory on the host holding the root image and the extra disk images
+    pub fn image_dir(&self) -> &Path {
+        &self.image_dir
+    }
+
+    // Host path of the image backing `disk_name`
+    pub fn disk_path(&self, disk_name: &str) -> PathBuf {
+        if disk_name.eq_ignore_ascii_case(ROOT_DISK) {
+            return self.image_path.clone();
+        }
+        self.image_dir.join(format!("{}.img", disk_name))
+    }
+
+    // ------------------------------------------------------------------
+    // Accounts
+    // ------------------------------------------------------------------
+
+    pub fn needs_setup(&self) -> bool {
+        self.root.root_dir().open_file(SETUP_FLAG).is_err()
+    }
+
+    // Create the root account (if missing) and the first user account
+    pub fn setup(&mut self, username: &str, password: &str) -> Result<()> {
+        if username.is_empty() || username.contains(':') || username.contains('/') {
+            return Err(RnixError::InvalidArgument(format!("invalid username '{}'", username)));
+        }
+        let mut files = Vec::new();
+        if self.root.root_dir().open_file(ROOT_ACCOUNT).is_err() {
+            let root = Account::new("root", DEFAULT_ROOT_PASSWORD)?;
+            files.push((ROOT_ACCOUNT, encode_accounts(&[root])));
+        }
+        let mut accounts = self.read_accounts(USER_ACCOUNTS)?;
+        accounts.retain(|account| account.username != username);
+        accounts.push(Account::new(username, password)?);
+        files.push((USER_ACCOUNTS, encode_accounts(&accounts)));
+
+        // The accounts, the home directory and the setup flag go in together
+        let mut steps = Vec::new();
+        for (file, _) in &files {
+            steps.push(self.replacement(&path::join("/", file))?.1);
+        }
+        steps.push(Step::CreateDir(format!("home/{}", username)));
+        steps.push(Step::CreateFile(SETUP_FLAG.to_string()));
+        self.transaction(ROOT_DISK, &steps, || {
+            for (step, (_, data)) in steps.iter().zip(&files) {
+                self.stage(ROOT_DISK, step, |file| Ok(file.write_all(data)?))?;
+            }
+            Ok(())
+        })
+    }
+
+    // Names of every account on the system, root first
+    pub fn users(&self) -> Result<Vec<String>> {
+        let mut users: Vec<String> = self
+            .read_accounts(ROOT_ACCOUNT)?
+            .into_iter()
+            .map(|account| account.username)
+            .collect();
+        users.extend(self.read_accounts(USER_ACCOUNTS)?.into_iter().map(|account| account.username));
+        Ok(users)
+    }
+
+    // A root session at "/" without a password, for maintenance tools that run
+    // instead of the shell (the binary's subcommands)
+    pub fn root_session(&self) -> Session {
+        Session {
+            user: "root".to_string(),
+            cwd: "/".to_string(),
+            env: Environment::standard("root", "/"),
+            aliases: Aliases::new(),
+            functions: Functions::new(),
+        }
+    }
+
+    // Check a username and password and start a session in the user's home
+    pub fn authenticate(&self, username: &str, password: &str) -> Result<Session> {
+        let file = if username == "root" { ROOT_ACCOUNT } else { USER_ACCOUNTS };
+        let valid = self
+            .read_accounts(file)?
+            .iter()
+            .any(|account| account.username == username && account.verify(password));
+        if !valid {
+            return Err(RnixError::AuthFailed);
+        }
+
+        let mut session = Session {
+            user: username.to_string(),
+            cwd: "/".to_string(),
+            env: Environment::new(),
+            aliases: Aliases::new(),
+            functions: Functions::new(),
+        };
+        let home = session.home();
+        if self.open_dir(&home).is_err() {
+            self.root.root_dir().create_dir(home.trim_start_matches('/'))?;
+        }
+        session.env = Environment::standard(username, &home);
+        session.cwd = home;
+        Ok(session)
+    }
+
+    fn read_accounts(&self, file: &str) -> Result<Vec<Account>> {
+        let mut contents = Vec::new();
+        match self.root.root_dir().open_file(file) {
+            Ok(mut f) => {
+                f.read_to_end(&mut contents)?;
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err.into()),
+        }
+        Ok(decode_accounts(&contents))
+    }
+
+    // Wipe the user accounts so that setup runs again on next start
+    pub fn reset_root(&mut self, session: &Session) -> Result<Vec<String>> {
+        if !session.is_root() {
+            return Err(RnixError::PermissionDenied(
+                "Only root can execute resetroot command.".to_string(),
+            ));
+        }
+        let root_dir = self.root.root_dir();
+        let removed: Vec<String> = [SETUP_FLAG, USER_ACCOUNTS]
+            .into_iter()
+            .filter(|file| root_dir.open_file(file).is_ok())
+            .map(str::to_string)
+            .collect();
+        let steps: Vec<Step> = removed.iter().cloned().map(Step::Remove).collect();
+        self.transaction(ROOT_DISK, &steps, || Ok(()))?;
+        Ok(removed)
+    }
+
+    // ------------------------------------------------------------------
+    // Mount table
+    // ------------------------------------------------------------------
+
+    // Disks currently mounted besides the root disk
+    pub fn mounted_disks(&self) -> Vec<String> {
+        self.mounts.keys().cloned().collect()
+    }
+
+    pub fn create_disk(&self, disk_name: &str) -> Result<PathBuf> {
+        if disk_name.is_empty() || disk_name.contains('/') || disk_name == ROOT_DISK {
+            return Err(RnixError::InvalidArgument(format!("invalid disk name '{}'", disk_name)));
+        }
+        let disk_path = self.disk_path(disk_name);
+        createdisk(&disk_path)?;
+        Ok(disk_path)
+    }
+
+    // Whether `disk_name` is mounted read-only
+    pub fn is_read_only(&self, disk_name: &str) -> bool {
+        self.read_only.contains(disk_name)
+    }
+
+    // Mount `disk_name` under /volumes and return the mount point
+    pub fn mount(&mut self, disk_name: &str) -> Result<String> {
+        self.attach(disk_name, false)
+    }
+
+    // Mount `disk_name` without ever writing to its image, which only needs
+    // to be readable on the host
+    pub fn mount_read_only(&mut self, disk_name: &str) -> Result<String> {
+        self.attach(disk_name, true)
+    }
+
+    fn attach(&mut self, disk_name: &str, read_only: bool) -> Result<String> {
+        if disk_name == ROOT_DISK {
+            if read_only {
+                return Err(RnixError::InvalidArgument("the root disk cannot be mounted read-only".to_string()));
+            }
+            return Ok("/".to_string());
+        }
+        if !MOUNTABLE_DISKS.contains(&disk_name) {
+            return Err(RnixError::InvalidArgument(
+                "Invalid disk name. Only disk1 and disk2 can be mounted.".to_string(),
+            ));
+        }
+        let disk_img = self.disk_path(disk_name);
+        if !disk_img.exists() {
+            return Err(RnixError::NotFound(disk_img.display().to_string()));
+        }
+        if self.mounts.contains_key(disk_name) {
+            return Err(RnixError::AlreadyMounted(disk_name.to_string()));
+        }
+
+        let mount_point = path::join(VOLUMES_DIR, disk_name);
+        let root_dir = self.root.root_dir();
+        if root_dir.open_dir(mount_point.trim_start_matches('/')).is_err() {
+            root_dir.create_dir(mount_point.trim_start_matches('/'))?;
+        }
+
+        let fs = self.open_disk(&disk_img, read_only)?;
+        self.mounts.insert(disk_name.to_string(), fs);
+        if read_only {
+            self.read_only.insert(disk_name.to_string());
+        } else {
+            self.recover(disk_name)?;
+        }
+        Ok(mount_point)
+    }
+
+    pub fn unmount(&mut self, disk_name: &str) -> Result<()> {
+        if disk_name == ROOT_DISK {
+            return Err(RnixError::PermissionDenied("Cannot unmount root disk.".to_string()));
+        }
+        if !MOUNTABLE_DISKS.contains(&disk_name) {
+            return Err(RnixError::InvalidArgument(
+                "Invalid disk name. Only disk1 and disk2 can be unmounted.".to_string(),
+            ));
+        }
+        let fs = self.mounts.remove(disk_name).ok_or_else(|| RnixError::NotMounted(disk_name.to_string()))?;
+        let read_only = self.read_only.remove(disk_name);
+        // Unmounting writes the free cluster count and clears the dirty flag
+        fs.unmount().at(disk_name)?;
+        match read_only {
+            true => Ok(()),
+            false => sync_image(&self.disk_path(disk_name)),
+        }
+    }
+
+    // Function to write every mounted disk out to its image, as unmounting
+    // would, leaving it mounted. The root disk stays mounted while rnix runs,
+    // so its data is written out but its dirty flag stays set until exit.
+    pub fn sync(&mut self) -> Result<()> {
+        let disks: Vec<String> = self.mounts.keys().filter(|disk| !self.is_read_only(disk)).cloned().collect();
+        for disk in disks {
+            let image = self.disk_path(&disk);
+            let Some(fs) = self.mounts.remove(&disk) else {
+                continue;
+            };
+            let flushed = fs.unmount().at(&disk);
+            // fatfs can only flush by unmounting, so the disk is opened again,
+            // even when that failed, to keep it in the mount table
+            let fs = self.open_disk(&image, false).map_err(|err| {
+                RnixError::InvalidArgument(format!("{}: unmounted, as it could not be opened again: {}", disk, err))
+            })?;
+            self.mounts.insert(disk.clone(), fs);
+            flushed?;
+            sync_image(&image)?;
+        }
+        sync_image(&self.image_path)
+    }
+
+    fn open_disk(&self, image: &Path, read_only: bool) -> Result<DiskFs> {
+        let file = OpenOptions::new().read(true).write(!read_only).open(image)?;
+        FileSystem::new(file, FsOptions::new().time_provider(self.clock))
+            .map_err(|err| RnixError::CorruptImage(format!("{}: {}", image.display(), err)))
+    }
+
+    // Function to report the space on the root disk and on each mounted disk
+    pub fn disk_usage(&self) -> Result<Vec<DiskUsage>> {
+        let mut usage = vec![usage_of(ROOT_DISK, "/", &self.root)?];
+        for (disk, fs) in &self.mounts {
+            usage.push(usage_of(disk, &path::join(VOLUMES_DIR, disk), fs)?);
+        }
+        Ok(usage)
+    }
+
+    // Name of the disk holding the absolute path `abs`
+    pub fn disk_of(&self, abs: &str) -> &str {
+        if let Some(rest) = abs.strip_prefix(VOLUMES_DIR).and_then(|r| r.strip_prefix('/')) {
+            let disk = rest.split('/').next().unwrap_or("");
+            if let Some(name) = self.mounts.keys().find(|name| name.eq_ignore_ascii_case(disk)) {
+                return name;
+            }
+        }
+        ROOT_DISK
+    }
+
+    // Function to refuse writes below `abs` when its disk is mounted read-only
+    fn writable(&self, abs: &str) -> Result<()> {
+        match self.read_only.contains(self.disk_of(abs)) {
+            true => Err(RnixError::ReadOnly(abs.to_string())),
+            false => Ok(()),
+        }
+    }
+
+    // Function to refuse changes to `abs` itself: as well as `writable`, a
+    // file with the read-only attribute may not be written or removed
+    fn modifiable(&self, abs: &str) -> Result<()> {
+        self.writable(abs)?;
+        match self.metadata_of(abs.to_string()) {
+            Ok(metadata) if !metadata.is_dir && metadata.attributes.contains(FileAttributes::READ_ONLY) => {
+                Err(RnixError::PermissionDenied(format!("{}: file is read-only", abs)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Fail unless `p` could be created or replaced, for writes that go
+    // through `dir` rather than the methods here
+    pub fn check_writable(&self, session: &Session, p: &str) -> Result<()> {
+        self.modifiable(&session.resolve(p))
+    }
+
+    fn route(&self, abs: &str) -> (&DiskFs, String) {
+        if let Some(rest) = abs.strip_prefix(VOLUMES_DIR).and_then(|r| r.strip_prefix('/')) {
+            let (disk, rel) = match rest.find('/') {
+                Some(idx) => (&rest[..idx], &rest[idx + 1..]),
+                None => (rest, ""),
+            };
+            if let Some((_, fs)) = self.mounts.iter().find(|(name, _)| name.eq_ignore_ascii_case(disk)) {
+                return (fs, rel.to_string());
+            }
+        }
+        (&self.root, abs.trim_start_matches('/').to_string())
+    }
+
+    // ------------------------------------------------------------------
+    // Journal
+    // ------------------------------------------------------------------
+
+    fn fs_of(&self, disk: &str) -> &DiskFs {
+        self.mounts.get(disk).unwrap_or(&self.root)
+    }
+
+    // Function to make the change `steps` describe to `disk` in full or not at
+    // all. `prepare` writes the temporary files the steps move into place;
+    // if it fails, nothing changes.
+    fn transaction(&self, disk: &str, steps: &[Step], prepare: impl FnOnce() -> Result<()>) -> Result<()> {
+        if steps.is_empty() {
+            return Ok(());
+        }
+        let journal = journal::path(disk);
+        let internal = self.root.root_dir();
+        let mut file = internal.create_file(&journal).at(&journal)?;
+        file.truncate()?;
+        file.write_all(journal::encode(steps).as_bytes())?;
+        drop(file);
+
+        let root = self.fs_of(disk).root_dir();
+        if let Err(err) = prepare() {
+            for step in steps {
+                step.roll_back(&root)?;
+            }
+            internal.remove(&journal).at(&journal)?;
+            return Err(err);
+        }
+        let mut file = internal.open_file(&journal).at(&journal)?;
+        file.seek(SeekFrom::End(0))?;
+        file.write_all(journal::encode_commit().as_bytes())?;
+        drop(file);
+
+        for step in steps {
+            step.apply(&root)?;
+        }
+        internal.remove(&journal).at(&journal)
+    }
+
+    // Function to finish, or undo, a change to `disk` that rnix stopped in the
+    // middle of
+    fn recover(&self, disk: &str) -> Result<()> {
+        let journal = journal::path(disk);
+        let internal = self.root.root_dir();
+        let mut text = String::new();
+        match internal.open_file(&journal) {
+            Ok(mut file) => file.read_to_string(&mut text).at(&journal)?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(RnixError::from(err)),
+        };
+        let (steps, committed) = journal::parse(&text);
+        let root = self.fs_of(disk).root_dir();
+        for step in &steps {
+            match committed {
+                true => step.apply(&root)?,
+                false => step.roll_back(&root)?,
+            }
+        }
+        internal.remove(&journal).at(&journal)
+    }
+
+    // The disk of `abs` and the step that replaces it with a temporary file,
+    // keeping its name as it is spelled on the disk if it exists
+    fn replacement<'a>(&'a self, abs: &str) -> Result<(&'a str, Step)> {
+        let (dir, name) = self.open_parent(abs)?;
+        let (_, rel) = self.route(abs);
+        let target = match dir.iter().filter_map(|entry| entry.ok()).find(|entry| entry.file_name().eq_ignore_ascii_case(name)) {
+            Some(entry) => match rel.rsplit_once('/') {
+                Some((parent, _)) => format!("{}/{}", parent, entry.file_name()),
+                None => entry.file_name(),
+            },
+            None => rel,
+        };
+        let step = Step::Replace {
+            temp: journal::temp_path(&target),
+            target,
+        };
+        Ok((self.disk_of(abs), step))
+    }
+
+    // Function to write the temporary file of a `Step::Replace` with what
+    // `fill` writes. It takes the attributes and creation time of the file it
+    // will replace, if there is one.
+    fn stage(&self, disk: &str, step: &Step, fill: impl FnOnce(&mut VFile<'_>) -> Result<()>) -> Result<()> {
+        let Step::Replace { temp, target } = step else {
+            return Ok(());
+        };
+        let root = self.fs_of(disk).root_dir();
+        let mut file = root.create_file(temp).at(temp)?;
+        file.truncate()?;
+        fill(&mut file)?;
+        drop(file);
+        if root.open_file(target).is_err() {
+            return Ok(());
+        }
+        let mut volume = Volume::open(&self.disk_path(disk))?;
+        let offset = volume.locate(target)?;
+        let original = volume.read_entry(offset)?;
+        let offset = volume.locate(temp)?;
+        let mut entry = volume.read_entry(offset)?;
+        entry[11] = original[11];
+        entry[13..18].copy_from_slice(&original[13..18]);
+        volume.write_entry(offset, &entry)
+    }
+
+    // Function to replace the file at `abs`, or create it, with what `fill`
+    // writes. The new contents go to a temporary file in the same directory
+    // that is then renamed over the original, so the file holds either its
+    // old or its new contents whenever rnix stops.
+    fn replace_file(&self, abs: &str, fill: impl FnOnce(&mut VFile<'_>) -> Result<()>) -> Result<()> {
+        let (disk, step) = self.replacement(abs)?;
+        self.transaction(disk, std::slice::from_ref(&step), || self.stage(disk, &step, fill))
+    }
+
+    // ------------------------------------------------------------------
+    // Filesystem operations
+    // ------------------------------------------------------------------
+
+    fn open_dir(&self, abs: &str) -> Result<VDir<'_>> {
+        let (fs, rel) = self.route(abs);
+        if rel.is_empty() {
+            return Ok(fs.root_dir());
+        }
+        fs.root_dir().open_dir(&rel).at(abs)
+    }
+
+    // Open the parent directory of `abs` and return it with the final name
+    fn open_parent<'a>(&self, abs: &'a str) -> Result<(VDir<'_>, &'a str)> {
+        let (parent, name) = path::split(abs);
+        if name.is_empty() {
+            return Err(RnixError::InvalidArgument("operation not permitted on '/'".to_string()));
+        }
+        Ok((self.open_dir(parent)?, name))
+    }
+
+    // Open a directory for direct access through fatfs
+    pub fn dir(&self, session: &Session, p: &str) -> Result<VDir<'_>> {
+        self.open_dir(&session.resolve(p))
+    }
+
+    pub fn is_dir(&self, session: &Session, p: &str) -> bool {
+        self.open_dir(&session.resolve(p)).is_ok()
+    }
+
+    pub fn is_file(&self, session: &Session, p: &str) -> bool {
+        let abs = session.resolve(p);
+        match self.open_parent(&abs) {
+            Ok((dir, name)) => dir.open_file(name).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    pub fn list_dir(&self, session: &Session, p: &str) -> Result<Vec<EntryInfo>> {
+        let dir = self.open_dir(&session.resolve(p))?;
+        let mut entries = Vec::new();
+        for entry in dir.iter() {
+            let entry = entry?;
+            let name = entry.file_name();
+            if name == "." || name == ".." {
+                continue;
+            }
+            entries.push(EntryInfo {
+                name,
+                is_dir: entry.is_dir(),
+                len: entry.len(),
+                attributes: entry.attributes(),
+                modified: entry.modified(),
+            });
+        }
+        Ok(entries)
+    }
+
+    pub fn cd(&self, session: &mut Session, p: &str) -> Result<()> {
+        let abs = session.resolve(p);
+        self.open_dir(&abs)?;
+        let old = std::mem::replace(&mut session.cwd, abs);
+        session.env.set("OLDPWD", &old)?;
+        session.env.set("PWD", &session.cwd)?;
+        Ok(())
+    }
+
+    pub fn mkdir(&self, session: &Session, p: &str) -> Result<()> {
+        let abs = session.resolve(p);
+        let (dir, name) = self.open_parent(&abs)?;
+        // fatfs hands back the existing entry instead of failing
+        if has_entry(&dir, name) {
+            return Err(RnixError::AlreadyExists(abs));
+        }
+        self.writable(&abs)?;
+        dir.create_dir(name).at(&abs)?;
+        Ok(())
+    }
+
+    // Create an empty file, or mark an existing file or directory as modified now
+    pub fn touch(&self, session: &Session, p: &str) -> Result<()> {
+        let abs = session.resolve(p);
+        let (dir, name) = self.open_parent(&abs)?;
+        if has_entry(&dir, name) {
+            return self.set_modified(session, p, self.now());
+        }
+        self.writable(&abs)?;
+        dir.create_file(name).at(&abs)?;
+        Ok(())
+    }
+
+    // Describe the entry of a file or directory
+    pub fn metadata(&self, session: &Session, p: &str) -> Result<Metadata> {
+        self.metadata_of(session.resolve(p))
+    }
+
+    fn metadata_of(&self, abs: String) -> Result<Metadata> {
+        if self.route(&abs).1.is_empty() {
+            return Ok(Metadata {
+                path: abs,
+                short_name: String::new(),
+                is_dir: true,
+                len: 0,
+                attributes: FileAttributes::DIRECTORY,
+                created: None,
+                modified: None,
+                accessed: None,
+            });
+        }
+        let (dir, name) = self.open_parent(&abs)?;
+        let entry = dir
+            .iter()
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.file_name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| RnixError::NotFound(abs.clone()))?;
+        Ok(Metadata {
+            path: abs,
+            short_name: entry.short_file_name(),
+            is_dir: entry.is_dir(),
+            len: entry.len(),
+            attributes: entry.attributes(),
+            created: Some(entry.created()),
+            modified: Some(entry.modified()),
+            accessed: Some(entry.accessed()),
+        })
+    }
+
+    // Set when a file or directory was last modified, and accessed
+    pub fn set_modified(&self, session: &Session, p: &str, time: SystemTime) -> Result<()> {
+        self.modifiable(&session.resolve(p))?;
+        let fatfs::DateTime { date, time } = to_fat(time);
+        self.patch_entry(session, p, |entry| {
+            entry[18..20].copy_from_slice(&encode_date(date).to_le_bytes());
+            entry[22..24].copy_from_slice(&encode_time(time).to_le_bytes());
+            entry[24..26].copy_from_slice(&encode_date(date).to_le_bytes());
+        })
+    }
+
+    // Turn the `set` attributes on and the `clear` ones off, leaving the
+    // others as they are, and return the attributes that result. Only
+    // `CHANGEABLE_ATTRIBUTES` can be changed, and the read-only attribute
+    // does not stop its own removal.
+    pub fn set_attributes(&self, session: &Session, p: &str, set: FileAttributes, clear: FileAttributes) -> Result<FileAttributes> {
+        let (set, clear) = (set & CHANGEABLE_ATTRIBUTES, clear & CHANGEABLE_ATTRIBUTES);
+        let mut attributes = FileAttributes::empty();
+        self.patch_entry(session, p, |entry| {
+            attributes = (FileAttributes::from_bits_truncate(entry[11]) | set) - clear;
+            entry[11] = attributes.bits();
+        })?;
+        Ok(attributes)
+    }
+
+    // Function to change the directory entry of `p` in place, for what fatfs
+    // has no way to set
+    fn patch_entry(&self, session: &Session, p: &str, edit: impl FnOnce(&mut [u8; ENTRY_SIZE])) -> Result<()> {
+        let abs = self.metadata(session, p)?.path;
+        let (_, rel) = self.route(&abs);
+        if rel.is_empty() {
+            return Err(RnixError::InvalidArgument(format!("{}: the root of a disk has no directory entry", abs)));
+        }
+        self.writable(&abs)?;
+        let mut volume = Volume::open(&self.disk_path(self.disk_of(&abs)))?;
+        let offset = volume.locate(&rel)?;
+        let mut entry = volume.read_entry(offset)?;
+        edit(&mut entry);
+        volume.write_entry(offset, &entry)
+    }
+
+    // Remove a file or an empty directory
+    pub fn remove(&self, session: &Session, p: &str) -> Result<()> {
+        let abs = session.resolve(p);
+        let (dir, name) = self.open_parent(&abs)?;
+        if self.mounts.keys().any(|disk| path::join(VOLUMES_DIR, disk).eq_ignore_ascii_case(&abs)) {
+            return Err(RnixError::InvalidArgument(format!("{}: mount point is busy", abs)));
+        }
+        self.modifiable(&abs)?;
+        dir.remove(name).at(&abs)
+    }
+
+    // Rename or move `src` to `dst`; moving into an existing directory keeps the name
+    pub fn rename(&self, session: &Session, src: &str, dst: &str) -> Result<()> {
+        let src_abs = session.resolve(src);
+        let mut dst_abs = session.resolve(dst);
+        if self.open_dir(&dst_abs).is_ok() {
+            dst_abs = path::join(&dst_abs, path::split(&src_abs).1);
+        }
+        let (src_fs, _) = self.route(&src_abs);
+        let (dst_fs, _) = self.route(&dst_abs);
+        if !std::ptr::eq(src_fs, dst_fs) {
+            return Err(RnixError::InvalidArgument("cannot move across disks; use cp".to_string()));
+        }
+        // As on DOS, a read-only file can still be renamed, but not replaced
+        self.writable(&src_abs)?;
+        self.modifiable(&dst_abs)?;
+        let (src_dir, src_name) = self.open_parent(&src_abs)?;
+        let (dst_dir, dst_name) = self.open_parent(&dst_abs)?;
+        src_dir
+            .rename(src_name, &dst_dir, dst_name)
+            .at(&src_abs)
+    }
+
+    // Copy a file; copying into an existing directory keeps the name
+    pub fn copy(&self, session: &Session, src: &str, dst: &str) -> Result<()> {
+        let src_abs = session.resolve(src);
+        let mut dst_abs = session.resolve(dst);
+        if self.open_dir(&dst_abs).is_ok() {
+            dst_abs = path::join(&dst_abs, path::split(&src_abs).1);
+        }
+        let (src_dir, src_name) = self.open_parent(&src_abs)?;
+        let mut src_file = src_dir.open_file(src_name).at(&src_abs)?;
+        self.modifiable(&dst_abs)?;
+        self.replace_file(&dst_abs, |dst_file| {
+            io::copy(&mut src_file, dst_file)?;
+            Ok(())
+        })
+    }
+
+    pub fn read_file(&self, session: &Session, p: &str) -> Result<Vec<u8>> {
+        let abs = session.resolve(p);
+        let (dir, name) = self.open_parent(&abs)?;
+        let mut file = dir.open_file(name).at(&abs)?;
+        let mut contents = Vec::new();
+        file.read_to_end(&mut contents)?;
+        Ok(contents)
+    }
+
+    // Replace the contents of an existing file, atomically
+    pub fn write_file(&self, session: &Session, p: &str, data: &[u8]) -> Result<()> {
+        let abs = session.resolve(p);
+        let (dir, name) = self.open_parent(&abs)?;
+        dir.open_file(name).at(&abs)?;
+        self.modifiable(&abs)?;
+        self.replace_file(&abs, |file| Ok(file.write_all(data)?))
+    }
+
+    // Create or replace the file at `p` with what `fill` writes, atomically,
+    // for contents that are streamed rather than held in memory
+    pub fn fill_file(&self, session: &Session, p: &str, fill: impl FnOnce(&mut VFile<'_>) -> Result<()>) -> Result<()> {
+        let abs = session.resolve(p);
+        self.open_parent(&abs)?;
+        self.modifiable(&abs)?;
+        self.replace_file(&abs, fill)
+    }
+
+    // Function to total the disk space `p` takes up, counting whole clusters.
+    // `visit` is called with each directory's total after its contents', along
+    // with its path (built on `p` as given) and its depth below `p`. A file
+    // argument is visited on its own.
+    pub fn disk_used(
+        &self,
+        session: &Session,
+        p: &str,
+        visit: &mut dyn FnMut(&str, usize, u64) -> Result<()>,
+    ) -> Result<u64> {
+        let abs = session.resolve(p);
+        if self.is_dir(session, p) {
+            return self.dir_used(&abs, p, 0, visit);
+        }
+        let (dir, name) = self.open_parent(&abs)?;
+        let entry = dir
+            .iter()
+            .filter_map(|entry| entry.ok())
+            .find(|entry| entry.file_name().eq_ignore_ascii_case(name))
+            .ok_or_else(|| RnixError::NotFound(abs.clone()))?;
+        let used = whole_clusters(entry.len(), self.route(&abs).0.cluster_size() as u64);
+        visit(p, 0, used)?;
+        Ok(used)
+    }
+
+    fn dir_used(
+        &self,
+        abs: &str,
+        shown: &str,
+        depth: usize,
+        visit: &mut dyn FnMut(&str, usize, u64) -> Result<()>,
+    ) -> Result<u64> {
+        let cluster = self.route(abs).0.cluster_size() as u64;
+        let mut entry_bytes = 0;
+        let mut used = 0;
+        let mut subdirs = Vec::new();
+        for entry in self.open_dir(abs)?.iter() {
+            let entry = entry.at(abs)?;
+            let name = entry.file_name();
+            // fatfs writes a long name for every entry: 13 characters per extra slot
+            entry_bytes += 32 * (1 + name.encode_utf16().count().div_ceil(13) as u64);
+            if name == "." || name == ".." {
+                continue;
+            }
+            if entry.is_dir() {
+                subdirs.push(name);
+            } else {
+                used += whole_clusters(entry.len(), cluster);
+            }
+        }
+        // A directory always has at least one cluster of its own
+        used += whole_clusters(entry_bytes, cluster).max(cluster);
+        for name in subdirs {
+            used += self.dir_used(&path::join(abs, &name), &path::join(shown, &name), depth + 1, visit)?;
+        }
+        visit(shown, depth, used)?;
+        Ok(used)
+    }
+}
+
+fn usage_of(disk: &str, mount_point: &str, fs: &DiskFs) -> Result<DiskUsage> {
+    let stats = fs.stats()?;
+    let cluster_size = stats.cluster_size() as u64;
+    Ok(DiskUsage {
+        disk: disk.to_string(),
+        mount_point: mount_point.to_string(),
+        fat_type: match fs.fat_type() {
+            FatType::Fat12 => "FAT12",
+            FatType::Fat16 => "FAT16",
+            FatType::Fat32 => "FAT32",
+        },
+        cluster_size,
+        total: stats.total_clusters() as u64 * cluster_size,
+        free: stats.free_clusters() as u64 * cluster_size,
+    })
+}
+
+// Function to have the host write `image` out to its storage, so what was
+// flushed to the file survives the host going down too
+fn sync_image(image: &Path) -> Result<()> {
+    File::open(image).and_then(|file| file.sync_all()).at(&image.display().to_string())
+}
+
+// Bytes `len` takes up once rounded up to whole clusters
+fn whole_clusters(len: u64, cluster: u64) -> u64 {
+    len.div_ceil(cluster) * cluster
+}
+
+// Function to check for an entry by name, ignoring case the way FAT lookups do
+fn has_entry(dir: &VDir<'_>, name: &str) -> bool {
+    dir.iter()
+        .filter_map(|entry| entry.ok())
+        .any(|entry| entry.file_name().eq_ignore_ascii_case(name))
+}
@@ -0,0 +1,302 @@
+// The text utilities behind grep, sort, uniq, cut, tr, sed and diff. They
+// work on lines already read, so the commands can take them from files in the
+// image or from the previous command in a pipeline alike.
+
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+use regex::{Regex, RegexBuilder};
+
+use crate::ed::{delimited, replacement};
+use crate::error::{Result, RnixError};
+
+// Function to compile a grep pattern
+pub fn pattern(pattern: &str, ignore_case: bool) -> Result<Regex> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(ignore_case)
+        .build()
+        .map_err(|err| RnixError::InvalidArgument(format!("bad pattern: {}", err)))
+}
+
+// Function to sort lines as text, or with `numeric` by the number they start
+// with (0 when there is none); `unique` keeps one of each run of equal lines
+pub fn sort(lines: &mut Vec<&str>, numeric: bool, reverse: bool, unique: bool) {
+    let key = |a: &str, b: &str| match numeric {
+        true => leading_number(a).total_cmp(&leading_number(b)),
+        false => a.cmp(b),
+    };
+    lines.sort_by(|a, b| {
+        let order = key(a, b).then_with(|| a.cmp(b));
+        if reverse {
+            order.reverse()
+        } else {
+            order
+        }
+    });
+    if unique {
+        lines.dedup_by(|a, b| key(a, b) == Ordering::Equal);
+    }
+}
+
+fn leading_number(line: &str) -> f64 {
+    let line = line.trim_start();
+    let end = line
+        .char_indices()
+        .find(|&(at, c)| !(c.is_ascii_digit() || c == '.' || (at == 0 && c == '-')))
+        .map_or(line.len(), |(at, _)| at);
+    line[..end].parse().unwrap_or(0.0)
+}
+
+// Function to collapse runs of equal adjacent lines into each line and how
+// many times it appeared in a row
+pub fn uniq<'a>(lines: &[&'a str]) -> Vec<(usize, &'a str)> {
+    let mut runs: Vec<(usize, &str)> = Vec::new();
+    for &line in lines {
+        match runs.last_mut() {
+            Some((count, last)) if *last == line => *count += 1,
+            _ => runs.push((1, line)),
+        }
+    }
+    runs
+}
+
+// The fields or characters cut selects, as 1-based inclusive ranges
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct List(Vec<(usize, usize)>);
+
+impl List {
+    // Function to parse `N`, `N-M`, `N-` and `-M` items separated by commas
+    pub fn parse(text: &str) -> Result<List> {
+        let invalid = || RnixError::InvalidArgument(format!("invalid list '{}'", text));
+        let number = |digits: &str| digits.parse::<usize>().ok().filter(|n| *n > 0);
+        let mut ranges = Vec::new();
+        for item in text.split(',') {
+            let range = match item.split_once('-') {
+                Some(("", "")) => return Err(invalid()),
+                Some(("", end)) => (1, number(end).ok_or_else(invalid)?),
+                Some((start, "")) => (number(start).ok_or_else(invalid)?, usize::MAX),
+                Some((start, end)) => (number(start).ok_or_else(invalid)?, number(end).ok_or_else(invalid)?),
+                None => {
+                    let n = number(item).ok_or_else(invalid)?;
+                    (n, n)
+                }
+            };
+            if range.0 > range.1 {
+                return Err(invalid());
+            }
+            ranges.push(range);
+        }
+        Ok(List(ranges))
+    }
+
+    pub fn contains(&self, n: usize) -> bool {
+        self.0.iter().any(|&(start, end)| start <= n && n <= end)
+    }
+
+    // Function to keep the selected fields of `line`; as with cut, a line
+    // without the delimiter is kept whole
+    pub fn fields(&self, line: &str, delimiter: char) -> String {
+        if !line.contains(delimiter) {
+            return line.to_string();
+        }
+        let fields: Vec<&str> = line
+            .split(delimiter)
+            .enumerate()
+            .filter(|(index, _)| self.contains(index + 1))
+            .map(|(_, field)| field)
+            .collect();
+        fields.join(&delimiter.to_string())
+    }
+
+    pub fn chars(&self, line: &str) -> String {
+        line.chars()
+            .enumerate()
+            .filter(|(index, _)| self.contains(index + 1))
+            .map(|(_, c)| c)
+            .collect()
+    }
+}
+
+// Function to expand a tr set: `a-z` ranges and the escapes `\n`, `\t` and `\\`
+pub fn char_set(text: &str) -> Result<Vec<char>> {
+    let mut chars = Vec::new();
+    let mut rest = text.chars();
+    while let Some(c) = rest.next() {
+        chars.push(match c {
+            '\\' => match rest.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(c) => c,
+                None => '\\',
+            },
+            c => c,
+        });
+    }
+    let mut set = Vec::new();
+    let mut i = 0;
+    while i < chars.len() {
+        if chars.get(i + 1) == Some(&'-') && i + 2 < chars.len() {
+            let (start, end) = (chars[i], chars[i + 2]);
+            if start > end {
+                return Err(RnixError::InvalidArgument(format!(
+                    "range-endpoints of '{}-{}' are in reverse collating sequence order",
+                    start, end
+                )));
+            }
+            set.extend(start..=end);
+            i += 3;
+        } else {
+            set.push(chars[i]);
+            i += 1;
+        }
+    }
+    Ok(set)
+}
+
+// Function to replace each character of `from` by the one at the same place
+// in `to`, whose last character stands in when it is the shorter
+pub fn translate(text: &str, from: &[char], to: &[char]) -> String {
+    text.chars()
+        .map(|c| match from.iter().rposition(|&f| f == c) {
+            Some(index) => to.get(index).or(to.last()).copied().unwrap_or(c),
+            None => c,
+        })
+        .collect()
+}
+
+// A sed `s/regex/replacement/[gi]` command
+#[derive(Debug, Clone)]
+pub struct Substitution {
+    regex: Regex,
+    replacement: String,
+    global: bool,
+}
+
+impl Substitution {
+    // Function to parse a script; any character but a backslash or newline can
+    // stand in for `/`, and the replacement may use `&` and `\1`..`\9`
+    pub fn parse(script: &str) -> Result<Substitution> {
+        let chars: Vec<char> = script.chars().collect();
+        let unterminated = || RnixError::InvalidArgument("unterminated `s' command".to_string());
+        let delimiter = match chars.as_slice() {
+            ['s', delimiter, ..] if !matches!(delimiter, '\\' | '\n') => *delimiter,
+            ['s', ..] | [] => return Err(unterminated()),
+            [c, ..] => return Err(RnixError::InvalidArgument(format!("unknown command: `{}'", c))),
+        };
+        let mut pos = 2;
+        let pattern = section(&chars, &mut pos, delimiter).ok_or_else(unterminated)?;
+        let with = section(&chars, &mut pos, delimiter).ok_or_else(unterminated)?;
+        let (mut global, mut ignore_case) = (false, false);
+        for flag in &chars[pos..] {
+            match flag {
+                'g' => global = true,
+                'i' | 'I' => ignore_case = true,
+                other => return Err(RnixError::InvalidArgument(format!("unknown option to `s': '{}'", other))),
+            }
+        }
+        Ok(Substitution {
+            regex: pattern_of(&pattern, ignore_case)?,
+            replacement: replacement(&with),
+            global,
+        })
+    }
+
+    // Function to apply the substitution to one line
+    pub fn apply<'a>(&self, line: &'a str) -> Cow<'a, str> {
+        let limit = if self.global { 0 } else { 1 };
+        self.regex.replacen(line, limit, self.replacement.as_str())
+    }
+}
+
+// Function to read up to the next unescaped `delimiter`, or `None` when there
+// is none
+fn section(chars: &[char], pos: &mut usize, delimiter: char) -> Option<String> {
+    let mut escaped = false;
+    let end = (*pos..chars.len()).find(|&at| {
+        let found = !escaped && chars[at] == delimiter;
+        escaped = !escaped && chars[at] == '\\';
+        found
+    })?;
+    let text = delimited(&chars[..=end], pos, delimiter);
+    Some(text)
+}
+
+fn pattern_of(text: &str, ignore_case: bool) -> Result<Regex> {
+    if text.is_empty() {
+        return Err(RnixError::InvalidArgument("no previous regular expression".to_string()));
+    }
+    pattern(text, ignore_case)
+}
+
+// Function to compare two files line by line, returning diff's normal format:
+// `NaM`, `NdM` and `NcM` hunk headers with `<` lines from `old` and `>` lines
+// from `new`. No output means the files are the same.
+pub fn diff(old: &[&str], new: &[&str]) -> Vec<String> {
+    // Lines the files start and end with alike need no table
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let a = &old[prefix..old.len() - suffix];
+    let b = &new[prefix..new.len() - suffix];
+
+    // common[i][j] is the length of the longest common subsequence of a[i..] and b[j..]
+    let mut common = vec![vec![0u32; b.len() + 1]; a.len() + 1];
+    for i in (0..a.len()).rev() {
+        for j in (0..b.len()).rev() {
+            common[i][j] = if a[i] == b[j] {
+                common[i + 1][j + 1] + 1
+            } else {
+                common[i + 1][j].max(common[i][j + 1])
+            };
+        }
+    }
+
+    let mut output = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < a.len() || j < b.len() {
+        if i < a.len() && j < b.len() && a[i] == b[j] {
+            i += 1;
+            j += 1;
+            continue;
+        }
+        let (start_i, start_j) = (i, j);
+        while i < a.len() || j < b.len() {
+            if i < a.len() && j < b.len() && a[i] == b[j] {
+                break;
+            }
+            if j == b.len() || (i < a.len() && common[i + 1][j] >= common[i][j + 1]) {
+                i += 1;
+            } else {
+                j += 1;
+            }
+        }
+        let (deleted, added) = (&a[start_i..i], &b[start_j..j]);
+        let (at_old, at_new) = (prefix + start_i, prefix + start_j);
+        let kind = match (deleted.is_empty(), added.is_empty()) {
+            (false, true) => 'd',
+            (true, false) => 'a',
+            _ => 'c',
+        };
+        output.push(format!("{}{}{}", range(at_old, deleted.len()), kind, range(at_new, added.len())));
+        output.extend(deleted.iter().map(|line| format!("< {}", line)));
+        if kind == 'c' {
+            output.push("---".to_string());
+        }
+        output.extend(added.iter().map(|line| format!("> {}", line)));
+    }
+    output
+}
+
+// A hunk's lines as diff numbers them: `N` or `N,M`, or the line before
+// when there are none
+fn range(start: usize, len: usize) -> String {
+    match len {
+        0 => start.to_string(),
+        1 => (start + 1).to_string(),
+        len => format!("{},{}", start + 1, start + len),
+    }
+}
@@ -0,0 +1,216 @@
+use std::iter::Peekable;
+use std::str::Chars;
+
+use crate::error::{Result, RnixError};
+
+// Maps a variable name to its value
+pub type Lookup<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+// Function to split a command line into words the way sh does for simple commands:
+// whitespace separates words, single quotes are literal, double quotes group words
+// and allow `\"` and `\\`, and a backslash outside quotes escapes the next character.
+// A `$` is kept as is; see `tokenize_with` for variable expansion.
+pub fn tokenize(line: &str) -> Result<Vec<String>> {
+    split(line, None)
+}
+
+// Like `tokenize`, but `$NAME`, `${NAME}`, `$?`, `$#`, `$@`, `$*` and `$1`..`$9`
+// outside single quotes are replaced by `lookup(name)`, or by nothing when it returns `None`. Expanded values are not
+// split into further words, and a word that expands to nothing unquoted disappears.
+pub fn tokenize_with(line: &str, lookup: Lookup<'_>) -> Result<Vec<String>> {
+    split(line, Some(lookup))
+}
+
+// A word of a command line. `pattern` is set when the word has unquoted
+// glob characters; it is the word with every quoted `*?[]\\` backslash-escaped.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Word {
+    pub text: String,
+    pub pattern: Option<String>,
+}
+
+// Like `tokenize_with`, but keeps track of which glob characters were quoted
+pub fn words_with(line: &str, lookup: Lookup<'_>) -> Result<Vec<Word>> {
+    Ok(split_words(line, Some(lookup), false)?.remove(0))
+}
+
+// Like `words_with`, but an unquoted `|` splits the line into the commands of
+// a pipeline. Every command must have at least one word.
+pub fn pipeline_with(line: &str, lookup: Lookup<'_>) -> Result<Vec<Vec<Word>>> {
+    let commands = split_words(line, Some(lookup), true)?;
+    if commands.len() > 1 && commands.iter().any(Vec::is_empty) {
+        return Err(RnixError::InvalidArgument("syntax error near unexpected token `|'".to_string()));
+    }
+    Ok(commands)
+}
+
+fn split(line: &str, lookup: Option<Lookup<'_>>) -> Result<Vec<String>> {
+    Ok(split_words(line, lookup, false)?.remove(0).into_iter().map(|word| word.text).collect())
+}
+
+// The word being read, with its glob pattern alongside
+#[derive(Default)]
+struct Builder {
+    text: String,
+    pattern: String,
+    glob: bool,
+}
+
+impl Builder {
+    // A character that came from quotes or a backslash escape
+    fn quoted(&mut self, c: char) {
+        if matches!(c, '*' | '?' | '[' | ']' | '\\') {
+            self.pattern.push('\\');
+        }
+        self.pattern.push(c);
+        self.text.push(c);
+    }
+
+    fn quoted_str(&mut self, s: &str) {
+        s.chars().for_each(|c| self.quoted(c));
+    }
+
+    // A character typed bare, which may take part in a glob
+    fn bare(&mut self, c: char) {
+        self.glob |= matches!(c, '*' | '?' | '[');
+        self.pattern.push(c);
+        self.text.push(c);
+    }
+
+    fn finish(&mut self) -> Word {
+        let builder = std::mem::take(self);
+        Word {
+            text: builder.text,
+            pattern: if builder.glob { Some(builder.pattern) } else { None },
+        }
+    }
+}
+
+// Function to split `line` into words, and into the commands of a pipeline
+// when `pipes` is set; there is always at least one command
+fn split_words(line: &str, lookup: Option<Lookup<'_>>, pipes: bool) -> Result<Vec<Vec<Word>>> {
+    let mut commands = Vec::new();
+    let mut words = Vec::new();
+    let mut word = Builder::default();
+    let mut in_word = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.quoted(c),
+                        None => return Err(unterminated('\'')),
+                    }
+                }
+            }
+            '"' => {
+                in_word = true;
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.quoted(c),
+                            Some('$') if lookup.is_some() => word.quoted('$'),
+                            Some(c) => {
+                                word.quoted('\\');
+                                word.quoted(c);
+                            }
+                            None => return Err(unterminated('"')),
+                        },
+                        Some('$') => match lookup {
+                            Some(lookup) => match variable(&mut chars)? {
+                                Some(name) => word.quoted_str(&lookup(&name).unwrap_or_default()),
+                                None => word.quoted('$'),
+                            },
+                            None => word.quoted('$'),
+                        },
+                        Some(c) => word.quoted(c),
+                        None => return Err(unterminated('"')),
+                    }
+                }
+            }
+            '\\' => {
+                in_word = true;
+                if let Some(c) = chars.next() {
+                    word.quoted(c);
+                }
+            }
+            '$' if lookup.is_some() => match variable(&mut chars)? {
+                Some(name) => {
+                    let value = lookup.and_then(|lookup| lookup(&name)).unwrap_or_default();
+                    in_word |= !value.is_empty();
+                    value.chars().for_each(|c| word.bare(c));
+                }
+                None => {
+                    in_word = true;
+                    word.bare('$');
+                }
+            },
+            c if c.is_whitespace() => {
+                if in_word {
+                    words.push(word.finish());
+                    in_word = false;
+                }
+            }
+            '|' if pipes => {
+                if in_word {
+                    words.push(word.finish());
+                    in_word = false;
+                }
+                commands.push(std::mem::take(&mut words));
+            }
+            c => {
+                in_word = true;
+                word.bare(c);
+            }
+        }
+    }
+    if in_word {
+        words.push(word.finish());
+    }
+    commands.push(words);
+    Ok(commands)
+}
+
+// Read the variable name after a `$`, or `None` if the `$` is literal
+fn variable(chars: &mut Peekable<Chars<'_>>) -> Result<Option<String>> {
+    match chars.peek().copied() {
+        Some(c @ ('?' | '#' | '@' | '*' | '0'..='9')) => {
+            chars.next();
+            Ok(Some(c.to_string()))
+        }
+        Some('{') => {
+            chars.next();
+            let mut name = String::new();
+            loop {
+                match chars.next() {
+                    Some('}') => break,
+                    Some(c) => name.push(c),
+                    None => return Err(unterminated('}')),
+                }
+            }
+            let special = name == "?" || name == "#" || (!name.is_empty() && name.bytes().all(|b| b.is_ascii_digit()));
+            if !special && !crate::env::is_valid_name(&name) {
+                return Err(RnixError::InvalidArgument(format!("${{{}}}: bad substitution", name)));
+            }
+            Ok(Some(name))
+        }
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => {
+            let mut name = String::new();
+            while let Some(c) = chars.peek().copied().filter(|c| c.is_ascii_alphanumeric() || *c == '_') {
+                name.push(c);
+                chars.next();
+            }
+            Ok(Some(name))
+        }
+        _ => Ok(None),
+    }
+}
+
+fn unterminated(quote: char) -> RnixError {
+    RnixError::InvalidArgument(format!("unexpected end of input while looking for matching `{}'", quote))
+}
@@ -0,0 +1,206 @@
+// Copying files and directory trees between the host and the disk images.
+// File modification times are carried across both ways. FAT keeps them in
+// local time to two-second precision, for years 1980 to 2107.
+
+use std::fmt;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+use chrono::{Datelike, Local, TimeZone, Timelike};
+
+use crate::error::{IoResultExt, Result, RnixError};
+use crate::path;
+use crate::system::{DiskFs, Session, System, VDir};
+
+// What a transfer copied
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Transferred {
+    pub files: usize,
+    pub dirs: usize,
+    pub bytes: u64,
+}
+
+impl fmt::Display for Transferred {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let plural = |count: usize, one: &str, many: &str| format!("{} {}", count, if count == 1 { one } else { many });
+        write!(f, "{}", plural(self.files, "file", "files"))?;
+        if self.dirs > 0 {
+            write!(f, " and {}", plural(self.dirs, "directory", "directories"))?;
+        }
+        write!(f, " ({} bytes)", self.bytes)
+    }
+}
+
+// Function to copy the host file or directory `host` into the image at `dest`.
+// As with `cp`, an existing directory at `dest` receives it under its own name.
+pub fn import(system: &System, session: &Session, host: &Path, dest: &str) -> Result<Transferred> {
+    let mut dest = session.resolve(dest);
+    if system.is_dir(session, &dest) {
+        let name = host
+            .file_name()
+            .ok_or_else(|| RnixError::InvalidArgument(format!("{}: no file name to import as", host.display())))?;
+        dest = path::join(&dest, &name.to_string_lossy());
+    }
+    let mut done = Transferred::default();
+    import_entry(system, session, host, &dest, &mut done)?;
+    Ok(done)
+}
+
+fn import_entry(system: &System, session: &Session, host: &Path, dest: &str, done: &mut Transferred) -> Result<()> {
+    let display = host.display().to_string();
+    let metadata = fs::metadata(host).at(&display)?;
+    if metadata.is_dir() {
+        if !system.is_dir(session, dest) {
+            system.mkdir(session, dest)?;
+        }
+        done.dirs += 1;
+        let mut entries: Vec<_> = fs::read_dir(host).at(&display)?.collect::<io::Result<_>>()?;
+        entries.sort_by_key(|entry| entry.file_name());
+        for entry in entries {
+            let name = entry.file_name().to_string_lossy().to_string();
+            import_entry(system, session, &entry.path(), &path::join(dest, &name), done)?;
+        }
+        return Ok(());
+    }
+    if !metadata.is_file() {
+        return Err(RnixError::InvalidArgument(format!("{}: not a regular file", display)));
+    }
+
+    let mut source = File::open(host).at(&display)?;
+    system.fill_file(session, dest, |file| {
+        done.bytes += io::copy(&mut source, file)?;
+        // Writing stamps the file with the current time, so this has to come after
+        if let Some(modified) = metadata.modified().ok().map(to_fat) {
+            #[allow(deprecated)]
+            file.set_modified(modified);
+        }
+        Ok(())
+    })?;
+    done.files += 1;
+    Ok(())
+}
+
+// Function to copy the image file or directory `src` out to `host`. An
+// existing host directory receives it under its own name.
+pub fn export(system: &System, session: &Session, src: &str, host: &Path) -> Result<Transferred> {
+    let src = session.resolve(src);
+    let (parent, name) = path::split(&src);
+    // A mount point has to be opened through the system to reach the disk mounted there
+    if system.is_dir(session, &src) {
+        return export_tree(&system.dir(session, &src)?, name, host);
+    }
+    export_from(&system.dir(session, parent)?, name, &src, host)
+}
+
+// Function to copy `src` out of a disk image opened with `disk::opendisk`,
+// without mounting it. The host side behaves as for `export`.
+pub fn extract(fs: &DiskFs, src: &str, host: &Path) -> Result<Transferred> {
+    let src = path::resolve("/", src);
+    let (parent, name) = path::split(&src);
+    if name.is_empty() {
+        return export_tree(&fs.root_dir(), name, host);
+    }
+    let dir = if parent == "/" { fs.root_dir() } else { fs.root_dir().open_dir(&parent[1..]).at(parent)? };
+    export_from(&dir, name, &src, host)
+}
+
+// Copy the entry `name` of `dir` to `host`
+fn export_from(dir: &VDir<'_>, name: &str, src: &str, host: &Path) -> Result<Transferred> {
+    let entry = dir
+        .iter()
+        .filter_map(|entry| entry.ok())
+        .find(|entry| entry.file_name().eq_ignore_ascii_case(name))
+        .ok_or_else(|| RnixError::NotFound(src.to_string()))?;
+    if entry.is_dir() {
+        return export_tree(&entry.to_dir(), name, host);
+    }
+    let mut done = Transferred::default();
+    export_file(&entry, &target(name, host), &mut done)?;
+    Ok(done)
+}
+
+fn export_tree(dir: &VDir<'_>, name: &str, host: &Path) -> Result<Transferred> {
+    let mut done = Transferred::default();
+    export_dir(dir, &target(name, host), &mut done)?;
+    Ok(done)
+}
+
+// Where an entry called `name` lands when copied to `host`
+fn target(name: &str, host: &Path) -> PathBuf {
+    if host.is_dir() && !name.is_empty() {
+        host.join(name)
+    } else {
+        host.to_path_buf()
+    }
+}
+
+fn export_dir(dir: &VDir<'_>, host: &Path, done: &mut Transferred) -> Result<()> {
+    if !host.is_dir() {
+        fs::create_dir(host).at(&host.display().to_string())?;
+    }
+    done.dirs += 1;
+    for entry in dir.iter() {
+        let entry = entry?;
+        let name = entry.file_name();
+        if name == "." || name == ".." {
+            continue;
+        }
+        if entry.is_dir() {
+            export_dir(&entry.to_dir(), &host.join(&name), done)?;
+        } else {
+            export_file(&entry, &host.join(&name), done)?;
+        }
+    }
+    Ok(())
+}
+
+fn export_file(entry: &fatfs::DirEntry<'_, File>, host: &Path, done: &mut Transferred) -> Result<()> {
+    let display = host.display().to_string();
+    let mut target = File::create(host).at(&display)?;
+    done.bytes += io::copy(&mut entry.to_file(), &mut target)?;
+    done.files += 1;
+    if let Some(modified) = from_fat(entry.modified()) {
+        target.set_modified(modified).at(&display)?;
+    }
+    Ok(())
+}
+
+// Function to convert a host time to FAT's local date and time, clamped to the years FAT can hold
+pub fn to_fat(time: SystemTime) -> fatfs::DateTime {
+    let local = chrono::DateTime::<Local>::from(time);
+    let (year, month, day, hour, min, sec) = match local.year() {
+        year if year < 1980 => (1980, 1, 1, 0, 0, 0),
+        year if year > 2107 => (2107, 12, 31, 23, 59, 58),
+        year => (
+            year as u16,
+            local.month() as u16,
+            local.day() as u16,
+            local.hour() as u16,
+            local.minute() as u16,
+            // A leap second still fits in the minute
+            local.second().min(59) as u16,
+        ),
+    };
+    fatfs::DateTime {
+        date: fatfs::Date { year, month, day },
+        time: fatfs::Time { hour, min, sec, millis: 0 },
+    }
+}
+
+// Function to convert a FAT date and time to a host time, if it is a valid local time
+pub fn from_fat(date_time: fatfs::DateTime) -> Option<SystemTime> {
+    let fatfs::DateTime { date, time } = date_time;
+    let local = Local
+        .with_ymd_and_hms(
+            date.year as i32,
+            date.month as u32,
+            date.day as u32,
+            time.hour as u32,
+            time.min as u32,
+            time.sec as u32,
+        )
+        .earliest()?;
+    Some(local.into())
+}
@@ -0,0 +1,35 @@
+use std::fmt;
+
+// Struct to hold RNIX version information
+pub struct RnixVersion {
+    version: &'static str,
+}
+
+impl fmt::Display for RnixVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RNIX Version: {}", self.version)
+    }
+}
+
+// Function to get RNIX version information
+pub fn get_rnix_version() -> RnixVersion {
+    RnixVersion { version: "1.0.0" }
+}
+
+// Struct to hold RNIX API version information
+pub struct RnixApiVersion {
+    version: &'static str,
+}
+
+impl fmt::Display for RnixApiVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "RNIX API Version: {}", self.version)
+    }
+}
+
+// Function to get RNIX API version information (the version of the `rnix` library crate)
+pub fn get_rnix_api_version() -> RnixApiVersion {
+    RnixApiVersion {
+        version: env!("CARGO_PKG_VERSION"),
+    }
+}
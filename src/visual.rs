@@ -0,0 +1,476 @@
+// A full-screen text editor in the style of kilo/nano. Like the prompt's line
+// editor it is a pure state machine: keys go in through `feed`, and `render`
+// returns the escape sequences that draw the screen, so the terminal loop in
+// the console and the tests drive it the same way. Lines are kept as chars, so
+// the cursor always sits on a character boundary of UTF-8 text.
+
+use crate::console::Console;
+use crate::error::{Result, RnixError};
+use crate::readline::Key;
+use crate::system::{Session, System};
+
+// Columns a tab advances to
+pub const TAB_STOP: usize = 4;
+
+// Extra Ctrl-Q presses needed to quit with unsaved changes
+const QUIT_TIMES: usize = 1;
+
+const HELP: &str = "^S save | ^O save as | ^F find | ^Q quit";
+
+// What the caller should do after feeding a key
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    None,
+    // Write `contents()` to this path, then report back with `saved`
+    Save(String),
+    Quit,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PromptKind {
+    SaveAs,
+    // Remembers where the cursor was, for Esc
+    Search { cx: usize, cy: usize, row_offset: usize, col_offset: usize },
+}
+
+#[derive(Debug, Clone)]
+struct Prompt {
+    kind: PromptKind,
+    input: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct Visual {
+    path: String,
+    lines: Vec<Vec<char>>,
+    // Cursor position: character index and line index
+    cx: usize,
+    cy: usize,
+    row_offset: usize,
+    col_offset: usize,
+    // Text area size; the two bars below it are extra
+    rows: usize,
+    cols: usize,
+    dirty: bool,
+    quit_times: usize,
+    message: String,
+    prompt: Option<Prompt>,
+}
+
+impl Visual {
+    // An editor for `contents` in a terminal of `rows` x `cols`
+    pub fn new(path: &str, contents: &str, rows: usize, cols: usize) -> Visual {
+        let mut lines: Vec<Vec<char>> = contents.lines().map(|line| line.chars().collect()).collect();
+        if lines.is_empty() {
+            lines.push(Vec::new());
+        }
+        let mut visual = Visual {
+            path: path.to_string(),
+            lines,
+            cx: 0,
+            cy: 0,
+            row_offset: 0,
+            col_offset: 0,
+            rows: 0,
+            cols: 0,
+            dirty: false,
+            quit_times: QUIT_TIMES,
+            message: HELP.to_string(),
+            prompt: None,
+        };
+        visual.resize(rows, cols);
+        visual
+    }
+
+    // Adapt to a new terminal size, keeping room for the status and message bars
+    pub fn resize(&mut self, rows: usize, cols: usize) {
+        self.rows = rows.saturating_sub(2).max(1);
+        self.cols = cols.max(1);
+        self.scroll();
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+
+    // Line and character index of the cursor
+    pub fn cursor(&self) -> (usize, usize) {
+        (self.cy, self.cx)
+    }
+
+    // The buffer as file contents, each line ending in a newline
+    pub fn contents(&self) -> String {
+        let mut contents = String::new();
+        if self.lines.len() == 1 && self.lines[0].is_empty() {
+            return contents;
+        }
+        for line in &self.lines {
+            contents.extend(line);
+            contents.push('\n');
+        }
+        contents
+    }
+
+    // Report how a `Save` went
+    pub fn saved(&mut self, result: Result<usize>) {
+        match result {
+            Ok(bytes) => {
+                self.dirty = false;
+                self.message = format!("{} bytes written to {}", bytes, self.path);
+            }
+            Err(err) => self.message = format!("Can't save: {}", err),
+        }
+    }
+
+    pub fn feed(&mut self, key: Key) -> Action {
+        if self.prompt.is_some() {
+            return self.feed_prompt(key);
+        }
+        let action = self.feed_edit(key);
+        if key != Key::Ctrl('q') {
+            self.quit_times = QUIT_TIMES;
+        }
+        self.scroll();
+        action
+    }
+
+    fn feed_edit(&mut self, key: Key) -> Action {
+        match key {
+            Key::Ctrl('q') => {
+                if self.dirty && self.quit_times > 0 {
+                    self.message = format!(
+                        "File has unsaved changes. Press Ctrl-Q {} more time(s) to quit.",
+                        self.quit_times
+                    );
+                    self.quit_times -= 1;
+                    return Action::None;
+                }
+                return Action::Quit;
+            }
+            Key::Ctrl('s') => return Action::Save(self.path.clone()),
+            Key::Ctrl('o') => self.start_prompt(PromptKind::SaveAs, &self.path.clone()),
+            Key::Ctrl('f') => {
+                let kind = PromptKind::Search {
+                    cx: self.cx,
+                    cy: self.cy,
+                    row_offset: self.row_offset,
+                    col_offset: self.col_offset,
+                };
+                self.start_prompt(kind, "");
+            }
+            Key::Char(c) => self.insert(c),
+            Key::Tab => self.insert('\t'),
+            Key::Enter => self.newline(),
+            Key::Backspace | Key::Ctrl('h') => self.backspace(),
+            Key::Delete if self.cx < self.lines[self.cy].len() || self.cy + 1 < self.lines.len() => {
+                self.move_right();
+                self.backspace();
+            }
+            Key::Left => self.move_left(),
+            Key::Right => self.move_right(),
+            Key::Up => self.cy = self.cy.saturating_sub(1),
+            Key::Down => self.cy = (self.cy + 1).min(self.lines.len() - 1),
+            Key::Home | Key::Ctrl('a') => self.cx = 0,
+            Key::End | Key::Ctrl('e') => self.cx = self.lines[self.cy].len(),
+            Key::PageUp => {
+                self.cy = self.row_offset.saturating_sub(self.rows);
+            }
+            Key::PageDown => {
+                self.cy = (self.row_offset + self.rows * 2).saturating_sub(1).min(self.lines.len() - 1);
+            }
+            _ => {}
+        }
+        // Keep the cursor inside the line it moved to
+        self.cx = self.cx.min(self.lines[self.cy].len());
+        Action::None
+    }
+
+    fn start_prompt(&mut self, kind: PromptKind, input: &str) {
+        self.prompt = Some(Prompt {
+            kind,
+            input: input.to_string(),
+        });
+    }
+
+    fn feed_prompt(&mut self, key: Key) -> Action {
+        let mut prompt = match self.prompt.take() {
+            Some(prompt) => prompt,
+            None => return Action::None,
+        };
+        match (prompt.kind, key) {
+            (PromptKind::Search { cx, cy, row_offset, col_offset }, Key::Esc | Key::Ctrl('c')) => {
+                self.cx = cx;
+                self.cy = cy;
+                self.row_offset = row_offset;
+                self.col_offset = col_offset;
+                self.message.clear();
+                return Action::None;
+            }
+            (_, Key::Esc | Key::Ctrl('c')) => {
+                self.message = "Cancelled".to_string();
+                return Action::None;
+            }
+            (PromptKind::Search { .. }, Key::Enter) => {
+                self.message.clear();
+                return Action::None;
+            }
+            (PromptKind::SaveAs, Key::Enter) => {
+                if prompt.input.is_empty() {
+                    self.message = "Cancelled".to_string();
+                } else {
+                    self.path = prompt.input;
+                    return Action::Save(self.path.clone());
+                }
+                return Action::None;
+            }
+            (PromptKind::Search { .. }, Key::Down | Key::Right | Key::Ctrl('f')) => {
+                self.find(&prompt.input, true, true);
+            }
+            (PromptKind::Search { .. }, Key::Up | Key::Left) => {
+                self.find(&prompt.input, false, true);
+            }
+            (kind, Key::Backspace | Key::Ctrl('h')) => {
+                prompt.input.pop();
+                if let PromptKind::Search { .. } = kind {
+                    self.find(&prompt.input, true, false);
+                }
+            }
+            (kind, Key::Char(c)) => {
+                prompt.input.push(c);
+                if let PromptKind::Search { .. } = kind {
+                    self.find(&prompt.input, true, false);
+                }
+            }
+            _ => {}
+        }
+        self.prompt = Some(prompt);
+        self.scroll();
+        Action::None
+    }
+
+    // Move to the next (or previous) occurrence of `query`, wrapping around.
+    // Without `skip` a match at the cursor itself counts, so typing extends
+    // the current match instead of jumping past it.
+    fn find(&mut self, query: &str, forward: bool, skip: bool) {
+        let query: Vec<char> = query.chars().collect();
+        if query.is_empty() {
+            return;
+        }
+        let count = self.lines.len();
+        // Check the cursor's line first, then the others in order, then the cursor's line again
+        for step in 0..=count {
+            let index = if forward { (self.cy + step) % count } else { (self.cy + count - step % count) % count };
+            let line = &self.lines[index];
+            let at = |start: &usize| line[*start..].starts_with(&query);
+            let found = if forward {
+                let from = match step {
+                    0 if skip => self.cx + 1,
+                    0 => self.cx,
+                    _ => 0,
+                };
+                (from..line.len()).find(at)
+            } else {
+                let before = if step == 0 { self.cx } else { line.len() };
+                (0..before).rev().find(at)
+            };
+            if let Some(start) = found {
+                self.cy = index;
+                self.cx = start;
+                self.message.clear();
+                return;
+            }
+        }
+        self.message = "Not found".to_string();
+    }
+
+    fn insert(&mut self, c: char) {
+        self.lines[self.cy].insert(self.cx, c);
+        self.cx += 1;
+        self.dirty = true;
+    }
+
+    fn newline(&mut self) {
+        let rest = self.lines[self.cy].split_off(self.cx);
+        self.lines.insert(self.cy + 1, rest);
+        self.cy += 1;
+        self.cx = 0;
+        self.dirty = true;
+    }
+
+    fn backspace(&mut self) {
+        if self.cx > 0 {
+            self.cx -= 1;
+            self.lines[self.cy].remove(self.cx);
+            self.dirty = true;
+        } else if self.cy > 0 {
+            let line = self.lines.remove(self.cy);
+            self.cy -= 1;
+            self.cx = self.lines[self.cy].len();
+            self.lines[self.cy].extend(line);
+            self.dirty = true;
+        }
+    }
+
+    fn move_left(&mut self) {
+        if self.cx > 0 {
+            self.cx -= 1;
+        } else if self.cy > 0 {
+            self.cy -= 1;
+            self.cx = self.lines[self.cy].len();
+        }
+    }
+
+    fn move_right(&mut self) {
+        if self.cx < self.lines[self.cy].len() {
+            self.cx += 1;
+        } else if self.cy + 1 < self.lines.len() {
+            self.cy += 1;
+            self.cx = 0;
+        }
+    }
+
+    // Screen column of character `cx` on line `cy`, with tabs expanded
+    fn render_x(&self, cy: usize, cx: usize) -> usize {
+        self.lines[cy][..cx]
+            .iter()
+            .fold(0, |x, &c| if c == '\t' { x + TAB_STOP - x % TAB_STOP } else { x + 1 })
+    }
+
+    // Scroll so the cursor is on screen
+    fn scroll(&mut self) {
+        if self.cy < self.row_offset {
+            self.row_offset = self.cy;
+        }
+        if self.cy >= self.row_offset + self.rows {
+            self.row_offset = self.cy + 1 - self.rows;
+        }
+        let rx = self.render_x(self.cy, self.cx);
+        if rx < self.col_offset {
+            self.col_offset = rx;
+        }
+        if rx >= self.col_offset + self.cols {
+            self.col_offset = rx + 1 - self.cols;
+        }
+    }
+
+    // Escape sequence that redraws the whole screen
+    pub fn render(&self) -> String {
+        let mut out = String::from("\x1b[?25l\x1b[H");
+        for y in 0..self.rows {
+            let index = y + self.row_offset;
+            match self.lines.get(index) {
+                Some(line) => {
+                    let mut rendered = String::new();
+                    for &c in line {
+                        if c == '\t' {
+                            let width = TAB_STOP - rendered.chars().count() % TAB_STOP;
+                            rendered.extend(std::iter::repeat_n(' ', width));
+                        } else if c.is_control() {
+                            rendered.push('?');
+                        } else {
+                            rendered.push(c);
+                        }
+                    }
+                    out.extend(rendered.chars().skip(self.col_offset).take(self.cols));
+                }
+                None => out.push('~'),
+            }
+            out.push_str("\x1b[K\r\n");
+        }
+
+        // Status bar, in reverse video
+        let left = format!(
+            "{} - {} lines{}",
+            if self.path.is_empty() { "[No Name]" } else { self.path.as_str() },
+            self.lines.len(),
+            if self.dirty { " (modified)" } else { "" }
+        );
+        let right = format!("{}/{}", self.cy + 1, self.lines.len());
+        let left: String = left.chars().take(self.cols).collect();
+        let gap = self.cols.saturating_sub(left.chars().count());
+        out.push_str("\x1b[7m");
+        out.push_str(&left);
+        if gap >= right.len() {
+            out.push_str(&" ".repeat(gap - right.len()));
+            out.push_str(&right);
+        } else {
+            out.push_str(&" ".repeat(gap));
+        }
+        out.push_str("\x1b[m\r\n\x1b[K");
+
+        // Message bar, or the prompt being answered
+        let (row, col) = match &self.prompt {
+            Some(prompt) => {
+                let label = match prompt.kind {
+                    PromptKind::SaveAs => "Save as",
+                    PromptKind::Search { .. } => "Search (arrows for next/previous, Esc to cancel)",
+                };
+                let text = format!("{}: {}", label, prompt.input);
+                let text: String = text.chars().take(self.cols).collect();
+                out.push_str(&text);
+                (self.rows + 2, text.chars().count() + 1)
+            }
+            None => {
+                out.extend(self.message.chars().take(self.cols));
+                let rx = self.render_x(self.cy, self.cx);
+                (self.cy - self.row_offset + 1, rx - self.col_offset + 1)
+            }
+        };
+        out.push_str(&format!("\x1b[{};{}H\x1b[?25h", row, col));
+        out
+    }
+}
+
+// Function to edit `path` full-screen on the console's terminal until the user quits
+pub fn edit(system: &System, session: &Session, console: &mut dyn Console, path: &str) -> Result<()> {
+    let (rows, cols) = console
+        .screen_size()
+        .ok_or_else(|| RnixError::InvalidArgument("full-screen editing needs a terminal".to_string()))?;
+    let contents = if system.is_file(session, path) {
+        system.read_file(session, path)?
+    } else if system.is_dir(session, path) {
+        return Err(RnixError::InvalidArgument(format!("{}: Is a directory", path)));
+    } else {
+        Vec::new()
+    };
+    let mut visual = Visual::new(path, &String::from_utf8_lossy(&contents), rows, cols);
+
+    console.enter_screen()?;
+    let result = run(system, session, console, &mut visual);
+    console.leave_screen()?;
+    result
+}
+
+fn run(system: &System, session: &Session, console: &mut dyn Console, visual: &mut Visual) -> Result<()> {
+    loop {
+        if let Some((rows, cols)) = console.screen_size() {
+            visual.resize(rows, cols);
+        }
+        console.write(&visual.render())?;
+        let key = match console.read_key()? {
+            Some(key) => key,
+            None => return Ok(()),
+        };
+        match visual.feed(key) {
+            Action::None => {}
+            Action::Save(path) => {
+                let contents = visual.contents();
+                let result = save(system, session, &path, contents.as_bytes());
+                visual.saved(result.map(|()| contents.len()));
+            }
+            Action::Quit => return Ok(()),
+        }
+    }
+}
+
+// Write the file with `write_file`, creating it first if needed
+fn save(system: &System, session: &Session, path: &str, contents: &[u8]) -> Result<()> {
+    if !system.is_file(session, path) {
+        system.touch(session, path)?;
+    }
+    system.write_file(session, path, contents)
+}
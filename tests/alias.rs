@@ -0,0 +1,75 @@
+mod common;
+
+use common::Fixture;
+use rnix::alias::{parse_definition, split_commands, Aliases, Definition};
+
+const LOGIN: &str = "alice\nsecret\nalice\nsecret\n";
+
+#[test]
+fn alias_expansion_stops_at_repeats() {
+    let mut aliases = Aliases::new();
+    aliases.set("ls", "ls -a").unwrap();
+    aliases.set("ll", "ls -l").unwrap();
+    aliases.set("a", "b x").unwrap();
+    aliases.set("b", "a y").unwrap();
+    aliases.set("s", "sudo ").unwrap();
+    aliases.set("m", "mount").unwrap();
+
+    assert_eq!(aliases.expand("ll docs"), "ls -a -l docs");
+    assert_eq!(aliases.expand("  ls"), "  ls -a");
+    assert_eq!(aliases.expand("a"), "a y x");
+    assert_eq!(aliases.expand("s m disk1"), "sudo  mount disk1");
+    assert_eq!(aliases.expand("echo ll"), "echo ll");
+    assert!(aliases.set("bad name", "x").is_err());
+}
+
+#[test]
+fn function_definitions_are_recognised() {
+    assert_eq!(
+        parse_definition("greet() { echo hi; echo \"a;b\" }").unwrap(),
+        Definition::Complete("greet".to_string(), vec!["echo hi".to_string(), "echo \"a;b\"".to_string()])
+    );
+    assert_eq!(
+        parse_definition("function up {").unwrap(),
+        Definition::Open("up".to_string(), Vec::new())
+    );
+    assert_eq!(parse_definition("echo (hi)").unwrap(), Definition::None);
+    assert_eq!(parse_definition("ls").unwrap(), Definition::None);
+    assert!(parse_definition("9x() { ls; }").is_err());
+    assert_eq!(split_commands("a; 'b;c' ;; # note\n d"), vec!["a", "'b;c'", "d"]);
+}
+
+#[test]
+fn aliases_and_functions_in_the_shell() {
+    let fixture = Fixture::new();
+    let script = format!(
+        "{}touch notes\nalias ll='ls -l' l=ll\nalias\nl\nmk() {{ mkdir $1; cd $1; echo made $# $@; }}\nmk docs\npwd\n\
+         unalias ll\nl\nunalias nope\nloop() {{ loop; }}\nloop\nunset -f mk\nmk x\n",
+        LOGIN
+    );
+    let (status, output) = fixture.transcript(&script);
+    assert!(output.contains("alias l='ll'\nalias ll='ls -l'\n"));
+    // The alias carries its flag through to `ls`
+    assert!(output.contains("> Contents of current directory:\n-----          0 "), "{}", output);
+    assert!(output.contains("  notes\n"));
+    assert!(output.contains("Changed directory to 'docs'.\nmade 1 docs\n"));
+    assert!(output.contains("/home/alice/docs\n"));
+    assert!(output.contains("rnix: ll: command not found"));
+    assert!(output.contains("unalias: nope: not found"));
+    assert!(output.contains("loop: maximum function nesting level exceeded (100)"));
+    assert!(output.contains("rnix: mk: command not found"));
+    assert_eq!(status, 127);
+}
+
+#[test]
+fn rc_file_defines_multi_line_functions() {
+    let fixture = Fixture::new();
+    {
+        let (system, session) = fixture.login();
+        system.touch(&session, ".rnixrc").unwrap();
+        let rc = "alias h='echo hello'\nshout() {\n  echo $1!\n  h $2\n}\n";
+        system.write_file(&session, ".rnixrc", rc.as_bytes()).unwrap();
+    }
+    let (_, output) = fixture.transcript("alice\nsecret\nshout hey you\n");
+    assert!(output.contains("hey!\nhello you\n"));
+}
@@ -0,0 +1,110 @@
+mod common;
+
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use common::Fixture;
+use fatfs::FileAttributes;
+use rnix::clock::FixedTime;
+use rnix::fsck::{check, Options};
+use rnix::transfer::to_fat;
+use rnix::{MemoryConsole, Shell};
+
+// 2024-03-01 in UTC, give or take the local time zone
+const MARCH_2024: u64 = 1_709_251_200;
+
+#[test]
+fn timestamps_come_from_the_time_provider() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+    let start = SystemTime::UNIX_EPOCH + Duration::from_secs(MARCH_2024);
+    let clock = Arc::new(FixedTime::new(start));
+    system.set_time_provider(clock.clone());
+    assert_eq!(system.now(), start);
+
+    system.touch(&session, "notes").unwrap();
+    let created = system.metadata(&session, "notes").unwrap();
+    assert_eq!(created.modified, Some(to_fat(start)));
+    assert_eq!(created.created, Some(to_fat(start)));
+    assert_eq!(created.accessed, Some(to_fat(start).date));
+
+    // Touching again moves the modification time but not the creation time
+    clock.advance(Duration::from_secs(2 * 24 * 60 * 60));
+    system.touch(&session, "notes").unwrap();
+    let touched = system.metadata(&session, "notes").unwrap();
+    assert_eq!(touched.modified, Some(to_fat(system.now())));
+    assert_eq!(touched.created, created.created);
+
+    // Writing uses the provider too
+    clock.advance(Duration::from_secs(60));
+    system.write_file(&session, "notes", b"hello").unwrap();
+    assert_eq!(system.metadata(&session, "notes").unwrap().modified, Some(to_fat(system.now())));
+}
+
+#[test]
+fn times_and_attributes_are_set_in_place() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    let (mut system, session) = fixture.login_root();
+    system.mount("disk1").unwrap();
+    let when = SystemTime::UNIX_EPOCH + Duration::from_secs(MARCH_2024);
+    let paths = ["/home/alice/A Long File Name.txt", "/volumes/disk1/deep", "/volumes/disk1/deep/Inner File"];
+    system.touch(&session, paths[0]).unwrap();
+    system.mkdir(&session, paths[1]).unwrap();
+    system.touch(&session, paths[2]).unwrap();
+
+    for p in paths {
+        system.set_modified(&session, p, when).unwrap();
+        assert_eq!(system.metadata(&session, p).unwrap().modified, Some(to_fat(when)), "{}", p);
+    }
+
+    let file = paths[2];
+    let set = FileAttributes::HIDDEN | FileAttributes::READ_ONLY;
+    let attributes = system.set_attributes(&session, file, set, FileAttributes::empty()).unwrap();
+    assert_eq!(attributes, set);
+    let attributes = system
+        .set_attributes(&session, file, FileAttributes::SYSTEM | FileAttributes::ARCHIVE, FileAttributes::READ_ONLY)
+        .unwrap();
+    assert_eq!(attributes, FileAttributes::HIDDEN | FileAttributes::SYSTEM | FileAttributes::ARCHIVE);
+    assert_eq!(system.metadata(&session, file).unwrap().flags(), "--hsa");
+    // A directory stays one whatever is asked for
+    let dir = system.set_attributes(&session, paths[1], FileAttributes::DIRECTORY, FileAttributes::DIRECTORY).unwrap();
+    assert_eq!(dir, FileAttributes::DIRECTORY);
+    // Nor does the data move
+    system.write_file(&session, file, b"still readable").unwrap();
+    assert_eq!(system.read_file(&session, file).unwrap(), b"still readable");
+
+    assert!(system.set_modified(&session, "/volumes/disk1", when).is_err());
+    assert!(system.set_modified(&session, "/home/alice/missing", when).is_err());
+    assert!(system.metadata(&session, "/").unwrap().modified.is_none());
+
+    system.unmount("disk1").unwrap();
+    let report = check(&fixture.dir.path().join("disk1.img"), Options::default()).unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+}
+
+#[test]
+fn touch_stat_and_attrib_from_the_shell() {
+    let fixture = Fixture::new();
+    drop(fixture.login());
+    let system = fixture.open();
+    system.set_time_provider(Arc::new(FixedTime::new(SystemTime::UNIX_EPOCH + Duration::from_secs(MARCH_2024))));
+    let script = "alice\nsecret\ntouch notes\ntouch notes\ntouch -t 202401021530.44 notes\nstat notes\n\
+                  touch -t 01021530 notes\nstat notes\nattrib +h +R notes\nattrib notes\nattrib -h notes\n\
+                  attrib +x notes\nstat /\ntouch -t 99 other\ntouch -t 2401021530.44 other\nstat other\n";
+    let mut shell = Shell::new(system, MemoryConsole::new(script));
+    shell.run().unwrap();
+    let output = shell.console_mut().take_output();
+
+    assert_eq!(output.matches("File 'notes' created.").count(), 1);
+    assert!(output.contains("      File: notes\n      Size: 0\n      Type: regular file\nShort name: NOTES\n"));
+    assert!(output.contains("Attributes: ----- (none)\n   Created: 2024-03-0"));
+    assert!(output.contains("  Modified: 2024-01-02 15:30:44\n  Accessed: 2024-01-02\n"));
+    // Without a year, the current one
+    assert!(output.contains("  Modified: 2024-01-02 15:30:00\n"));
+    assert_eq!(output.matches("  Modified: 2024-01-02 15:30:44\n").count(), 2);
+    assert!(output.contains("-rh--  notes\n") && output.contains("-r---  notes\n"));
+    assert!(output.contains("usage: attrib [+r|-r] [+h|-h] [+s|-s] [+a|-a] <path>..."));
+    assert!(output.contains("Attributes: d---- (directory)\nThe root of a disk has no timestamps."));
+    assert!(output.contains("touch: invalid date format '99'"));
+}
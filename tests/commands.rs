@@ -0,0 +1,117 @@
+mod common;
+
+use common::Fixture;
+use rnix::command::{Privilege, Spec};
+use rnix::shell::Flow;
+use rnix::{Builtin, MemoryConsole, Registry, RnixError, Shell};
+
+const LOGIN: &str = "alice\nsecret\nalice\nsecret\n";
+
+#[test]
+fn spec_parses_flags_options_and_positionals() {
+    let spec = Spec::new("[-s] [-d N] <path>...").args(1, None).flag('s').flag('h').option('d');
+
+    let args = spec.parse("du", &["-sh", "-d", "2", "docs", "-x"]).unwrap();
+    assert!(args.has('s') && args.has('h'));
+    assert_eq!(args.value('d'), Some("2"));
+    assert_eq!(args.positional(), ["docs", "-x"]);
+
+    let args = spec.parse("du", &["-d3", "--", "-s"]).unwrap();
+    assert_eq!(args.value('d'), Some("3"));
+    assert!(!args.has('s'));
+    assert_eq!(args.positional(), ["-s"]);
+
+    // "-" on its own is an argument, not an option
+    assert_eq!(spec.parse("du", &["-"]).unwrap().positional(), ["-"]);
+
+    let usage = "usage: du [-s] [-d N] <path>...";
+    assert_eq!(spec.parse("du", &[]).unwrap_err().to_string(), usage);
+    assert_eq!(spec.parse("du", &["-q", "x"]).unwrap_err().to_string(), usage);
+    assert_eq!(spec.parse("du", &["x", "-d"]).unwrap().positional(), ["x", "-d"]);
+    assert!(matches!(spec.parse("du", &["-d"]), Err(RnixError::Usage(_))));
+}
+
+#[test]
+fn spec_accepts_long_names() {
+    let spec = Spec::new("[--summarize] [--max-depth=N] <path>")
+        .args(1, Some(1))
+        .flag('s')
+        .long("summarize", 's')
+        .option('d')
+        .long("max-depth", 'd');
+
+    let args = spec.parse("du", &["--summarize", "--max-depth=2", "docs"]).unwrap();
+    assert!(args.has('s'));
+    assert_eq!(args.value('d'), Some("2"));
+    assert_eq!(spec.parse("du", &["--max-depth", "4", "x"]).unwrap().value('d'), Some("4"));
+    assert!(spec.parse("du", &["--summarize=yes", "x"]).is_err());
+    assert!(spec.parse("du", &["--nope", "x"]).is_err());
+}
+
+#[test]
+fn registry_finds_commands_by_name_and_alias() {
+    let mut registry = Registry::with_builtins();
+    assert_eq!(registry.find("cls").unwrap().name(), "clear");
+    assert_eq!(registry.find("mount").unwrap().privilege(), Privilege::Sudo);
+    assert!(registry.find("bogus").is_none());
+
+    assert!(registry.unregister("clear"));
+    assert!(registry.find("cls").is_none());
+    assert!(!registry.names().contains(&"clear".to_string()));
+}
+
+#[test]
+fn help_describes_a_single_command() {
+    let fixture = Fixture::new();
+    let (status, output) = fixture.transcript(&format!("{}help mount\nhelp nope\nexit\n", LOGIN));
+    assert!(output.contains("usage: mount [-n] [-o ro|rw] <disk_name>\n    Mount a disk and change to it (-n stays put, -o ro mounts it read-only)\n    Requires sudo.\n"));
+    assert!(output.contains("help: no help topics match 'nope'"));
+    assert_eq!(status, 1);
+}
+
+#[test]
+fn argument_counts_are_checked_before_running() {
+    let fixture = Fixture::new();
+    let script = format!("{}cd a b\nls -x\nhistory -x\npwd extra\n", LOGIN);
+    let (status, output) = fixture.transcript(&script);
+    assert!(output.contains("cd: usage: cd [directory | -]"));
+    assert!(output.contains("ls: usage: ls [-l] [directory...]"));
+    assert!(output.contains("history: usage: history [-c | -w | N]"));
+    assert!(output.contains("pwd: usage: pwd"));
+    assert_eq!(status, 2);
+}
+
+#[test]
+fn library_users_can_register_commands() {
+    let fixture = Fixture::new();
+    let mut shell = Shell::new(fixture.open(), MemoryConsole::new(&format!("{}greet -l bob\nhi\nwhoami\n", LOGIN)));
+    shell.register(
+        Builtin::new("greet", Spec::new("[-l] <name>").args(1, Some(1)).flag('l'), "Say hello", |ctx, args| {
+            let name = args.required(0)?;
+            let greeting = if args.has('l') { "HELLO" } else { "hello" };
+            writeln!(ctx.console, "{} {} from {}", greeting, name, ctx.session.user())?;
+            Ok(Flow::Continue)
+        })
+        .alias("hi"),
+    );
+    // Registering an existing name replaces the built-in
+    shell.register(Builtin::new("whoami", Spec::new(""), "Leave", |_, _| Ok(Flow::Exit(7))));
+
+    let status = shell.run().unwrap();
+    let output = shell.console_mut().take_output();
+    assert!(output.contains("HELLO bob from alice"));
+    assert!(output.contains("hi: usage: greet [-l] <name>"));
+    assert!(!output.contains("> alice\n"));
+    assert_eq!(status, 7);
+}
+
+#[test]
+fn sudo_only_elevates_the_command_it_runs() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    let script = format!("{}f() {{ sudo echo hi; mount -n disk1; }}\nf\nsecret\nlistdisks\n", LOGIN);
+    let (_, output) = fixture.transcript(&script);
+    assert!(output.contains("Password: hi\n"), "{}", output);
+    assert!(output.contains("mount: Permission denied: This command requires sudo privileges."));
+    assert!(output.contains("No disks currently mounted."));
+}
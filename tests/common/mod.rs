@@ -0,0 +1,66 @@
+// Shared fixtures for the integration tests: temporary directories holding
+// freshly formatted rnix images.
+#![allow(dead_code)]
+
+use std::path::PathBuf;
+
+use rnix::disk::{dformat, ocdi, DISK_IMAGE_SIZE};
+use rnix::{MemoryConsole, Session, Shell, System};
+use tempfile::TempDir;
+
+pub const USER: &str = "alice";
+pub const PASSWORD: &str = "secret";
+pub const ROOT_PASSWORD: &str = "iloveapple";
+
+pub struct Fixture {
+    pub dir: TempDir,
+}
+
+impl Fixture {
+    // A temporary directory with a formatted, not yet set up, root image
+    pub fn new() -> Fixture {
+        let dir = tempfile::tempdir().unwrap();
+        let fixture = Fixture { dir };
+        fixture.image("rnix");
+        fixture
+    }
+
+    pub fn root_image(&self) -> PathBuf {
+        self.dir.path().join("rnix.img")
+    }
+
+    // Create and format `<name>.img` next to the root image
+    pub fn image(&self, name: &str) -> PathBuf {
+        let path = self.dir.path().join(format!("{}.img", name));
+        drop(ocdi(&path, DISK_IMAGE_SIZE).unwrap());
+        dformat(&path).unwrap();
+        path
+    }
+
+    pub fn open(&self) -> System {
+        System::open(self.root_image()).unwrap()
+    }
+
+    // Open the root image with the standard user account set up and logged in
+    pub fn login(&self) -> (System, Session) {
+        let mut system = self.open();
+        if system.needs_setup() {
+            system.setup(USER, PASSWORD).unwrap();
+        }
+        let session = system.authenticate(USER, PASSWORD).unwrap();
+        (system, session)
+    }
+
+    pub fn login_root(&self) -> (System, Session) {
+        let (system, _) = self.login();
+        let session = system.authenticate("root", ROOT_PASSWORD).unwrap();
+        (system, session)
+    }
+
+    // Run a scripted shell session against the root image and return its output
+    pub fn transcript(&self, script: &str) -> (i32, String) {
+        let mut shell = Shell::new(self.open(), MemoryConsole::new(script));
+        let status = shell.run().unwrap();
+        (status, shell.console_mut().take_output())
+    }
+}
@@ -0,0 +1,115 @@
+mod common;
+
+use common::Fixture;
+use rnix::complete::{Completer, Completion, ShellCompleter};
+use rnix::history::History;
+use rnix::readline::{Key, LineEditor, Outcome};
+use rnix::Registry;
+use rnix::{Session, System};
+
+fn completer<'a>(system: &'a System, session: &'a Session) -> ShellCompleter<'a> {
+    ShellCompleter::new(system, session, Registry::with_builtins().names())
+}
+
+fn candidates(completer: &dyn Completer, line: &str) -> Vec<String> {
+    completer.complete(line, line.chars().count()).candidates
+}
+
+// Offers a fixed list for every word
+struct Fixed(Vec<&'static str>);
+
+impl Completer for Fixed {
+    fn complete(&self, line: &str, cursor: usize) -> Completion {
+        let start = line[..cursor].rfind(' ').map_or(0, |idx| idx + 1);
+        Completion {
+            start,
+            candidates: self
+                .0
+                .iter()
+                .filter(|c| c.starts_with(&line[start..cursor]))
+                .map(|c| c.to_string())
+                .collect(),
+        }
+    }
+}
+
+#[test]
+fn first_word_completes_command_names() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+    let completer = completer(&system, &session);
+
+    assert_eq!(candidates(&completer, "his"), vec!["history"]);
+    assert_eq!(candidates(&completer, "c"), vec!["cd", "clear", "cls", "compact", "cp", "createdisk", "cut"]);
+    assert_eq!(candidates(&completer, "sudo mo"), vec!["mount"]);
+}
+
+#[test]
+fn arguments_complete_paths_through_the_mount_table() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    let (mut system, session) = fixture.login();
+    system.mkdir(&session, "Documents").unwrap();
+    system.touch(&session, "Documents/my notes.txt").unwrap();
+    system.touch(&session, "draft").unwrap();
+    system.touch(&session, "price [$5].txt").unwrap();
+    system.mount("disk1").unwrap();
+    system.mkdir(&session, "/volumes/disk1/backup").unwrap();
+
+    let completer = completer(&system, &session);
+    assert_eq!(candidates(&completer, "ls d"), vec!["Documents/", "draft"]);
+    assert_eq!(candidates(&completer, "cat Documents/m"), vec!["Documents/my\\ notes.txt"]);
+    assert_eq!(candidates(&completer, "rm pr"), vec!["price\\ \\[\\$5].txt"]);
+    assert_eq!(candidates(&completer, "rm price\\ \\[\\$"), vec!["price\\ \\[\\$5].txt"]);
+    assert_eq!(candidates(&completer, "cd /vol"), vec!["/volumes/"]);
+    assert_eq!(candidates(&completer, "cd /volumes/disk1/b"), vec!["/volumes/disk1/backup/"]);
+    assert!(candidates(&completer, "ls missing/").is_empty());
+}
+
+#[test]
+fn disk_arguments_complete_from_the_system() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    let (mut system, session) = fixture.login();
+
+    assert_eq!(candidates(&completer(&system, &session), "mount "), vec!["disk1", "disk2"]);
+    system.mount("disk1").unwrap();
+    let completer = completer(&system, &session);
+    assert_eq!(candidates(&completer, "sudo mount d"), vec!["disk2"]);
+    assert_eq!(candidates(&completer, "umount "), vec!["disk1"]);
+    assert_eq!(candidates(&completer, "readdisk disk"), vec!["disk0", "disk1", "disk2"]);
+}
+
+#[test]
+fn tab_inserts_unique_matches_and_common_prefixes() {
+    let history = History::new();
+    let completer = Fixed(vec!["mount", "mkdir", "history", "histogram", "dir/"]);
+
+    let mut editor = LineEditor::new(&history);
+    for c in "mo".chars() {
+        editor.feed(Key::Char(c));
+    }
+    assert_eq!(editor.feed(Key::Tab), Outcome::Complete);
+    assert!(editor.complete(&completer).is_empty());
+    assert_eq!(editor.line(), "mount ");
+
+    // Ambiguous: extend to the shared prefix, then list the matches
+    for c in "hi".chars() {
+        editor.feed(Key::Char(c));
+    }
+    assert!(editor.complete(&completer).is_empty());
+    assert_eq!(editor.line(), "mount histo");
+    assert_eq!(editor.complete(&completer), vec!["history", "histogram"]);
+    assert_eq!(editor.line(), "mount histo");
+
+    // Directories don't get a trailing space; text after the cursor is kept
+    let mut editor = LineEditor::new(&history);
+    for c in "d x".chars() {
+        editor.feed(Key::Char(c));
+    }
+    editor.feed(Key::Left);
+    editor.feed(Key::Left);
+    editor.complete(&completer);
+    assert_eq!(editor.line(), "dir/ x");
+    assert_eq!(editor.cursor(), 4);
+}
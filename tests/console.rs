@@ -0,0 +1,59 @@
+use rnix::{MemoryConsole, Shell, System};
+
+fn shell_with(dir: &tempfile::TempDir, script: &str) -> Shell<MemoryConsole> {
+    let system = System::open(dir.path().join("rnix.img")).unwrap();
+    Shell::new(system, MemoryConsole::new(script))
+}
+
+#[test]
+fn scripted_setup_login_and_commands() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut shell = shell_with(
+        &dir,
+        "alice\nsecret\n\
+         alice\nsecret\n\
+         pwd\nmkdir docs\ncd docs\ntouch notes.txt\nls\nwhoami\nexit\n",
+    );
+
+    assert_eq!(shell.run().unwrap(), 0);
+    let output = shell.console().output();
+    assert!(output.contains("User account created."));
+    assert!(output.contains("alice(rnix) > /home/alice\n"));
+    assert!(output.contains("Directory 'docs' created."));
+    assert!(output.contains("Contents of current directory:\nnotes.txt\n"));
+    assert!(output.contains("alice(rnix) > alice\n"));
+}
+
+#[test]
+fn rejected_login_prompts_again_and_eof_ends_the_session() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut shell = shell_with(&dir, "bob\nhunter2\nbob\nwrong\nbob\nhunter2\nfrobnicate\n");
+
+    assert_eq!(shell.run().unwrap(), 127);
+    let output = shell.console().output();
+    assert_eq!(output.matches("RNIX | LogIn").count(), 2);
+    assert!(output.contains("Invalid username or password. Please try again."));
+    assert!(output.contains("rnix: frobnicate: command not found"));
+}
+
+#[test]
+fn edit_appends_lines_and_leaves_input_for_the_shell() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut shell = shell_with(&dir, "carol\npw\ncarol\npw\ntouch todo\nedit todo\na\nfirst\nsecond\n.\nwq\nexit 5\n");
+
+    assert_eq!(shell.run().unwrap(), 5);
+    let session = shell.system().authenticate("carol", "pw").unwrap();
+    let contents = shell.system().read_file(&session, "todo").unwrap();
+    assert_eq!(contents, b"first\nsecond\n");
+}
+
+#[test]
+fn sudo_reads_the_password_from_the_console() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut shell = shell_with(&dir, "dave\npw\ndave\npw\nsudo mount disk1\nnope\nsudo mount disk1\npw\n");
+
+    shell.run().unwrap();
+    let output = shell.console().output();
+    assert!(output.contains("sudo: Authentication failed"));
+    assert!(output.contains("disk1.img: No such file or directory"));
+}
@@ -0,0 +1,64 @@
+mod common;
+
+use common::Fixture;
+
+const LOGIN: &str = "alice\nsecret\nalice\nsecret\n";
+
+// Run `commands` in the editor on `notes` after seeding it with `contents`
+fn edit(contents: &str, commands: &str) -> (String, String) {
+    let fixture = Fixture::new();
+    {
+        let (system, session) = fixture.login();
+        system.touch(&session, "notes").unwrap();
+        system.write_file(&session, "notes", contents.as_bytes()).unwrap();
+    }
+    let (_, output) = fixture.transcript(&format!("alice\nsecret\nedit notes\n{}whoami\n", commands));
+    let (system, session) = fixture.login();
+    let saved = String::from_utf8(system.read_file(&session, "notes").unwrap()).unwrap();
+    (output, saved)
+}
+
+#[test]
+fn lines_are_addressed_printed_and_changed() {
+    let (output, saved) = edit(
+        "one\ntwo\nthree\nfour\n",
+        "2\n,n\n$d\n1a\none and a half\n.\n/three/i\nbefore three\n.\n0a\nzero\n.\n2,3c\nONE\n.\n=\n.=\nw\nq\n",
+    );
+    assert!(output.contains("> 19\ntwo\n1\tone\n2\ttwo\n3\tthree\n4\tfour\n"));
+    assert!(output.contains("5\n2\n"));
+    assert_eq!(saved, "zero\nONE\ntwo\nbefore three\nthree\n");
+    // The shell still reads commands after the editor quits
+    assert!(output.ends_with("alice\nalice(rnix) > "));
+}
+
+#[test]
+fn substitute_uses_regular_expressions_and_undo_reverts_it() {
+    let (output, saved) = edit(
+        "cat hat\nbat\nmat\n",
+        "1s/(.)at/<\\1&>/g\n1p\n2,3s/a/o/\n,p\nu\n,p\nu\n3s/x/y/\ns|o|0|\np\nwq\n",
+    );
+    assert!(output.contains("<ccat> <hhat>\n"));
+    assert!(output.contains("<ccat> <hhat>\nbot\nmot\n<ccat> <hhat>\nbat\nmat\n"));
+    assert!(output.contains("? no match\n"));
+    assert!(output.contains("m0t\n"));
+    assert_eq!(saved, "<ccat> <hhat>\nbot\nm0t\n");
+}
+
+#[test]
+fn quitting_with_changes_warns_once() {
+    let (output, saved) = edit("keep\n", "d\nq\nfrobnicate\n9\nq\nq\n");
+    assert!(output.contains("? warning: buffer modified"));
+    assert!(output.contains("? unknown command `f'\n? invalid address\n"));
+    assert_eq!(saved, "keep\n");
+    assert!(output.ends_with("alice\nalice(rnix) > "));
+}
+
+#[test]
+fn new_files_are_created_on_write() {
+    let fixture = Fixture::new();
+    let script = format!("{}edit fresh.txt\na\nhello\n.\nw\nQ\nedit /home\nedit fresh.txt\np\nq\n", LOGIN);
+    let (_, output) = fixture.transcript(&script);
+    assert!(output.contains("fresh.txt: No such file\n6\n"));
+    assert!(output.contains("edit: /home: Is a directory"));
+    assert!(output.contains("> 6\nhello\n"));
+}
@@ -0,0 +1,71 @@
+mod common;
+
+use common::Fixture;
+use rnix::env::{expand_prompt, Environment, PromptInfo};
+use rnix::tokenizer::{tokenize, tokenize_with};
+
+const LOGIN: &str = "alice\nsecret\nalice\nsecret\n";
+
+fn expand(line: &str) -> Vec<String> {
+    let mut env = Environment::standard("alice", "/home/alice");
+    env.set("DIR", "my docs").unwrap();
+    env.set("EMPTY", "").unwrap();
+    let lookup = |name: &str| match name {
+        "?" => Some("3".to_string()),
+        _ => env.get(name).map(str::to_string),
+    };
+    tokenize_with(line, &lookup).unwrap()
+}
+
+#[test]
+fn tokenizer_expands_variables_outside_single_quotes() {
+    assert_eq!(expand("cd $HOME/x"), vec!["cd", "/home/alice/x"]);
+    assert_eq!(expand("ls ${DIR}x \"$DIR\""), vec!["ls", "my docsx", "my docs"]);
+    assert_eq!(expand("echo '$HOME' \\$HOME \"\\$HOME\""), vec!["echo", "$HOME", "$HOME", "$HOME"]);
+    assert_eq!(expand("echo $? $NOPE$ $ \"$EMPTY\" $EMPTY"), vec!["echo", "3", "$", "$", ""]);
+    assert!(tokenize_with("echo ${HOME", &|_| None).is_err());
+    assert!(tokenize_with("echo ${1x}", &|_| None).is_err());
+    // Plain tokenizing leaves `$` alone
+    assert_eq!(tokenize("echo $HOME").unwrap(), vec!["echo", "$HOME"]);
+}
+
+#[test]
+fn environment_rejects_bad_names() {
+    let mut env = Environment::new();
+    assert!(env.set("1X", "a").is_err());
+    assert!(env.set("A-B", "a").is_err());
+    env.set("_ok9", "a").unwrap();
+    assert!(env.unset("_ok9"));
+    assert!(!env.unset("_ok9"));
+}
+
+#[test]
+fn prompt_escapes() {
+    let info = PromptInfo {
+        user: "alice",
+        cwd: "/home/alice/docs",
+        home: "/home/alice",
+        disk: "disk0",
+        status: 1,
+    };
+    assert_eq!(expand_prompt("\\u(rnix) > ", &info), "alice(rnix) > ");
+    assert_eq!(expand_prompt("[\\u@\\h:\\w \\W on \\D (\\?)]\\$ \\\\x\\q", &info), "[alice@rnix:~/docs docs on disk0 (1)]$ \\x\\q");
+}
+
+#[test]
+fn export_unset_env_and_prompt_in_the_shell() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    let script = format!(
+        "{}export GREETING=hi NAME\nenv\nmkdir $GREETING\ncd\ncd $GREETING\ncd -\nunset GREETING\n\
+         export PS1='\\u:\\w [\\?]> '\nls nowhere\nsudo mount disk1\nsecret\nexport PS1='\\D \\W> '\nexport 2x=1\n",
+        LOGIN
+    );
+    let (status, output) = fixture.transcript(&script);
+    assert!(output.contains("GREETING=hi\nHOME=/home/alice\nPATH=/internal/bin\nPS1=\\u(rnix) > \nPWD=/home/alice\nUSER=alice\n"));
+    assert!(output.contains("Directory 'hi' created."));
+    assert!(output.contains("Changed directory to 'hi'.\nalice(rnix) > Changed directory to '/home/alice'."));
+    assert!(output.contains("alice:~ [0]> ls: /home/alice/nowhere: No such file or directory\nalice:~ [1]> "));
+    assert!(output.contains("disk1 disk1> export: `2x': not a valid identifier\ndisk1 disk1> "));
+    assert_eq!(status, 1);
+}
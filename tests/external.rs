@@ -0,0 +1,83 @@
+mod common;
+
+use std::fs;
+
+use common::Fixture;
+use rnix::external::{edit, Outcome};
+
+const LOGIN: &str = "alice\nsecret\nalice\nsecret\n";
+
+#[test]
+fn changes_made_in_the_editor_are_written_back() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+    system.touch(&session, "list.txt").unwrap();
+    system.write_file(&session, "list.txt", b"milk\n").unwrap();
+
+    let mut seen = None;
+    let outcome = edit(&system, &session, "list.txt", |copy| {
+        assert!(copy.to_string_lossy().ends_with("list.txt"));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(copy).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        assert_eq!(fs::read(copy).unwrap(), b"milk\n");
+        fs::write(copy, "milk\neggs\n").unwrap();
+        seen = Some(copy.to_path_buf());
+        Ok(())
+    })
+    .unwrap();
+    assert_eq!(outcome, Outcome::Saved(10));
+    assert_eq!(system.read_file(&session, "list.txt").unwrap(), b"milk\neggs\n");
+    assert!(!seen.unwrap().exists());
+
+    assert_eq!(edit(&system, &session, "list.txt", |_| Ok(())).unwrap(), Outcome::Unchanged);
+    assert_eq!(edit(&system, &session, "new.txt", |_| Ok(())).unwrap(), Outcome::Unchanged);
+    assert!(!system.is_file(&session, "new.txt"));
+}
+
+#[test]
+fn concurrent_changes_in_the_image_are_not_overwritten() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+    system.touch(&session, "list.txt").unwrap();
+    system.write_file(&session, "list.txt", b"milk\n").unwrap();
+
+    let err = edit(&system, &session, "list.txt", |copy| {
+        fs::write(copy, "mine\n").unwrap();
+        system.write_file(&session, "list.txt", b"theirs\n").unwrap();
+        Ok(())
+    })
+    .unwrap_err()
+    .to_string();
+    assert!(err.starts_with("list.txt: changed in the image while it was being edited; your version is in "));
+    let kept = err.rsplit(' ').next().unwrap();
+    assert_eq!(fs::read(kept).unwrap(), b"mine\n");
+    fs::remove_file(kept).unwrap();
+    assert_eq!(system.read_file(&session, "list.txt").unwrap(), b"theirs\n");
+}
+
+#[cfg(unix)]
+#[test]
+fn edit_external_runs_the_session_editor() {
+    let fixture = Fixture::new();
+    let script = format!(
+        "{}export EDITOR=\"sed -i 's/milk/oat milk/'\"\ntouch list\nedit --external list\nedit -e list\n\
+         export EDITOR=false\nedit --external list\nedit -v --external list\n",
+        LOGIN
+    );
+    let (_, output) = fixture.transcript(&script);
+    assert!(output.contains("No changes to 'list'.\n"));
+    assert!(output.contains("edit: false exited with exit status: 1; nothing saved"));
+    assert!(output.contains("edit: usage: edit [-v | --external] <file_name>"));
+
+    let (system, session) = fixture.login();
+    system.write_file(&session, "list", b"milk\n").unwrap();
+    drop(system);
+    let script = "alice\nsecret\nexport EDITOR=\"sed -i 's/milk/oat milk/'\"\nedit --external list\n";
+    let (_, output) = fixture.transcript(script);
+    assert!(output.contains("File 'list' has been updated (9 bytes).\n"));
+    let (system, session) = fixture.login();
+    assert_eq!(system.read_file(&session, "list").unwrap(), b"oat milk\n");
+}
@@ -0,0 +1,85 @@
+mod common;
+
+use std::time::{Duration, SystemTime};
+
+use common::Fixture;
+use rnix::find::{search, Action, Compare, Query, Test};
+
+fn paths(found: &[rnix::find::Found]) -> Vec<&str> {
+    found.iter().map(|found| found.path.as_str()).collect()
+}
+
+#[test]
+fn expressions_parse_into_tests_and_actions() {
+    let query = Query::parse(&["docs", "/tmp", "-name", "*.txt", "-type", "f", "-size", "+2k", "-mtime", "-3"]).unwrap();
+    assert_eq!(query.paths, vec!["docs", "/tmp"]);
+    assert_eq!(
+        query.tests,
+        vec![
+            Test::Name("*.txt".to_string()),
+            Test::Type(false),
+            Test::Size(Compare::More(2), 1024),
+            Test::MTime(Compare::Less(3)),
+        ]
+    );
+    assert_eq!(query.actions, vec![Action::Print]);
+
+    let query = Query::parse(&["-maxdepth", "1", "-exec", "rm", "{}", ";", "-print"]).unwrap();
+    assert_eq!(query.paths, vec!["."]);
+    assert_eq!(query.max_depth, Some(1));
+    assert_eq!(query.actions, vec![Action::Exec(vec!["rm".to_string(), "{}".to_string()]), Action::Print]);
+
+    for bad in [&["-frob"][..], &["-type", "x"], &["-exec", "rm", "{}"], &["-size", "big"], &["-name"]] {
+        assert!(Query::parse(bad).is_err(), "{:?}", bad);
+    }
+}
+
+#[test]
+fn search_walks_the_tree_and_mounted_disks() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    let (mut system, session) = fixture.login_root();
+    system.mount("disk1").unwrap();
+    system.mkdir(&session, "/home/alice/docs").unwrap();
+    for (file, len) in [("/home/alice/docs/a.txt", 10), ("/home/alice/docs/big.bin", 5000), ("/volumes/disk1/b.txt", 1)] {
+        system.touch(&session, file).unwrap();
+        system.write_file(&session, file, &vec![b'x'; len]).unwrap();
+    }
+    let now = SystemTime::now();
+    let find = |words: &[&str]| {
+        let query = Query::parse(words).unwrap();
+        search(&system, &session, &query, &query.paths[0], now).unwrap()
+    };
+
+    assert_eq!(paths(&find(&["/", "-name", "*.TXT"])), vec!["/home/alice/docs/a.txt", "/volumes/disk1/b.txt"]);
+    assert_eq!(paths(&find(&["/home/alice", "-type", "d"])), vec!["/home/alice", "/home/alice/docs"]);
+    assert_eq!(paths(&find(&["/home", "-size", "+4k"])), vec!["/home/alice/docs/big.bin"]);
+    assert_eq!(paths(&find(&["/home", "-size", "10c", "-type", "f"])), vec!["/home/alice/docs/a.txt"]);
+    assert_eq!(paths(&find(&["/home/alice", "-maxdepth", "1"])), vec!["/home/alice", "/home/alice/docs"]);
+    assert_eq!(find(&["/home/alice/docs", "-mtime", "0", "-type", "f"]).len(), 2);
+    assert!(find(&["/home/alice/docs", "-mtime", "+1"]).is_empty());
+
+    // Deleting wants entries after their contents
+    let found = find(&["/home/alice", "-delete"]);
+    assert_eq!(paths(&found).last(), Some(&"/home/alice"));
+    assert_eq!(paths(&found)[0], "/home/alice/docs/a.txt");
+
+    // A week from now the files are six days old
+    let later = now + Duration::from_secs(7 * 24 * 60 * 60);
+    let query = Query::parse(&["/home/alice/docs", "-mtime", "+5", "-type", "f"]).unwrap();
+    assert_eq!(search(&system, &session, &query, "/home/alice/docs", later).unwrap().len(), 2);
+}
+
+#[test]
+fn find_prints_deletes_and_runs_commands_from_the_shell() {
+    let fixture = Fixture::new();
+    let script = "alice\nsecret\nalice\nsecret\nmkdir logs\ntouch logs/a.log logs/b.log notes.txt\n\
+                  find . -name '*.txt'\nfind logs -name a.log -exec echo found {} \\;\n\
+                  find . -name '*.log' -delete\nfind logs\nfind missing\nfind -bogus\n";
+    let (_, output) = fixture.transcript(script);
+    assert!(output.contains("> ./notes.txt\nalice"));
+    assert!(output.contains("found logs/a.log\n"));
+    assert!(output.contains("> logs\nalice(rnix) > "));
+    assert!(output.contains("find: missing: No such file or directory"));
+    assert!(output.contains("find: unknown predicate '-bogus'"));
+}
@@ -0,0 +1,191 @@
+mod common;
+
+use std::fs::{self, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::Path;
+use std::process::Command;
+
+use common::Fixture;
+use rnix::fsck::{check, Options};
+
+const REPORT: Options = Options { repair: false, mounted: false };
+const REPAIR: Options = Options { repair: true, mounted: false };
+
+// Offsets of the first FAT, the second FAT and cluster 2, and the cluster size, of a FAT32 image
+fn layout(image: &Path) -> (u64, u64, u64, u64) {
+    let mut sector = [0u8; 512];
+    fs::File::open(image).unwrap().read_exact(&mut sector).unwrap();
+    let u16_at = |at: usize| u16::from_le_bytes([sector[at], sector[at + 1]]) as u64;
+    let bytes_per_sector = u16_at(11);
+    let reserved = u16_at(14);
+    let fats = sector[16] as u64;
+    let fat_sectors = u32::from_le_bytes([sector[36], sector[37], sector[38], sector[39]]) as u64;
+    let fat = reserved * bytes_per_sector;
+    let fat_bytes = fat_sectors * bytes_per_sector;
+    (fat, fat + fat_bytes, fat + fats * fat_bytes, bytes_per_sector * sector[13] as u64)
+}
+
+fn read_at(image: &Path, offset: u64, len: usize) -> Vec<u8> {
+    let mut file = fs::File::open(image).unwrap();
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    let mut buffer = vec![0; len];
+    file.read_exact(&mut buffer).unwrap();
+    buffer
+}
+
+fn write_at(image: &Path, offset: u64, bytes: &[u8]) {
+    let mut file = OpenOptions::new().write(true).open(image).unwrap();
+    file.seek(SeekFrom::Start(offset)).unwrap();
+    file.write_all(bytes).unwrap();
+}
+
+// Offset of the short directory entry called `name` in the root directory
+fn root_entry(image: &Path, name: &[u8; 11]) -> u64 {
+    let (_, _, data, cluster_bytes) = layout(image);
+    let root = read_at(image, data, cluster_bytes as usize);
+    let index = root.chunks(32).position(|entry| &entry[..11] == name).unwrap();
+    data + index as u64 * 32
+}
+
+fn messages(image: &Path, options: Options) -> (i32, Vec<String>) {
+    let report = check(image, options).unwrap();
+    (report.status(), report.problems.iter().map(|problem| problem.to_string()).collect())
+}
+
+// disk1 holding two 3000 byte files, unmounted again
+fn disk1_with_files(fixture: &Fixture) -> std::path::PathBuf {
+    let image = fixture.image("disk1");
+    let (mut system, session) = fixture.login_root();
+    system.mount("disk1").unwrap();
+    for name in ["a.txt", "b.txt"] {
+        let path = format!("/volumes/disk1/{}", name);
+        system.touch(&session, &path).unwrap();
+        system.write_file(&session, &path, &[b'x'; 3000]).unwrap();
+    }
+    system.unmount("disk1").unwrap();
+    image
+}
+
+#[test]
+fn freshly_written_images_are_clean() {
+    let fixture = Fixture::new();
+    let image = disk1_with_files(&fixture);
+
+    let report = check(&image, REPORT).unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!((report.files, report.dirs, report.status()), (2, 0, 0));
+    assert!(report.to_string().starts_with("2 files, 0 directories, "));
+    assert!(report.to_string().ends_with("clusters used; no problems"));
+
+    let report = check(&fixture.root_image(), REPORT).unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert!(report.dirs >= 6);
+}
+
+#[test]
+fn damage_is_reported_then_repaired() {
+    let fixture = Fixture::new();
+    let image = disk1_with_files(&fixture);
+    let (fat, second_fat, _, cluster_bytes) = layout(&image);
+    let a = root_entry(&image, b"A       TXT");
+    let b = root_entry(&image, b"B       TXT");
+
+    // a.txt claims far more data than it has, b.txt shares a.txt's clusters
+    // (leaving its own lost), and the second FAT disagrees with the first
+    write_at(&image, a + 28, &1_000_000u32.to_le_bytes());
+    let a_start = read_at(&image, a + 26, 2);
+    write_at(&image, b + 26, &a_start);
+    write_at(&image, second_fat + 4000, &[0xFF, 0xFF, 0xFF, 0x0F]);
+
+    let (status, problems) = messages(&image, REPORT);
+    assert_eq!(status, 4);
+    assert!(problems.contains(&"FAT 2 differs from FAT 1".to_string()), "{:?}", problems);
+    let holds = 3000u64.div_ceil(cluster_bytes) * cluster_bytes;
+    assert!(problems.contains(&format!("/a.txt: size 1000000 is more than its clusters hold ({})", holds)));
+    assert!(problems.iter().any(|problem| problem.starts_with("/b.txt: cross-linked with /a.txt at cluster ")));
+    assert!(problems.iter().any(|problem| problem.ends_with(" lost clusters in 1 chain")));
+    // Reporting leaves the image alone
+    assert_eq!(messages(&image, REPORT).1, problems);
+
+    let (status, fixed) = messages(&image, REPAIR);
+    assert_eq!(status, 1);
+    assert!(fixed.iter().all(|problem| problem.ends_with(" (fixed)")), "{:?}", fixed);
+    assert_eq!(read_at(&image, fat, 4096), read_at(&image, second_fat, 4096));
+    let report = check(&image, REPORT).unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+
+    let entries = rnix::disk::displaydisk(&image).unwrap();
+    let sizes: Vec<(&str, u64)> = entries.iter().map(|entry| (entry.name(), entry.len)).collect();
+    assert_eq!(sizes, vec![("a.txt", holds), ("b.txt", 0)]);
+}
+
+#[test]
+fn orphaned_long_names_and_bad_boot_sectors() {
+    let fixture = Fixture::new();
+    let image = disk1_with_files(&fixture);
+    let a = root_entry(&image, b"A       TXT");
+
+    // Renaming the short entry leaves its long name pointing nowhere
+    write_at(&image, a, b"Q");
+    let (_, problems) = messages(&image, REPORT);
+    assert_eq!(problems, vec!["/: long name before Q.TXT does not belong to it"]);
+    assert_eq!(messages(&image, REPAIR).0, 1);
+    let names: Vec<String> = rnix::disk::displaydisk(&image).unwrap().iter().map(|entry| entry.name().to_string()).collect();
+    assert_eq!(names, vec!["Q.TXT", "b.txt"]);
+
+    write_at(&image, 510, &[0, 0]);
+    let err = check(&image, REPORT).unwrap_err();
+    assert!(matches!(err, rnix::RnixError::CorruptImage(_)));
+    assert!(err.to_string().contains("boot sector signature is missing"));
+}
+
+#[test]
+fn rnix_layout_is_checked_on_root_images() {
+    let fixture = Fixture::new();
+    drop(fixture.login());
+    {
+        let file = OpenOptions::new().read(true).write(true).open(fixture.root_image()).unwrap();
+        let fs = fatfs::FileSystem::new(file, fatfs::FsOptions::new()).unwrap();
+        fs.root_dir().remove("home/alice").unwrap();
+        fs.root_dir().remove("volumes").unwrap();
+    }
+
+    let (status, problems) = messages(&fixture.root_image(), REPAIR);
+    assert_eq!(status, 1);
+    assert_eq!(
+        problems,
+        vec![
+            "/volumes: required directory is missing (fixed)",
+            "/home/alice: home directory is missing (fixed)"
+        ]
+    );
+    assert!(check(&fixture.root_image(), REPORT).unwrap().is_clean());
+}
+
+#[test]
+fn fsck_from_the_shell_and_the_binary() {
+    let fixture = Fixture::new();
+    disk1_with_files(&fixture);
+    let (status, output) = fixture.transcript("alice\nsecret\nsudo fsck disk1\nsecret\nsudo fsck --repair\nsecret\nsudo fsck\nsecret\n");
+    assert_eq!(status, 0);
+    assert!(output.contains("disk1: 2 files, 0 directories, "));
+    assert!(output.contains("fsck: disk0: cannot repair a disk in use; run `rnix fsck --repair` from the host"));
+    assert!(output.contains("disk0: 3 files, 7 directories, "));
+    assert!(!output.contains(" fixed"));
+
+    let rnix = |args: &[&str]| Command::new(env!("CARGO_BIN_EXE_rnix")).args(args).current_dir(fixture.dir.path()).output().unwrap();
+    let output = rnix(&["fsck"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("rnix.img: "));
+
+    let image = fixture.dir.path().join("disk1.img");
+    let a = root_entry(&image, b"A       TXT");
+    write_at(&image, a + 28, &1_000_000u32.to_le_bytes());
+    assert_eq!(rnix(&["fsck", "disk1.img"]).status.code(), Some(4));
+    let output = rnix(&["fsck", "--repair", "disk1.img"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("1 problem, 1 fixed"));
+    assert_eq!(rnix(&["fsck", "-r", "disk1.img"]).status.code(), Some(0));
+    assert_eq!(rnix(&["fsck", "missing.img"]).status.code(), Some(8));
+    assert_eq!(rnix(&["fsck", "a", "b"]).status.code(), Some(2));
+}
@@ -0,0 +1,84 @@
+mod common;
+
+use common::Fixture;
+use rnix::glob::{expand, has_magic, matches};
+use rnix::tokenizer::{words_with, Word};
+
+const LOGIN: &str = "alice\nsecret\nalice\nsecret\n";
+
+#[test]
+fn patterns_match_names_ignoring_case() {
+    assert!(matches("*.TXT", "notes.txt"));
+    assert!(matches("n?tes.*", "NOTES.md"));
+    assert!(matches("[abc]*", "banana"));
+    assert!(matches("[!abc]*", "date"));
+    assert!(!matches("[^abc]*", "cherry"));
+    assert!(matches("file[0-9]", "file7"));
+    assert!(!matches("file[0-9]", "filex"));
+    assert!(matches("a\\*b", "a*b"));
+    assert!(!matches("a\\*b", "axb"));
+    assert!(matches("[]x]", "]"));
+    assert!(matches("a[b", "a[b"));
+    assert!(!matches("*", ".rnixrc"));
+    assert!(matches(".*", ".rnixrc"));
+    assert!(has_magic("*.txt") && !has_magic("\\*.txt") && !has_magic("plain"));
+}
+
+#[test]
+fn quoted_glob_characters_stay_literal() {
+    let lookup = |name: &str| (name == "P").then(|| "*.txt".to_string());
+    let words = words_with("ls *.txt '*.txt' a\\?b \"[x]\"* $P", &lookup).unwrap();
+    let patterns: Vec<Option<&str>> = words.iter().map(|word| word.pattern.as_deref()).collect();
+    assert_eq!(patterns, vec![None, Some("*.txt"), None, None, Some("\\[x\\]*"), Some("*.txt")]);
+    assert_eq!(
+        words[3],
+        Word {
+            text: "a?b".to_string(),
+            pattern: None
+        }
+    );
+}
+
+#[test]
+fn expansion_walks_the_tree_and_the_mount_table() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    let (mut system, session) = fixture.login();
+    system.mount("disk1").unwrap();
+    system.mkdir(&session, "docs").unwrap();
+    system.mkdir(&session, "docs/old").unwrap();
+    for file in ["a.txt", "B.TXT", "c.md", "docs/d.txt", "docs/old/e.txt", "/volumes/disk1/f.txt"] {
+        system.touch(&session, file).unwrap();
+    }
+
+    assert_eq!(expand(&system, &session, "*.txt"), vec!["a.txt", "B.TXT"]);
+    assert_eq!(expand(&system, &session, "?.*"), vec!["a.txt", "B.TXT", "c.md"]);
+    assert_eq!(expand(&system, &session, "*/"), vec!["docs/"]);
+    assert_eq!(expand(&system, &session, "**/*.txt"), vec!["a.txt", "B.TXT", "docs/d.txt", "docs/old/e.txt"]);
+    assert_eq!(expand(&system, &session, "DOCS/**"), vec!["DOCS/d.txt", "DOCS/old", "DOCS/old/e.txt"]);
+    assert_eq!(expand(&system, &session, "/volumes/*/*.txt"), vec!["/volumes/disk1/f.txt"]);
+    assert_eq!(expand(&system, &session, "/home/alice/[ab].txt"), vec!["/home/alice/a.txt", "/home/alice/B.TXT"]);
+    assert!(expand(&system, &session, "*.rs").is_empty());
+}
+
+#[test]
+fn the_shell_expands_arguments_before_running_commands() {
+    let fixture = Fixture::new();
+    let script = format!(
+        "{}touch one.txt two.txt three.md\nls *.txt\necho '*.txt' *.none\nrm *.txt nope\nls\nmkdir d\ncp three.md nope d\ncp three.md d copy.md\n\
+         touch four.md\ncp *.md e\nls d\n",
+        LOGIN
+    );
+    let (status, output) = fixture.transcript(&script);
+    assert!(output.contains("File 'one.txt' created.\nFile 'two.txt' created.\nFile 'three.md' created.\n"));
+    assert!(output.contains("> one.txt\ntwo.txt\n"));
+    assert!(output.contains("*.txt *.none\n"));
+    assert!(output.contains("File 'one.txt' removed.\nFile 'two.txt' removed.\nrm: "));
+    assert!(output.contains("Contents of current directory:\nthree.md\n"));
+    assert!(output.contains("File 'three.md' copied to 'd/three.md'.\ncp: /home/alice/nope: No such file or directory\n"));
+    assert!(output.contains("File 'three.md' copied to 'd/copy.md'.\n"));
+    // Two matches before a name that isn't a directory are refused, not taken as SRC DST_DIR DST_NAME
+    assert!(output.contains("cp: target 'e' is not a directory\n"));
+    assert!(output.contains("Contents of directory 'd':\nthree.md\ncopy.md\n"));
+    assert_eq!(status, 0);
+}
@@ -0,0 +1,66 @@
+// Golden-output tests: each scripted shell session must reproduce the
+// transcript stored in tests/golden/<name>.txt byte for byte.
+// Run with RNIX_UPDATE_GOLDEN=1 to rewrite the transcripts after an intended change.
+
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+
+use common::Fixture;
+
+const LOGIN: &str = "alice\nsecret\nalice\nsecret\n";
+
+fn check(name: &str, fixture: &Fixture, script: &str, expected_status: i32) {
+    let (status, output) = fixture.transcript(script);
+    let golden = PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.txt", name));
+
+    if std::env::var_os("RNIX_UPDATE_GOLDEN").is_some() {
+        fs::write(&golden, &output).unwrap();
+    }
+    let expected = fs::read_to_string(&golden)
+        .unwrap_or_else(|err| panic!("{}: {} (run with RNIX_UPDATE_GOLDEN=1)", golden.display(), err));
+    assert_eq!(output, expected, "transcript for '{}' changed", name);
+    assert_eq!(status, expected_status);
+}
+
+#[test]
+fn golden_file_commands() {
+    let fixture = Fixture::new();
+    let script = format!(
+        "{}pwd\nmkdir docs\ntouch docs/a.txt\ncp docs/a.txt docs/b.txt\nmv docs/b.txt c.txt\n\
+         ls\nls docs\nrm docs/a.txt\nrm docs\ncd /\nls\nwhoami\nexit\n",
+        LOGIN
+    );
+    check("file_commands", &fixture, &script, 0);
+}
+
+#[test]
+fn golden_errors_and_usage() {
+    let fixture = Fixture::new();
+    let script = format!(
+        "{}mkdir\ncd nowhere\nrm ghost\nmkdir x\nmkdir x\nmount disk1\nresetroot\nbogus\nexit\n",
+        LOGIN
+    );
+    check("errors", &fixture, &script, 127);
+}
+
+#[test]
+fn golden_disks() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    let script = format!(
+        "{}listdisks\nsudo mount disk1\nsecret\npwd\ntouch f\nlistdisks\nsudo umount disk1\nsecret\n\
+         cd /\nsudo umount disk1\nsecret\nlistdisks\nexit\n",
+        LOGIN
+    );
+    check("disks", &fixture, &script, 0);
+}
+
+#[test]
+fn golden_help() {
+    let fixture = Fixture::new();
+    check("help", &fixture, &format!("{}help\nexit\n", LOGIN), 0);
+}
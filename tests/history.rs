@@ -0,0 +1,173 @@
+mod common;
+
+use common::Fixture;
+use rnix::history::History;
+use rnix::readline::{Key, KeyDecoder, LineEditor, Outcome};
+use rnix::tokenizer::tokenize;
+
+fn history(lines: &[&str]) -> History {
+    let mut history = History::new();
+    for line in lines {
+        history.push(line);
+    }
+    history
+}
+
+fn type_keys(editor: &mut LineEditor<'_>, keys: &[Key]) -> Outcome {
+    let mut outcome = Outcome::Pending;
+    for key in keys {
+        outcome = editor.feed(*key);
+    }
+    outcome
+}
+
+fn chars(text: &str) -> Vec<Key> {
+    text.chars().map(Key::Char).collect()
+}
+
+#[test]
+fn push_skips_blanks_and_repeats() {
+    let history = history(&["ls", "ls", "  ", "pwd", "ls"]);
+    let lines: Vec<&str> = history.iter().map(|(_, line)| line).collect();
+    assert_eq!(lines, vec!["ls", "pwd", "ls"]);
+    assert_eq!(History::parse(&history.serialize()), history);
+}
+
+#[test]
+fn expansion_of_event_designators() {
+    let history = history(&["mkdir docs", "cd docs", "touch a"]);
+
+    assert_eq!(history.expand("!!").unwrap().as_deref(), Some("touch a"));
+    assert_eq!(history.expand("!1").unwrap().as_deref(), Some("mkdir docs"));
+    assert_eq!(history.expand("!-2").unwrap().as_deref(), Some("cd docs"));
+    assert_eq!(history.expand("!mk && !!").unwrap().as_deref(), Some("mkdir docs && touch a"));
+    assert_eq!(history.expand("echo hi").unwrap(), None);
+    assert_eq!(history.expand("echo '!!' \\!! ! x").unwrap(), None);
+    assert!(history.expand("!9").is_err());
+    assert!(history.expand("!nothing").is_err());
+}
+
+#[test]
+fn tokenizer_handles_quotes_and_escapes() {
+    assert_eq!(tokenize("  cp  a   b ").unwrap(), vec!["cp", "a", "b"]);
+    assert_eq!(tokenize("touch 'my file' \"x y\" z\\ w").unwrap(), vec!["touch", "my file", "x y", "z w"]);
+    assert_eq!(tokenize("echo \"a \\\"b\\\" \\n\"").unwrap(), vec!["echo", "a \"b\" \\n"]);
+    assert_eq!(tokenize("echo ''").unwrap(), vec!["echo", ""]);
+    assert!(tokenize("echo 'open").is_err());
+}
+
+#[test]
+fn decoder_understands_escape_sequences_and_utf8() {
+    let mut decoder = KeyDecoder::new();
+    let mut keys = Vec::new();
+    for byte in b"a\x1b[A\x1b[3~\x1bOH\x7f\x01\r".iter().chain("é".as_bytes()) {
+        if let Some(key) = decoder.feed(*byte) {
+            keys.push(key);
+        }
+    }
+    assert_eq!(
+        keys,
+        vec![
+            Key::Char('a'),
+            Key::Up,
+            Key::Delete,
+            Key::Home,
+            Key::Backspace,
+            Key::Ctrl('a'),
+            Key::Enter,
+            Key::Char('é'),
+        ]
+    );
+    assert_eq!(decoder.feed(0x1b), None);
+    assert_eq!(decoder.flush(), Some(Key::Esc));
+}
+
+#[test]
+fn editor_moves_the_cursor_and_edits_in_place() {
+    let history = History::new();
+    let mut editor = LineEditor::new(&history);
+    type_keys(&mut editor, &chars("mkdir dcs"));
+    type_keys(&mut editor, &[Key::Left, Key::Left]);
+    type_keys(&mut editor, &chars("o"));
+    assert_eq!(editor.line(), "mkdir docs");
+    assert_eq!(editor.cursor(), 8);
+
+    type_keys(&mut editor, &[Key::Ctrl('a'), Key::Delete, Key::Delete]);
+    type_keys(&mut editor, &chars("r"));
+    assert_eq!(editor.line(), "rdir docs");
+    type_keys(&mut editor, &[Key::End, Key::Ctrl('w')]);
+    assert_eq!(editor.line(), "rdir ");
+    assert_eq!(type_keys(&mut editor, &[Key::Enter]), Outcome::Submit("rdir ".to_string()));
+}
+
+#[test]
+fn editor_recalls_history_and_restores_the_draft() {
+    let history = history(&["ls", "pwd"]);
+    let mut editor = LineEditor::new(&history);
+    type_keys(&mut editor, &chars("wh"));
+
+    type_keys(&mut editor, &[Key::Up]);
+    assert_eq!(editor.line(), "pwd");
+    type_keys(&mut editor, &[Key::Up, Key::Up]);
+    assert_eq!(editor.line(), "ls");
+    type_keys(&mut editor, &[Key::Down]);
+    assert_eq!(editor.line(), "pwd");
+    type_keys(&mut editor, &[Key::Down]);
+    assert_eq!(editor.line(), "wh");
+}
+
+#[test]
+fn reverse_search_finds_older_matches() {
+    let history = history(&["cd /home", "touch notes", "cd /internal", "ls"]);
+    let mut editor = LineEditor::new(&history);
+
+    type_keys(&mut editor, &[Key::Ctrl('r')]);
+    type_keys(&mut editor, &chars("cd"));
+    assert!(editor.is_searching());
+    assert!(editor.render("> ").contains("(reverse-i-search)`cd': cd /internal"));
+
+    type_keys(&mut editor, &[Key::Ctrl('r')]);
+    assert!(editor.render("> ").contains("cd /home"));
+
+    // Moving the cursor accepts the match for editing
+    type_keys(&mut editor, &[Key::End]);
+    assert!(!editor.is_searching());
+    assert_eq!(editor.line(), "cd /home");
+
+    let mut editor = LineEditor::new(&history);
+    let outcome = type_keys(&mut editor, &[Key::Ctrl('r'), Key::Char('t'), Key::Char('o'), Key::Enter]);
+    assert_eq!(outcome, Outcome::Submit("touch notes".to_string()));
+}
+
+#[test]
+fn ctrl_d_on_an_empty_line_is_end_of_input() {
+    let history = History::new();
+    let mut editor = LineEditor::new(&history);
+    assert_eq!(editor.feed(Key::Ctrl('d')), Outcome::Eof);
+    assert_eq!(editor.feed(Key::Ctrl('c')), Outcome::Interrupted);
+}
+
+#[test]
+fn history_expands_and_persists_in_the_image() {
+    let fixture = Fixture::new();
+    let (_, output) = fixture.transcript("alice\nsecret\nalice\nsecret\nmkdir one\n!!\n!mk\nhistory\nexit\n");
+    assert!(output.contains("alice(rnix) > mkdir one\nmkdir: /home/alice/one: File exists"));
+    assert!(output.contains("    1  mkdir one\n    2  history\n"));
+
+    let (_, output) = fixture.transcript("alice\nsecret\nhistory 2\n!1\nexit\n");
+    assert!(output.contains("    3  exit\n    4  history 2\n"));
+    assert!(output.contains("mkdir one\nmkdir: /home/alice/one: File exists"));
+
+    let (system, session) = fixture.login();
+    let saved = system.read_file(&session, "/home/alice/.rnix_history").unwrap();
+    assert_eq!(saved, b"mkdir one\nhistory\nexit\nhistory 2\nmkdir one\nexit\n");
+}
+
+#[test]
+fn unknown_events_are_reported_and_not_recorded() {
+    let fixture = Fixture::new();
+    let (status, output) = fixture.transcript("alice\nsecret\nalice\nsecret\n!42\nhistory\n");
+    assert!(output.contains("rnix: !42: event not found"));
+    assert!(output.contains("    1  history\n"));
+    assert_eq!(status, 0);
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use std::fs::OpenOptions;
+use std::io::{Read, Write};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use common::Fixture;
+use fatfs::{FileAttributes, FileSystem, FsOptions};
+use rnix::clock::FixedTime;
+use rnix::fsck::{check, Options};
+
+const REPORT: Options = Options { repair: false, mounted: false };
+
+// Function to write files straight into an image, as rnix would have left
+// them had it stopped partway through a change
+fn plant(image: &Path, files: &[(&str, &str)]) {
+    let file = OpenOptions::new().read(true).write(true).open(image).unwrap();
+    let fs = FileSystem::new(file, FsOptions::new()).unwrap();
+    for (name, contents) in files {
+        let mut file = fs.root_dir().create_file(name).unwrap();
+        file.truncate().unwrap();
+        file.write_all(contents.as_bytes()).unwrap();
+    }
+    fs.unmount().unwrap();
+}
+
+fn read(image: &Path, name: &str) -> Option<String> {
+    let fs = rnix::disk::opendisk(image).unwrap();
+    let mut contents = String::new();
+    fs.root_dir().open_file(name).ok()?.read_to_string(&mut contents).unwrap();
+    Some(contents)
+}
+
+#[test]
+fn writes_replace_files_whole_and_keep_their_entry() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+    let clock = Arc::new(FixedTime::new(SystemTime::UNIX_EPOCH + Duration::from_secs(1_709_251_200)));
+    system.set_time_provider(clock.clone());
+    system.touch(&session, "Notes").unwrap();
+    system.set_attributes(&session, "Notes", FileAttributes::HIDDEN, FileAttributes::empty()).unwrap();
+    let before = system.metadata(&session, "Notes").unwrap();
+
+    clock.advance(Duration::from_secs(3600));
+    system.write_file(&session, "NOTES", b"first draft").unwrap();
+    system.copy(&session, "Notes", "copy").unwrap();
+    system.copy(&session, "Notes", "Notes").unwrap();
+    let after = system.metadata(&session, "notes").unwrap();
+    assert_eq!(after.created, before.created);
+    assert_ne!(after.modified, before.modified);
+    assert!(after.attributes.contains(FileAttributes::HIDDEN));
+    assert_eq!(system.read_file(&session, "notes").unwrap(), b"first draft");
+    assert_eq!(system.read_file(&session, "copy").unwrap(), b"first draft");
+
+    // Nothing is left behind but the files themselves, spelled as they were
+    let mut names: Vec<String> = system.list_dir(&session, ".").unwrap().into_iter().map(|entry| entry.name).collect();
+    names.sort();
+    assert_eq!(names, vec!["Notes", "copy"]);
+    assert!(system.list_dir(&session, "/internal").unwrap().iter().all(|entry| !entry.name.contains("journal")));
+}
+
+#[test]
+fn a_committed_journal_is_finished_at_startup() {
+    let fixture = Fixture::new();
+    drop(fixture.login());
+    let image = fixture.root_image();
+    plant(
+        &image,
+        &[
+            ("notes", "old"),
+            (".notes.rnix-tmp", "new"),
+            (".gone.rnix-tmp", "moved"),
+            ("internal/disk0.journal", "replace\t.notes.rnix-tmp\tnotes\nreplace\t.gone.rnix-tmp\tgone\nmkdir\tmade\nremove\tinternal/setup_completed.flag\ncommit\n"),
+        ],
+    );
+
+    let system = fixture.open();
+    let session = system.root_session();
+    assert_eq!(system.read_file(&session, "/notes").unwrap(), b"new");
+    assert_eq!(system.read_file(&session, "/gone").unwrap(), b"moved");
+    assert!(system.is_dir(&session, "/made"));
+    assert!(system.needs_setup());
+    for gone in ["/.notes.rnix-tmp", "/.gone.rnix-tmp", "/internal/disk0.journal"] {
+        assert!(!system.is_file(&session, gone), "{}", gone);
+    }
+    drop(system);
+    assert!(check(&image, REPORT).unwrap().is_clean());
+}
+
+#[test]
+fn an_uncommitted_journal_is_rolled_back_at_startup() {
+    let fixture = Fixture::new();
+    drop(fixture.login());
+    let image = fixture.root_image();
+    // The journal was cut off while it was being written
+    plant(
+        &image,
+        &[
+            ("notes", "old"),
+            (".notes.rnix-tmp", "half writ"),
+            ("internal/disk0.journal", "replace\t.notes.rnix-tmp\tnotes\nmkdir\tma"),
+        ],
+    );
+
+    let (system, session) = fixture.login_root();
+    assert_eq!(system.read_file(&session, "/notes").unwrap(), b"old");
+    assert!(!system.is_file(&session, "/.notes.rnix-tmp"));
+    assert!(!system.is_file(&session, "/internal/disk0.journal"));
+    assert!(!system.needs_setup());
+}
+
+#[test]
+fn a_disk_journal_waits_for_a_read_write_mount() {
+    let fixture = Fixture::new();
+    let disk = fixture.image("disk1");
+    drop(fixture.login());
+    plant(&disk, &[("notes", "old"), (".notes.rnix-tmp", "new")]);
+    plant(&fixture.root_image(), &[("internal/disk1.journal", "replace\t.notes.rnix-tmp\tnotes\ncommit\n")]);
+
+    let (mut system, session) = fixture.login_root();
+    system.mount_read_only("disk1").unwrap();
+    assert_eq!(system.read_file(&session, "/volumes/disk1/notes").unwrap(), b"old");
+    system.unmount("disk1").unwrap();
+    assert!(system.is_file(&session, "/internal/disk1.journal"));
+
+    system.mount("disk1").unwrap();
+    assert_eq!(system.read_file(&session, "/volumes/disk1/notes").unwrap(), b"new");
+    assert!(!system.is_file(&session, "/internal/disk1.journal"));
+    system.unmount("disk1").unwrap();
+    assert_eq!(read(&disk, ".notes.rnix-tmp"), None);
+    assert!(check(&disk, REPORT).unwrap().is_clean());
+}
+
+#[test]
+fn imports_replace_files_whole_and_keep_their_entry() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+    system.touch(&session, "notes").unwrap();
+    system.set_attributes(&session, "notes", FileAttributes::HIDDEN, FileAttributes::empty()).unwrap();
+    let host = tempfile::tempdir().unwrap();
+    let file = host.path().join("notes");
+    std::fs::write(&file, "imported").unwrap();
+
+    rnix::transfer::import(&system, &session, &file, "notes").unwrap();
+    assert_eq!(system.read_file(&session, "notes").unwrap(), b"imported");
+    assert!(system.metadata(&session, "notes").unwrap().attributes.contains(FileAttributes::HIDDEN));
+    let names: Vec<String> = system.list_dir(&session, ".").unwrap().into_iter().map(|entry| entry.name).collect();
+    assert_eq!(names, vec!["notes"]);
+}
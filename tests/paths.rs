@@ -0,0 +1,77 @@
+use proptest::prelude::*;
+use rnix::path::{join, resolve, split};
+
+// Path components as a user might type them, including the special ones
+fn component() -> impl Strategy<Value = String> {
+    prop_oneof![
+        Just(".".to_string()),
+        Just("..".to_string()),
+        Just(String::new()),
+        "[a-zA-Z0-9_ .-]{1,12}",
+    ]
+}
+
+fn raw_path() -> impl Strategy<Value = String> {
+    (any::<bool>(), prop::collection::vec(component(), 0..8)).prop_map(|(absolute, parts)| {
+        let joined = parts.join("/");
+        if absolute {
+            format!("/{}", joined)
+        } else {
+            joined
+        }
+    })
+}
+
+fn is_normalized(path: &str) -> bool {
+    path == "/"
+        || (path.starts_with('/')
+            && !path.ends_with('/')
+            && path[1..].split('/').all(|part| !part.is_empty() && part != "." && part != ".."))
+}
+
+proptest! {
+    #[test]
+    fn resolve_always_yields_a_normalized_absolute_path(cwd in raw_path(), path in raw_path()) {
+        let cwd = resolve("/", &cwd);
+        let resolved = resolve(&cwd, &path);
+        prop_assert!(is_normalized(&resolved), "{:?}", resolved);
+    }
+
+    #[test]
+    fn resolve_is_idempotent(cwd in raw_path(), path in raw_path()) {
+        let resolved = resolve(&resolve("/", &cwd), &path);
+        prop_assert_eq!(resolve("/", &resolved), resolved.clone());
+        prop_assert_eq!(resolve("/somewhere/else", &resolved), resolved);
+    }
+
+    #[test]
+    fn absolute_paths_ignore_the_working_directory(cwd in raw_path(), path in raw_path()) {
+        let absolute = format!("/{}", path);
+        prop_assert_eq!(resolve(&resolve("/", &cwd), &absolute), resolve("/", &absolute));
+    }
+
+    #[test]
+    fn parent_component_undoes_a_child(cwd in raw_path(), name in "[a-zA-Z0-9_-]{1,12}") {
+        let cwd = resolve("/", &cwd);
+        let child = resolve(&cwd, &name);
+        prop_assert_eq!(resolve(&child, ".."), cwd);
+    }
+
+    #[test]
+    fn split_and_join_round_trip(path in raw_path()) {
+        let resolved = resolve("/", &path);
+        let (parent, name) = split(&resolved);
+        prop_assert!(is_normalized(parent));
+        if resolved == "/" {
+            prop_assert_eq!(name, "");
+        } else {
+            prop_assert_eq!(join(parent, name), resolved.clone());
+        }
+    }
+}
+
+#[test]
+fn dotdot_never_escapes_the_root() {
+    assert_eq!(resolve("/", "../../.."), "/");
+    assert_eq!(resolve("/home", "../../internal"), "/internal");
+}
@@ -0,0 +1,77 @@
+mod common;
+
+use std::fs;
+
+use common::Fixture;
+use rnix::disk::{displaydisk, opendisk};
+use rnix::transfer::{extract, Transferred};
+use rnix::RnixError;
+
+// Fill disk1 with a small tree through the system, then close everything
+fn populated_disk1(fixture: &Fixture) {
+    fixture.image("disk1");
+    let (mut system, session) = fixture.login_root();
+    system.mount("disk1").unwrap();
+    system.mkdir(&session, "/volumes/disk1/docs").unwrap();
+    system.touch(&session, "/volumes/disk1/docs/notes.txt").unwrap();
+    system.write_file(&session, "/volumes/disk1/docs/notes.txt", b"first\nsecond\n").unwrap();
+    system.touch(&session, "/volumes/disk1/top.txt").unwrap();
+}
+
+#[test]
+fn the_whole_tree_is_listed_with_metadata() {
+    let fixture = Fixture::new();
+    populated_disk1(&fixture);
+
+    let entries = displaydisk(fixture.dir.path().join("disk1.img")).unwrap();
+    let paths: Vec<&str> = entries.iter().map(|entry| entry.path.as_str()).collect();
+    assert_eq!(paths, vec!["/docs", "/docs/notes.txt", "/top.txt"]);
+    let notes = &entries[1];
+    assert_eq!((notes.depth, notes.len, notes.is_dir), (1, 13, false));
+    assert_eq!(notes.name(), "notes.txt");
+    assert_eq!(entries[0].flags(), "d----");
+    assert!(notes.modified.date.year >= 1980);
+
+    let junk = fixture.dir.path().join("junk.img");
+    fs::write(&junk, vec![0xAB; 4096]).unwrap();
+    assert!(matches!(opendisk(&junk), Err(RnixError::CorruptImage(_))));
+}
+
+#[test]
+fn files_and_directories_are_extracted_to_the_host() {
+    let fixture = Fixture::new();
+    populated_disk1(&fixture);
+    let host = tempfile::tempdir().unwrap();
+
+    let fs = opendisk(fixture.dir.path().join("disk1.img")).unwrap();
+    let done = extract(&fs, "/DOCS", host.path()).unwrap();
+    assert_eq!(done, Transferred { files: 1, dirs: 1, bytes: 13 });
+    assert_eq!(fs::read(host.path().join("DOCS/notes.txt")).unwrap(), b"first\nsecond\n");
+
+    extract(&fs, "docs/notes.txt", &host.path().join("copy.txt")).unwrap();
+    assert_eq!(fs::read(host.path().join("copy.txt")).unwrap(), b"first\nsecond\n");
+    assert!(matches!(extract(&fs, "/missing", host.path()), Err(RnixError::NotFound(_))));
+}
+
+#[test]
+fn readdisk_lists_prints_and_extracts_from_the_shell() {
+    let fixture = Fixture::new();
+    populated_disk1(&fixture);
+    let host = tempfile::tempdir().unwrap();
+    let out = host.path().join("notes.txt");
+    let script = format!(
+        "alice\nsecret\nalice\nsecret\nreaddisk disk1\nreaddisk disk1 docs/notes.txt\nreaddisk --extract disk1 /docs/notes.txt {}\nreaddisk disk1 nope\nreaddisk --extract disk1\n",
+        out.display()
+    );
+    let (_, output) = fixture.transcript(&script);
+    assert!(output.contains("Contents of disk image 'disk1':\n"));
+    assert!(output.contains("d----          - "));
+    assert!(output.contains("  docs/\n"));
+    assert!(output.contains("        13 "));
+    assert!(output.contains("    notes.txt\n"));
+    assert!(output.contains("first\nsecond\n"));
+    assert!(output.contains(&format!("Extracted 1 file (13 bytes) to '{}'.", out.display())));
+    assert_eq!(fs::read(&out).unwrap(), b"first\nsecond\n");
+    assert!(output.contains("readdisk: /nope: No such file or directory"));
+    assert!(output.contains("usage: readdisk"));
+}
@@ -0,0 +1,109 @@
+mod common;
+
+use std::fs;
+
+use common::Fixture;
+use fatfs::FileAttributes;
+use rnix::RnixError;
+
+#[test]
+fn read_only_mounts_refuse_every_write() {
+    let fixture = Fixture::new();
+    let image = fixture.image("disk1");
+    {
+        let (mut system, session) = fixture.login_root();
+        system.mount("disk1").unwrap();
+        system.mkdir(&session, "/volumes/disk1/docs").unwrap();
+        system.touch(&session, "/volumes/disk1/docs/readme").unwrap();
+        system.write_file(&session, "/volumes/disk1/docs/readme", b"golden").unwrap();
+        system.unmount("disk1").unwrap();
+    }
+    let before = fs::read(&image).unwrap();
+
+    // The host copy may itself be read-only
+    let mut permissions = fs::metadata(&image).unwrap().permissions();
+    permissions.set_readonly(true);
+    fs::set_permissions(&image, permissions).unwrap();
+
+    let (mut system, session) = fixture.login_root();
+    assert_eq!(system.mount_read_only("disk1").unwrap(), "/volumes/disk1");
+    assert!(system.is_read_only("disk1"));
+    let file = "/volumes/disk1/docs/readme";
+    assert_eq!(system.read_file(&session, file).unwrap(), b"golden");
+    assert_eq!(system.list_dir(&session, "/volumes/disk1/docs").unwrap().len(), 1);
+
+    let refused = [
+        system.touch(&session, file),
+        system.touch(&session, "/volumes/disk1/new"),
+        system.mkdir(&session, "/volumes/disk1/new"),
+        system.write_file(&session, file, b"changed"),
+        system.remove(&session, file),
+        system.rename(&session, file, "/volumes/disk1/moved"),
+        system.copy(&session, "/internal/rnix", "/volumes/disk1/copy"),
+        system.set_attributes(&session, file, FileAttributes::HIDDEN, FileAttributes::empty()).map(|_| ()),
+        system.check_writable(&session, "/volumes/disk1/imported"),
+    ];
+    for result in refused {
+        match result {
+            Err(err @ RnixError::ReadOnly(_)) => assert!(err.to_string().ends_with(": read-only filesystem")),
+            other => panic!("expected a read-only error, got {:?}", other),
+        }
+    }
+    // Copying out of it is fine
+    system.copy(&session, file, "/home/readme").unwrap();
+    system.unmount("disk1").unwrap();
+    drop(system);
+    assert_eq!(fs::read(&image).unwrap(), before);
+}
+
+#[test]
+fn the_read_only_attribute_protects_a_file() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+    system.touch(&session, "keep").unwrap();
+    system.write_file(&session, "keep", b"original").unwrap();
+    system.set_attributes(&session, "keep", FileAttributes::READ_ONLY, FileAttributes::empty()).unwrap();
+
+    for result in [
+        system.write_file(&session, "keep", b"changed"),
+        system.touch(&session, "keep"),
+        system.remove(&session, "keep"),
+        system.copy(&session, "/internal/rnix", "keep"),
+    ] {
+        match result {
+            Err(err @ RnixError::PermissionDenied(_)) => assert!(err.to_string().contains("keep: file is read-only")),
+            other => panic!("expected a permission error, got {:?}", other),
+        }
+    }
+    assert_eq!(system.read_file(&session, "keep").unwrap(), b"original");
+
+    // As on DOS it can still be renamed, and the attribute taken off again
+    system.rename(&session, "keep", "kept").unwrap();
+    system.set_attributes(&session, "kept", FileAttributes::empty(), FileAttributes::READ_ONLY).unwrap();
+    system.write_file(&session, "kept", b"changed").unwrap();
+    system.remove(&session, "kept").unwrap();
+}
+
+#[test]
+fn mount_takes_a_read_only_option() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    drop(fixture.login());
+    let script = "alice\nsecret\n\
+                  sudo mount -o ro disk1\n\
+                  secret\n\
+                  listdisks\n\
+                  touch new\n\
+                  mkdir dir\n\
+                  edit new\n\
+                  cd /home/alice\n\
+                  sudo mount -o noexec disk2\n\
+                  secret\n";
+    let (_, output) = fixture.transcript(script);
+    assert!(output.contains("Disk disk1 mounted read-only.\n"), "{}", output);
+    assert!(output.contains("  disk1 - /dev/disk1 (read-only)\n"));
+    assert!(output.contains("touch: /volumes/disk1/new: read-only filesystem\n"));
+    assert!(output.contains("mkdir: /volumes/disk1/dir: read-only filesystem\n"));
+    assert!(output.contains("edit: /volumes/disk1/new: read-only filesystem\n"));
+    assert!(output.contains("mount: unknown mount option 'noexec'\n"));
+}
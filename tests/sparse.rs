@@ -0,0 +1,86 @@
+mod common;
+
+use std::process::Command;
+
+use common::Fixture;
+use rnix::fsck::{check, Options};
+use rnix::sparse::{compact, image_size, list_images};
+
+const MB: u64 = 1024 * 1024;
+
+#[test]
+fn images_only_store_what_is_in_use() {
+    let fixture = Fixture::new();
+    let image = fixture.image("disk1");
+    let fresh = image_size(&image).unwrap();
+    assert_eq!(fresh.apparent, 128 * MB);
+    if cfg!(target_os = "linux") {
+        assert!(fresh.actual < 2 * MB, "{:?}", fresh);
+    }
+
+    let (mut system, session) = fixture.login_root();
+    system.mount("disk1").unwrap();
+    let data: Vec<u8> = (0..8 * MB).map(|at| (at % 251) as u8 + 1).collect();
+    for name in ["/volumes/disk1/big", "/volumes/disk1/kept"] {
+        system.touch(&session, name).unwrap();
+    }
+    system.write_file(&session, "/volumes/disk1/big", &data).unwrap();
+    system.write_file(&session, "/volumes/disk1/kept", b"still here").unwrap();
+    system.remove(&session, "/volumes/disk1/big").unwrap();
+    let grown = image_size(&image).unwrap();
+    assert!(grown.actual >= fresh.actual + 8 * MB, "{:?}", grown);
+
+    // Compacting a mounted disk leaves what it holds alone
+    let done = compact(&image).unwrap();
+    assert!(done.free > 100 * MB);
+    assert_eq!(done.before, grown);
+    assert_eq!(done.after.apparent, 128 * MB);
+    if cfg!(target_os = "linux") {
+        assert!(done.after.actual < fresh.actual + MB, "{:?}", done);
+    }
+    assert_eq!(system.read_file(&session, "/volumes/disk1/kept").unwrap(), b"still here");
+    system.unmount("disk1").unwrap();
+    assert!(check(&image, Options { repair: false, mounted: false }).unwrap().is_clean());
+
+    let names: Vec<String> = list_images(fixture.dir.path()).unwrap().into_iter().map(|(name, _)| name).collect();
+    assert_eq!(names, vec!["disk1.img", "rnix.img"]);
+}
+
+#[test]
+fn compact_and_ls_images_from_the_shell_and_the_host() {
+    let fixture = Fixture::new();
+    let image = fixture.image("disk1");
+    drop(fixture.login());
+    let script = format!(
+        "alice\nsecret\n\
+         ls-images -h\n\
+         sudo compact disk1\n\
+         secret\n\
+         sudo mount -o ro disk1\n\
+         secret\n\
+         sudo compact disk1\n\
+         secret\n\
+         sudo compact {}\n\
+         secret\n",
+        image.display()
+    );
+    let (_, output) = fixture.transcript(&script);
+    assert!(output.contains("Image              Apparent     Actual\n"), "{}", output);
+    assert!(output.contains("disk1.img              128M "));
+    assert!(output.contains("rnix.img               128M "));
+    assert!(output.contains("disk1: 127M of free clusters zeroed; "));
+    assert!(output.contains("compact: disk1: read-only filesystem\n"));
+    // Naming the image by its host path doesn't get around the read-only mount
+    assert!(output.contains(&format!("compact: {}: read-only filesystem\n", image.display())));
+
+    let rnix = |args: &[&str]| Command::new(env!("CARGO_BIN_EXE_rnix")).args(args).current_dir(fixture.dir.path()).output().unwrap();
+    let output = rnix(&["ls-images"]);
+    assert_eq!(output.status.code(), Some(0));
+    let listing = String::from_utf8_lossy(&output.stdout);
+    assert!(listing.starts_with("Image            Apparent-K   Actual-K\ndisk1.img            131072 "), "{}", listing);
+    let output = rnix(&["compact", "disk1.img"]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(String::from_utf8_lossy(&output.stdout).starts_with("disk1.img: "));
+    assert_eq!(rnix(&["compact", "missing.img"]).status.code(), Some(1));
+    assert_eq!(rnix(&["ls-images", "a", "b"]).status.code(), Some(2));
+}
@@ -0,0 +1,68 @@
+mod common;
+
+use common::Fixture;
+use rnix::{Session, System};
+
+fn write(system: &System, session: &Session, path: &str, contents: &str) {
+    system.touch(session, path).unwrap();
+    system.write_file(session, path, contents.as_bytes()).unwrap();
+}
+
+#[test]
+fn profile_then_rc_run_on_login_and_logout_script_on_exit() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    {
+        let (system, session) = fixture.login();
+        write(
+            &system,
+            &session,
+            "/internal/etc/profile",
+            "# system-wide settings\nexport GREETING=hello\nsudo mount -n disk1\nmount -n disk2\n",
+        );
+        write(
+            &system,
+            &session,
+            "/home/alice/.rnixrc",
+            "\n  export PS1='\\u@\\W$ '\nmkdir $GREETING\ncd $GREETING\n",
+        );
+        write(&system, &session, "/home/alice/.rnix_logout", "touch /volumes/disk1/bye\n");
+    }
+
+    let (status, output) = fixture.transcript("alice\nsecret\nsecret\npwd\nlistdisks\nexit 4\n");
+    // The profile has no more privileges than the user
+    assert!(output.contains("Password: Disk disk1 mounted.\nmount: Permission denied: This command requires sudo privileges."));
+    assert!(output.contains("Directory 'hello' created.\nChanged directory to 'hello'.\nalice@hello$ /home/alice/hello\n"));
+    assert!(output.contains("  disk1 - /dev/disk1\n"));
+    assert!(output.contains("File '/volumes/disk1/bye' created."));
+    assert_eq!(status, 4);
+}
+
+#[test]
+fn rc_errors_are_reported_and_exit_ends_the_session() {
+    let fixture = Fixture::new();
+    {
+        let (system, session) = fixture.login();
+        write(&system, &session, "/home/alice/.rnixrc", "mount disk1\nbogus\nexit 3\nmkdir never\n");
+    }
+
+    let (status, output) = fixture.transcript("alice\nsecret\nmkdir typed\n");
+    assert!(output.contains("mount: Permission denied: This command requires sudo privileges."));
+    assert!(output.contains("rnix: bogus: command not found"));
+    assert!(!output.contains("never"));
+    assert!(!output.contains("typed"));
+    assert_eq!(status, 3);
+}
+
+#[test]
+fn source_runs_a_script_from_the_prompt() {
+    let fixture = Fixture::new();
+    {
+        let (system, session) = fixture.login();
+        write(&system, &session, "/home/alice/vars", "export A=1\nexport B=$A$A\n");
+    }
+
+    let (_, output) = fixture.transcript("alice\nsecret\n. vars\nenv\nsource missing\n");
+    assert!(output.contains("A=1\nB=11\n"));
+    assert!(output.contains("source: /home/alice/missing: No such file or directory"));
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use common::{Fixture, PASSWORD, USER};
+use rnix::disk::{dformatq, displaydisk};
+use rnix::RnixError;
+
+#[test]
+fn fresh_image_has_the_standard_layout_and_needs_setup() {
+    let fixture = Fixture::new();
+    assert!(dformatq(fixture.root_image()).unwrap());
+
+    let system = fixture.open();
+    assert!(system.needs_setup());
+    let names = displaydisk(fixture.root_image()).unwrap();
+    for dir in ["internal", "home", "volumes"] {
+        assert!(names.iter().any(|entry| entry.name() == dir && entry.is_dir), "missing {}", dir);
+    }
+}
+
+#[test]
+fn setup_creates_root_and_user_accounts() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+
+    assert!(!system.needs_setup());
+    assert_eq!(system.users().unwrap(), vec!["root".to_string(), USER.to_string()]);
+    assert_eq!(session.user(), USER);
+    assert_eq!(session.cwd(), "/home/alice");
+
+    // Accounts survive reopening the image
+    drop(system);
+    let system = fixture.open();
+    assert!(!system.needs_setup());
+    assert!(system.authenticate(USER, PASSWORD).is_ok());
+}
+
+#[test]
+fn authenticate_rejects_bad_credentials() {
+    let fixture = Fixture::new();
+    let (system, _) = fixture.login();
+
+    assert!(matches!(system.authenticate(USER, "wrong"), Err(RnixError::AuthFailed)));
+    assert!(matches!(system.authenticate("nobody", PASSWORD), Err(RnixError::AuthFailed)));
+    assert!(matches!(system.authenticate("root", PASSWORD), Err(RnixError::AuthFailed)));
+    assert!(system.authenticate("root", common::ROOT_PASSWORD).is_ok());
+}
+
+#[test]
+fn mkdir_touch_and_list() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+
+    system.mkdir(&session, "projects").unwrap();
+    system.mkdir(&session, "projects/rnix").unwrap();
+    system.touch(&session, "projects/rnix/README").unwrap();
+
+    let names: Vec<String> = system
+        .list_dir(&session, "projects")
+        .unwrap()
+        .into_iter()
+        .map(|entry| entry.name)
+        .collect();
+    assert_eq!(names, vec!["rnix"]);
+    assert!(system.is_file(&session, "/home/alice/projects/rnix/README"));
+    assert!(system.is_dir(&session, "projects/rnix"));
+
+    assert!(matches!(system.mkdir(&session, "projects"), Err(RnixError::AlreadyExists(_))));
+    // Touching an existing file only updates its time
+    system.touch(&session, "projects/rnix/README").unwrap();
+    assert!(matches!(system.mkdir(&session, "missing/child"), Err(RnixError::NotFound(_))));
+}
+
+#[test]
+fn cp_copies_contents_and_keeps_the_name_when_copying_into_a_directory() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+
+    system.touch(&session, "a.txt").unwrap();
+    system.write_file(&session, "a.txt", b"hello rnix").unwrap();
+    system.mkdir(&session, "backup").unwrap();
+
+    system.copy(&session, "a.txt", "b.txt").unwrap();
+    system.copy(&session, "a.txt", "backup").unwrap();
+
+    assert_eq!(system.read_file(&session, "b.txt").unwrap(), b"hello rnix");
+    assert_eq!(system.read_file(&session, "backup/a.txt").unwrap(), b"hello rnix");
+    assert!(matches!(system.copy(&session, "nope", "x"), Err(RnixError::NotFound(_))));
+}
+
+#[test]
+fn rename_moves_entries_within_a_disk() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+
+    system.touch(&session, "draft").unwrap();
+    system.mkdir(&session, "final").unwrap();
+
+    system.rename(&session, "draft", "essay").unwrap();
+    assert!(system.is_file(&session, "essay"));
+    assert!(!system.is_file(&session, "draft"));
+
+    system.rename(&session, "essay", "final").unwrap();
+    assert!(system.is_file(&session, "final/essay"));
+
+    system.touch(&session, "other").unwrap();
+    assert!(matches!(
+        system.rename(&session, "other", "final/essay"),
+        Err(RnixError::AlreadyExists(_))
+    ));
+}
+
+#[test]
+fn remove_deletes_files_and_only_empty_directories() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+
+    system.mkdir(&session, "dir").unwrap();
+    system.touch(&session, "dir/file").unwrap();
+
+    assert!(matches!(system.remove(&session, "dir"), Err(RnixError::DirectoryNotEmpty(_))));
+    system.remove(&session, "dir/file").unwrap();
+    system.remove(&session, "dir").unwrap();
+    assert!(!system.is_dir(&session, "dir"));
+    assert!(matches!(system.remove(&session, "dir"), Err(RnixError::NotFound(_))));
+}
+
+#[test]
+fn cd_follows_relative_and_absolute_paths() {
+    let fixture = Fixture::new();
+    let (system, mut session) = fixture.login();
+
+    system.mkdir(&session, "a").unwrap();
+    system.cd(&mut session, "a").unwrap();
+    assert_eq!(session.cwd(), "/home/alice/a");
+    system.cd(&mut session, "../..").unwrap();
+    assert_eq!(session.cwd(), "/home");
+    system.cd(&mut session, "/internal/bin").unwrap();
+    assert_eq!(session.cwd(), "/internal/bin");
+    assert!(matches!(system.cd(&mut session, "/nope"), Err(RnixError::NotFound(_))));
+    assert_eq!(session.cwd(), "/internal/bin");
+}
+
+#[test]
+fn resetroot_is_root_only_and_forces_setup() {
+    let fixture = Fixture::new();
+    let (mut system, session) = fixture.login();
+
+    assert!(matches!(system.reset_root(&session), Err(RnixError::PermissionDenied(_))));
+
+    let root = system.authenticate("root", common::ROOT_PASSWORD).unwrap();
+    let removed = system.reset_root(&root).unwrap();
+    assert_eq!(removed.len(), 2);
+    assert!(system.needs_setup());
+    assert!(matches!(system.authenticate(USER, PASSWORD), Err(RnixError::AuthFailed)));
+    assert!(system.authenticate("root", common::ROOT_PASSWORD).is_ok());
+}
+
+#[test]
+fn mounted_disks_are_routed_under_volumes() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    let (mut system, mut session) = fixture.login();
+
+    let mount_point = system.mount("disk1").unwrap();
+    assert_eq!(mount_point, "/volumes/disk1");
+    assert_eq!(system.mounted_disks(), vec!["disk1".to_string()]);
+    assert!(matches!(system.mount("disk1"), Err(RnixError::AlreadyMounted(_))));
+
+    system.cd(&mut session, &mount_point).unwrap();
+    system.touch(&session, "on-disk1").unwrap();
+    system.copy(&session, "on-disk1", "/home/alice/copied").unwrap();
+    assert!(matches!(
+        system.rename(&session, "on-disk1", "/home/alice/moved"),
+        Err(RnixError::InvalidArgument(_))
+    ));
+
+    system.unmount("disk1").unwrap();
+    assert!(system.mounted_disks().is_empty());
+    assert!(matches!(system.unmount("disk1"), Err(RnixError::NotMounted(_))));
+    assert!(system.is_file(&session, "/home/alice/copied"));
+
+    // The file went to the disk1 image, not to the root image's mount point
+    let names = displaydisk(fixture.dir.path().join("disk1.img")).unwrap();
+    assert!(names.iter().any(|entry| entry.path == "/on-disk1"));
+    assert!(system.list_dir(&session, "/volumes/disk1").unwrap().is_empty());
+}
+
+#[test]
+fn mount_rejects_unknown_and_missing_disks() {
+    let fixture = Fixture::new();
+    let (mut system, _) = fixture.login();
+
+    assert!(matches!(system.mount("disk7"), Err(RnixError::InvalidArgument(_))));
+    assert!(matches!(system.mount("disk2"), Err(RnixError::NotFound(_))));
+    assert!(matches!(system.unmount("disk0"), Err(RnixError::PermissionDenied(_))));
+}
+
+#[test]
+fn displaydisk_reports_corrupt_images() {
+    let fixture = Fixture::new();
+    let junk = fixture.dir.path().join("junk.img");
+    std::fs::write(&junk, vec![0xAB; 4096]).unwrap();
+
+    assert!(!dformatq(&junk).unwrap());
+    assert!(matches!(displaydisk(&junk), Err(RnixError::CorruptImage(_))));
+    assert!(matches!(displaydisk(fixture.dir.path().join("missing.img")), Err(RnixError::Io(_))));
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use common::Fixture;
+use rnix::text::{char_set, diff, sort, translate, uniq, List, Substitution};
+use rnix::tokenizer::pipeline_with;
+
+#[test]
+fn pipelines_split_on_unquoted_bars() {
+    let lookup = |_: &str| None;
+    let commands = pipeline_with("ls | grep 'a|b' \"|\" a\\|b|sort", &lookup).unwrap();
+    let texts: Vec<Vec<&str>> = commands
+        .iter()
+        .map(|words| words.iter().map(|word| word.text.as_str()).collect())
+        .collect();
+    assert_eq!(texts, vec![vec!["ls"], vec!["grep", "a|b", "|", "a|b"], vec!["sort"]]);
+    for bad in ["| ls", "ls |", "ls || sort", "ls | | sort"] {
+        assert!(pipeline_with(bad, &lookup).is_err(), "{}", bad);
+    }
+    assert_eq!(pipeline_with("", &lookup).unwrap(), vec![Vec::new()]);
+}
+
+#[test]
+fn line_utilities() {
+    let mut lines = vec!["10 b", "9 a", "b", "a", "9 a"];
+    sort(&mut lines, false, false, false);
+    assert_eq!(lines, vec!["10 b", "9 a", "9 a", "a", "b"]);
+    sort(&mut lines, true, true, true);
+    // Lines without a number all count as 0, so -u keeps one of them
+    assert_eq!(lines, vec!["10 b", "9 a", "b"]);
+    assert_eq!(uniq(&["a", "a", "b", "a"]), vec![(2, "a"), (1, "b"), (1, "a")]);
+
+    let list = List::parse("1,3-").unwrap();
+    assert_eq!(list.fields("a:b:c:d", ':'), "a:c:d");
+    assert_eq!(list.fields("no delimiter", ':'), "no delimiter");
+    assert_eq!(List::parse("-2").unwrap().chars("hello"), "he");
+    for bad in ["", "0", "3-1", "x", "-"] {
+        assert!(List::parse(bad).is_err(), "{}", bad);
+    }
+
+    assert_eq!(translate("hello", &char_set("a-z").unwrap(), &char_set("A-Z").unwrap()), "HELLO");
+    assert_eq!(translate("a-b", &char_set("ab-").unwrap(), &char_set("x").unwrap()), "xxx");
+    assert_eq!(char_set("\\n\\t").unwrap(), vec!['\n', '\t']);
+    assert!(char_set("z-a").is_err());
+}
+
+#[test]
+fn substitutions_follow_sed() {
+    let apply = |script: &str, line: &str| Substitution::parse(script).unwrap().apply(line).into_owned();
+    assert_eq!(apply("s/o/0/", "foo"), "f0o");
+    assert_eq!(apply("s/o/0/g", "foo"), "f00");
+    assert_eq!(apply("s|/|\\||g", "a/b/c"), "a|b|c");
+    assert_eq!(apply("s/([a-z]+) ([a-z]+)/\\2 \\1/", "hello world"), "world hello");
+    assert_eq!(apply("s/x/[&]/gi", "XxY"), "[X][x]Y");
+    assert_eq!(apply("s/a/$1/", "a"), "$1");
+    for bad in ["s/a/b", "s/a", "y/a/b/", "s/a/b/q", "s//b/", ""] {
+        assert!(Substitution::parse(bad).is_err(), "{}", bad);
+    }
+}
+
+#[test]
+fn diff_prints_normal_format_hunks() {
+    let old = ["a", "b", "c", "d", "e"];
+    let new = ["a", "B", "c", "e", "f", "g"];
+    assert_eq!(diff(&old, &new), vec!["2c2", "< b", "---", "> B", "4d3", "< d", "5a5,6", "> f", "> g"]);
+    assert!(diff(&old, &old).is_empty());
+    assert_eq!(diff(&[], &["x"]), vec!["0a1", "> x"]);
+    assert_eq!(diff(&["x", "y"], &[]), vec!["1,2d0", "< x", "< y"]);
+}
+
+#[test]
+fn text_commands_read_files_and_pipes_from_the_shell() {
+    let fixture = Fixture::new();
+    {
+        let (system, session) = fixture.login();
+        system.mkdir(&session, "logs").unwrap();
+        let files = [
+            ("fruit", "banana\napple\ncherry\napple\n"),
+            ("logs/a.log", "ok\nERROR disk full\n"),
+            ("logs/b.log", "error again\n"),
+            ("users", "alice:1000:/home/alice\nbob:1001:/home/bob\n"),
+            ("fruit2", "banana\napricot\ncherry\napple\n"),
+        ];
+        for (file, contents) in files {
+            system.touch(&session, file).unwrap();
+            system.write_file(&session, file, contents.as_bytes()).unwrap();
+        }
+    }
+    let script = "alice\nsecret\n\
+                  grep -n an fruit\n\
+                  grep -ri error logs\n\
+                  echo piped ok | grep -r ok\n\
+                  sort fruit | uniq -c\n\
+                  cut -d : -f 1,3 users\n\
+                  echo hello world | tr a-z A-Z\n\
+                  echo hello world | sed 's/o/0/g' | tr -d l\n\
+                  diff fruit fruit2\n\
+                  echo $?\n\
+                  grep nothing fruit\n\
+                  echo status $?\n\
+                  ls | grep -v ^Contents | sort -r\n\
+                  sort\n\
+                  echo x | | sort\n\
+                  grep '(' fruit\n";
+    let (_, output) = fixture.transcript(script);
+    assert!(output.contains("1:banana\n"), "{}", output);
+    assert!(output.contains("logs/a.log:ERROR disk full\nlogs/b.log:error again\n"));
+    assert!(output.contains("> piped ok\n"));
+    assert!(output.contains("      2 apple\n      1 banana\n      1 cherry\n"));
+    assert!(output.contains("alice:/home/alice\nbob:/home/bob\n"));
+    assert!(output.contains("HELLO WORLD\n"));
+    assert!(output.contains("he0 w0rd\n"));
+    assert!(output.contains("2c2\n< apple\n---\n> apricot\n"));
+    assert!(output.contains("> 1\n"));
+    assert!(output.contains("> status 1\n"));
+    assert!(output.contains("users\nlogs\nfruit2\nfruit\n"));
+    assert!(output.contains("sort: no input; name a file or pipe a command into this one"));
+    assert!(output.contains("rnix: syntax error near unexpected token `|'"));
+    assert!(output.contains("grep: bad pattern: "));
+}
+
+#[test]
+fn errors_in_a_pipeline_reach_the_terminal_rather_than_the_next_command() {
+    let fixture = Fixture::new();
+    drop(fixture.login());
+    let script = "alice\nsecret\n\
+                  ls /nowhere | grep where\n\
+                  echo grep $?\n\
+                  ls /nowhere | sort -r | grep -v .\n";
+    let (_, output) = fixture.transcript(script);
+    assert!(output.contains("> ls: /nowhere: "), "{}", output);
+    // Nothing went down the pipe for grep to find
+    assert!(output.contains("> grep 1\n"));
+    assert_eq!(output.matches("/nowhere: ").count(), 2);
+}
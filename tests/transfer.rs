@@ -0,0 +1,112 @@
+mod common;
+
+use std::fs;
+use std::process::Command;
+use std::time::{Duration, SystemTime};
+
+use common::Fixture;
+use rnix::transfer::{export, from_fat, import, to_fat, Transferred};
+
+// A fixed time FAT can represent exactly (an even number of seconds)
+fn stamp() -> SystemTime {
+    SystemTime::UNIX_EPOCH + Duration::from_secs(1_600_000_000)
+}
+
+#[test]
+fn trees_round_trip_with_their_timestamps() {
+    let fixture = Fixture::new();
+    let host = tempfile::tempdir().unwrap();
+    let src = host.path().join("project");
+    fs::create_dir_all(src.join("docs")).unwrap();
+    fs::write(src.join("README.md"), "hello\n").unwrap();
+    fs::write(src.join("docs/guide.txt"), "a guide\n").unwrap();
+    fs::File::options().write(true).open(src.join("README.md")).unwrap().set_modified(stamp()).unwrap();
+
+    let (system, session) = fixture.login();
+    let done = import(&system, &session, &src, ".").unwrap();
+    assert_eq!(done, Transferred { files: 2, dirs: 2, bytes: 14 });
+    assert_eq!(done.to_string(), "2 files and 2 directories (14 bytes)");
+    assert_eq!(system.read_file(&session, "project/docs/guide.txt").unwrap(), b"a guide\n");
+    let modified = system
+        .dir(&session, "project")
+        .unwrap()
+        .iter()
+        .map(|entry| entry.unwrap())
+        .find(|entry| entry.file_name() == "README.md")
+        .unwrap()
+        .modified();
+    assert_eq!(modified, to_fat(stamp()));
+
+    let out = host.path().join("out");
+    fs::create_dir(&out).unwrap();
+    assert_eq!(export(&system, &session, "project", &out).unwrap().files, 2);
+    assert_eq!(fs::read(out.join("project/docs/guide.txt")).unwrap(), b"a guide\n");
+    let exported = fs::metadata(out.join("project/README.md")).unwrap().modified().unwrap();
+    assert_eq!(exported, stamp());
+
+    export(&system, &session, "project/README.md", &out.join("copy.md")).unwrap();
+    assert_eq!(fs::read(out.join("copy.md")).unwrap(), b"hello\n");
+    assert!(export(&system, &session, "nope", &out).is_err());
+}
+
+#[test]
+fn times_outside_fat_range_are_clamped() {
+    let early = to_fat(SystemTime::UNIX_EPOCH);
+    assert_eq!((early.date.year, early.date.month, early.date.day), (1980, 1, 1));
+    assert_eq!(from_fat(to_fat(stamp())), Some(stamp()));
+}
+
+#[test]
+fn import_and_export_from_the_shell() {
+    let fixture = Fixture::new();
+    let host = tempfile::tempdir().unwrap();
+    let file = host.path().join("todo.txt");
+    fs::write(&file, "milk\n").unwrap();
+    let out = host.path().join("back.txt");
+    let script = format!(
+        "alice\nsecret\nalice\nsecret\nimport {} .\nexport-host todo.txt {}\nexport notes {}\nenv\n",
+        file.display(),
+        out.display(),
+        host.path().join("x").display()
+    );
+    let (_, output) = fixture.transcript(&script);
+    assert!(output.contains(&format!("Imported 1 file (5 bytes) from '{}'.", file.display())));
+    assert!(output.contains(&format!("Exported 1 file (5 bytes) to '{}'.", out.display())));
+    assert_eq!(fs::read(&out).unwrap(), b"milk\n");
+    // `export` only ever sets variables
+    assert!(output.contains("': not a valid identifier (export-host copies a file out to the host)"), "{}", output);
+    assert!(!host.path().join("x").exists());
+}
+
+#[test]
+fn the_binary_imports_and_exports_without_logging_in() {
+    let dir = tempfile::tempdir().unwrap();
+    fs::write(dir.path().join("in.txt"), "data\n").unwrap();
+    let rnix = |args: &[&str]| Command::new(env!("CARGO_BIN_EXE_rnix")).args(args).current_dir(dir.path()).output().unwrap();
+
+    // Without an image there is nothing to copy into, and none is made
+    let output = rnix(&["import", "in.txt", "/in.txt"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("rnix: rnix.img: no such image"));
+    assert!(!dir.path().join("rnix.img").exists());
+
+    let fixture = Fixture::new();
+    let dir = &fixture.dir;
+    fs::write(dir.path().join("in.txt"), "data\n").unwrap();
+    let rnix = |args: &[&str]| Command::new(env!("CARGO_BIN_EXE_rnix")).args(args).current_dir(dir.path()).output().unwrap();
+    let output = rnix(&["import", "in.txt", "/in.txt"]);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "Imported 1 file (5 bytes) from 'in.txt'.\n");
+    let output = rnix(&["export", "/IN.TXT", "out.txt"]);
+    assert!(output.status.success());
+    assert_eq!(fs::read(dir.path().join("out.txt")).unwrap(), b"data\n");
+
+    let output = rnix(&["export", "/missing", "x"]);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("rnix: export: "));
+    // A disk that can't be mounted is reported rather than taken for a missing path
+    let output = rnix(&["export", "/volumes/disk1/notes", "x"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr).starts_with("rnix: export: "));
+    assert_eq!(rnix(&["frobnicate"]).status.code(), Some(2));
+}
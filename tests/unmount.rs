@@ -0,0 +1,76 @@
+mod common;
+
+use common::Fixture;
+use rnix::fsck::{check, Options};
+
+const REPORT: Options = Options { repair: false, mounted: false };
+
+#[test]
+fn unmount_and_sync_leave_the_image_clean() {
+    let fixture = Fixture::new();
+    let image = fixture.image("disk1");
+    let (mut system, session) = fixture.login_root();
+    system.mount("disk1").unwrap();
+    system.mkdir(&session, "/volumes/disk1/docs").unwrap();
+    system.touch(&session, "/volumes/disk1/docs/notes").unwrap();
+    system.write_file(&session, "/volumes/disk1/docs/notes", &[b'x'; 10_000]).unwrap();
+
+    // While mounted the image is marked dirty and its free count is stale
+    let report = check(&image, REPORT).unwrap();
+    assert!(!report.is_clean());
+
+    system.sync().unwrap();
+    let report = check(&image, REPORT).unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+    assert_eq!(system.read_file(&session, "/volumes/disk1/docs/notes").unwrap().len(), 10_000);
+
+    system.write_file(&session, "/volumes/disk1/docs/notes", b"shorter").unwrap();
+    system.unmount("disk1").unwrap();
+    let report = check(&image, REPORT).unwrap();
+    assert!(report.is_clean(), "{:?}", report.problems);
+
+    system.mount_read_only("disk1").unwrap();
+    system.sync().unwrap();
+    system.unmount("disk1").unwrap();
+}
+
+#[test]
+fn umount_refuses_while_the_current_directory_is_on_the_disk() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    drop(fixture.login());
+    let script = "alice\nsecret\n\
+                  sudo mount disk1\n\
+                  secret\n\
+                  sudo umount disk1\n\
+                  secret\n\
+                  listdisks\n\
+                  sudo umount -f disk1\n\
+                  secret\n\
+                  pwd\n\
+                  sync\n\
+                  echo synced $?\n";
+    let (_, output) = fixture.transcript(script);
+    assert!(
+        output.contains("umount: disk1: target is busy; the current directory /volumes/disk1 is on it (-f unmounts anyway)\n"),
+        "{}",
+        output
+    );
+    assert!(output.contains("  disk1 - /dev/disk1\n"));
+    assert!(output.contains("Disk disk1 unmounted.\nChanged directory to '/home/alice'.\n"));
+    assert!(output.contains("> /home/alice\n"));
+    assert!(output.contains("> synced 0\n"));
+}
+
+#[test]
+fn sync_reports_a_disk_it_cannot_open_again() {
+    let fixture = Fixture::new();
+    let image = fixture.image("disk1");
+    let (mut system, _) = fixture.login_root();
+    system.mount("disk1").unwrap();
+    std::fs::remove_file(&image).unwrap();
+
+    let err = system.sync().unwrap_err().to_string();
+    assert!(err.starts_with("disk1: unmounted, as it could not be opened again: "), "{}", err);
+    assert!(system.mounted_disks().is_empty());
+}
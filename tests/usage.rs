@@ -0,0 +1,69 @@
+mod common;
+
+use common::Fixture;
+
+#[test]
+fn disk_usage_covers_every_mounted_disk() {
+    let fixture = Fixture::new();
+    fixture.image("disk1");
+    let (mut system, session) = fixture.login_root();
+
+    let usage = system.disk_usage().unwrap();
+    assert_eq!(usage.len(), 1);
+    let root = &usage[0];
+    assert_eq!((root.disk.as_str(), root.mount_point.as_str(), root.fat_type), ("disk0", "/", "FAT32"));
+    assert!(root.total > 120 * 1024 * 1024 && root.free < root.total);
+
+    system.mount("disk1").unwrap();
+    let before = system.disk_usage().unwrap()[1].clone();
+    assert_eq!(before.mount_point, "/volumes/disk1");
+    system.touch(&session, "/volumes/disk1/big").unwrap();
+    system.write_file(&session, "/volumes/disk1/big", &vec![7; 100_000]).unwrap();
+    let after = system.disk_usage().unwrap()[1].clone();
+    assert!(after.used() >= before.used() + 100_000);
+    assert!(after.use_percent() >= 1 && after.use_percent() <= 100);
+}
+
+#[test]
+fn disk_used_counts_whole_clusters_per_directory() {
+    let fixture = Fixture::new();
+    let (system, session) = fixture.login();
+    let cluster = system.disk_usage().unwrap()[0].cluster_size;
+    system.mkdir(&session, "tree").unwrap();
+    system.mkdir(&session, "tree/sub").unwrap();
+    for file in ["tree/one", "tree/sub/two"] {
+        system.touch(&session, file).unwrap();
+        system.write_file(&session, file, b"x").unwrap();
+    }
+
+    let mut visited = Vec::new();
+    let total = system
+        .disk_used(&session, "tree", &mut |path, depth, used| {
+            visited.push((path.to_string(), depth, used));
+            Ok(())
+        })
+        .unwrap();
+    // Each directory and each one-byte file takes a cluster
+    assert_eq!(visited, vec![("tree/sub".to_string(), 1, 2 * cluster), ("tree".to_string(), 0, 4 * cluster)]);
+    assert_eq!(total, 4 * cluster);
+
+    let file = system.disk_used(&session, "tree/one", &mut |_, _, _| Ok(())).unwrap();
+    assert_eq!(file, cluster);
+    assert!(system.disk_used(&session, "missing", &mut |_, _, _| Ok(())).is_err());
+}
+
+#[test]
+fn df_and_du_from_the_shell() {
+    let fixture = Fixture::new();
+    let script = "alice\nsecret\nalice\nsecret\nmkdir docs\ntouch docs/a\ndf\ndf -h\ndu -s\ndu -h --max-depth=0 docs\ndu -d 1 .\ndu -s -d 1\ndu -d x\n";
+    let (_, output) = fixture.transcript(script);
+    assert!(output.contains("Disk     Type    1K-blocks       Used  Available Use% Mounted on\n"));
+    assert!(output.contains("Disk     Type         Size       Used  Available Use% Mounted on\n"));
+    assert!(output.contains("disk0    FAT32 "));
+    assert!(output.contains("M   1% /\n") || output.contains("M   0% /\n"), "{}", output);
+    assert!(output.contains("\t.\n"));
+    assert!(output.contains("\tdocs\n"));
+    assert!(output.contains("\t./docs\n"));
+    assert!(output.contains("usage: du [-s] [-h] [-d depth] [path...]"));
+    assert!(output.contains("du: invalid depth 'x'"));
+}
@@ -0,0 +1,123 @@
+mod common;
+
+use common::Fixture;
+use rnix::readline::Key;
+use rnix::visual::{Action, Visual};
+use rnix::{MemoryConsole, Shell};
+
+fn type_text(visual: &mut Visual, text: &str) {
+    for c in text.chars() {
+        visual.feed(Key::Char(c));
+    }
+}
+
+#[test]
+fn typing_and_moving_edit_utf8_lines() {
+    let mut visual = Visual::new("notes", "héllo\nwörld\n", 10, 40);
+    visual.feed(Key::Right);
+    visual.feed(Key::Right);
+    type_text(&mut visual, "ß");
+    visual.feed(Key::Down);
+    visual.feed(Key::End);
+    visual.feed(Key::Enter);
+    type_text(&mut visual, "→ end");
+    assert_eq!(visual.cursor(), (2, 5));
+    visual.feed(Key::Home);
+    visual.feed(Key::Backspace);
+    visual.feed(Key::Up);
+    visual.feed(Key::Delete);
+    assert_eq!(visual.contents(), "héßll\nwörld→ end\n");
+    assert!(visual.is_dirty());
+}
+
+#[test]
+fn search_moves_to_matches_and_esc_goes_back() {
+    let mut visual = Visual::new("notes", "alpha\nbeta\ngamma alpha\n", 10, 40);
+    visual.feed(Key::Ctrl('f'));
+    type_text(&mut visual, "al");
+    assert_eq!(visual.cursor(), (0, 0));
+    visual.feed(Key::Down);
+    assert_eq!(visual.cursor(), (2, 6));
+    visual.feed(Key::Down);
+    assert_eq!(visual.cursor(), (0, 0));
+    visual.feed(Key::Up);
+    assert_eq!(visual.cursor(), (2, 6));
+    visual.feed(Key::Enter);
+    assert_eq!(visual.cursor(), (2, 6));
+
+    visual.feed(Key::Ctrl('f'));
+    type_text(&mut visual, "beta");
+    assert_eq!(visual.cursor(), (1, 0));
+    visual.feed(Key::Esc);
+    assert_eq!(visual.cursor(), (2, 6));
+}
+
+#[test]
+fn the_screen_scrolls_and_shows_a_status_bar() {
+    let contents: String = (1..=50).map(|n| format!("line {}\n", n)).collect();
+    let mut visual = Visual::new("long.txt", &contents, 6, 30);
+    let screen = visual.render();
+    assert!(screen.contains("line 1\x1b[K\r\nline 2"));
+    assert!(screen.contains("\x1b[7mlong.txt - 50 lines       1/50\x1b[m"));
+    for _ in 0..5 {
+        visual.feed(Key::Down);
+    }
+    let screen = visual.render();
+    assert!(!screen.contains("line 1\x1b[K"));
+    assert!(screen.contains("line 6\x1b[K"));
+    assert!(screen.ends_with("\x1b[4;1H\x1b[?25h"));
+    visual.feed(Key::PageDown);
+    assert_eq!(visual.cursor(), (9, 0));
+    type_text(&mut visual, "x");
+    assert!(visual.render().contains("50 lines (modified)"));
+}
+
+#[test]
+fn quitting_with_changes_needs_a_second_ctrl_q() {
+    let mut visual = Visual::new("notes", "", 10, 40);
+    assert_eq!(visual.feed(Key::Ctrl('q')), Action::Quit);
+    type_text(&mut visual, "hi");
+    assert_eq!(visual.feed(Key::Ctrl('q')), Action::None);
+    assert!(visual.render().contains("unsaved changes"));
+    assert_eq!(visual.feed(Key::Ctrl('q')), Action::Quit);
+    assert_eq!(visual.feed(Key::Ctrl('s')), Action::Save("notes".to_string()));
+    visual.feed(Key::Ctrl('o'));
+    for _ in 0.."notes".len() {
+        visual.feed(Key::Backspace);
+    }
+    type_text(&mut visual, "copy");
+    assert_eq!(visual.feed(Key::Enter), Action::Save("copy".to_string()));
+    assert_eq!(visual.path(), "copy");
+}
+
+#[test]
+fn edit_v_saves_through_the_console() {
+    let fixture = Fixture::new();
+    {
+        let (system, session) = fixture.login();
+        system.touch(&session, "todo").unwrap();
+        system.write_file(&session, "todo", b"milk\n").unwrap();
+    }
+    // Ctrl-E, Enter, text, Ctrl-S, Ctrl-O with a new name, Ctrl-Q
+    let script = "alice\nsecret\nedit -v todo\n\x05\reggs\x13\x0f\x08\x08\x08\x08done\r\x11\nwhoami\nedit -v /home\n";
+    let mut console = MemoryConsole::new(script);
+    console.set_screen_size(Some((24, 80)));
+    let mut shell = Shell::new(fixture.open(), console);
+    shell.run().unwrap();
+    let output = shell.console_mut().take_output();
+    assert!(output.contains("10 bytes written to todo"));
+    assert!(output.contains("10 bytes written to done"));
+    assert!(output.contains("> alice\n"));
+    assert!(output.contains("edit: /home: Is a directory"));
+
+    let (system, session) = fixture.login();
+    assert_eq!(system.read_file(&session, "todo").unwrap(), b"milk\neggs\n");
+    assert_eq!(system.read_file(&session, "done").unwrap(), b"milk\neggs\n");
+}
+
+#[test]
+fn edit_v_needs_a_terminal() {
+    let fixture = Fixture::new();
+    let (_, output) = fixture.transcript("alice\nsecret\nalice\nsecret\nedit -v notes\n");
+    assert!(output.contains("edit: full-screen editing needs a terminal"));
+}